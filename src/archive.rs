@@ -0,0 +1,391 @@
+extern crate tar;
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use rand::prelude::*;
+
+use crate::output;
+
+// Header extension marking pieces whose secret is an archive of files rather than a single file
+pub const ARCHIVE_KEY: &str = "archive";
+pub const TAR: &str = "tar";
+
+//# Write a tar archive of paths into sink
+//# Each path is stored under its own name, with directories included recursively. Only regular files
+//# and directories are archived, so that nothing unexpected can be created when the archive is extracted.
+pub fn write_archive<T, P, W>(paths: &T, sink: W) -> Result<W, String>
+    where T: AsRef<[P]> + ?Sized,
+        P: AsRef<Path>,
+        W: Write {
+    let mut builder = tar::Builder::new(sink);
+    let mut names: HashSet<OsString> = HashSet::new();
+
+    for path in paths.as_ref() {
+        let my_path = path.as_ref();
+        let name = match my_path.file_name() {
+            None => return Err(format!("Error parsing file name: {}", my_path.display())),
+            Some(name) => name,
+        };
+        if !names.insert(name.to_os_string()) {
+            return Err(format!("More than one input is named {}", name.to_string_lossy()));
+        }
+        append(&mut builder, my_path, Path::new(name))?;
+    }
+
+    return match builder.into_inner() {
+        Err(error) => Err(format!("Error writing archive: {}", error)),
+        Ok(sink) => Ok(sink),
+    }
+}
+
+// Total size of the regular files in paths, for reporting progress
+pub fn content_size<T, P>(paths: &T) -> u64
+    where T: AsRef<[P]> + ?Sized,
+        P: AsRef<Path> {
+    return paths.as_ref().iter().map(|path| {
+        let my_path = path.as_ref();
+        match my_path.symlink_metadata() {
+            Ok(ref metadata) if metadata.is_file() => metadata.len(),
+            Ok(ref metadata) if metadata.is_dir() => match std::fs::read_dir(my_path) {
+                Err(_) => 0,
+                Ok(entries) => content_size(&entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect::<Vec<PathBuf>>()),
+            },
+            _ => 0,
+        }
+    }).sum();
+}
+
+fn append<W>(builder: &mut tar::Builder<W>, path: &Path, name: &Path) -> Result<(), String>
+    where W: Write {
+    let file_type = match path.symlink_metadata() {
+        Err(error) => return Err(format!("Error reading metadata for {}: {}", path.display(), error)),
+        Ok(metadata) => metadata.file_type(),
+    };
+
+    if file_type.is_file() {
+        if let Err(error) = builder.append_path_with_name(path, name) {
+            return Err(format!("Error archiving {}: {}", path.display(), error));
+        }
+    } else if file_type.is_dir() {
+        if let Err(error) = builder.append_dir(name, path) {
+            return Err(format!("Error archiving {}: {}", path.display(), error));
+        }
+
+        let mut entries: Vec<OsString> = Vec::new();
+        match std::fs::read_dir(path) {
+            Err(error) => return Err(format!("Error reading directory {}: {}", path.display(), error)),
+            Ok(iterator) => for entry in iterator {
+                match entry {
+                    Err(error) => return Err(format!("Error reading directory {}: {}", path.display(), error)),
+                    Ok(entry) => entries.push(entry.file_name()),
+                }
+            },
+        }
+        // Keep archives of the same tree identical
+        entries.sort();
+
+        for entry in entries {
+            append(builder, &path.join(&entry), &name.join(&entry))?;
+        }
+    } else {
+        return Err(format!("Only regular files and directories can be archived: {}", path.display()));
+    }
+
+    return Ok(());
+}
+
+//# Extract the tar archive read from source into destination, returning the paths of its top-level entries
+//# Entries are extracted into a private temporary directory, synced to disk, and only moved into destination
+//# once the whole archive has been read, so a failed or cancelled extraction leaves nothing behind; if moving
+//# them fails partway, those already moved are taken back out and anything they replaced is put back.
+//# Unless overwrite is set, nothing existing in destination is replaced; existing directories never are.
+//# Owners aren't restored, so neither are setuid and setgid bits, as with metadata::Metadata.
+pub fn extract_archive<R, P>(source: R, destination: P, overwrite: bool) -> Result<Vec<PathBuf>, String>
+    where R: Read,
+        P: AsRef<Path> {
+    let my_destination = destination.as_ref();
+    let staging = my_destination.join(format!(".russs-{:016x}.tmp", thread_rng().gen::<u64>()));
+    create_private_directory(&staging)?;
+
+    let result = extract_into(source, &staging).and_then(|names| move_into_place(&staging, my_destination, &names, overwrite));
+    let _ = std::fs::remove_dir_all(&staging);
+
+    return result;
+}
+
+fn extract_into<R>(source: R, staging: &Path) -> Result<Vec<OsString>, String>
+    where R: Read {
+    let mut archive = tar::Archive::new(source);
+    // Only the permission bits, as no owner is restored to go with setuid and setgid
+    archive.set_preserve_permissions(false);
+    archive.set_preserve_mtime(true);
+    archive.set_overwrite(false);
+
+    let entries = match archive.entries() {
+        Err(error) => return Err(format!("Error reading archive: {}", error)),
+        Ok(entries) => entries,
+    };
+
+    let mut names: Vec<OsString> = Vec::new();
+    for entry in entries {
+        let mut entry = match entry {
+            Err(error) => return Err(format!("Error reading archive: {}", error)),
+            Ok(entry) => entry,
+        };
+        let path = match entry.path() {
+            Err(error) => return Err(format!("Error reading archive: {}", error)),
+            Ok(path) => path.into_owned(),
+        };
+        validate_entry_path(&path)?;
+
+        let entry_type = entry.header().entry_type();
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            return Err(format!("Archive entry is not a regular file or directory: {}", path.display()));
+        }
+
+        match entry.unpack_in(staging) {
+            Err(error) => return Err(format!("Error extracting {}: {}", path.display(), error)),
+            Ok(false) => return Err(format!("Unsafe path in archive: {}", path.display())),
+            Ok(true) => (),
+        }
+
+        let name = path.components().next().unwrap().as_os_str().to_os_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    for name in &names {
+        sync_tree(&staging.join(name))?;
+    }
+    return Ok(names);
+}
+
+// Sync the files under path to disk, and the directories holding them where the platform allows it
+fn sync_tree(path: &Path) -> Result<(), String> {
+    let metadata = match path.symlink_metadata() {
+        Err(error) => return Err(format!("Error reading metadata for {}: {}", path.display(), error)),
+        Ok(metadata) => metadata,
+    };
+
+    if metadata.is_dir() {
+        let entries = match std::fs::read_dir(path) {
+            Err(error) => return Err(format!("Error reading directory {}: {}", path.display(), error)),
+            Ok(entries) => entries,
+        };
+        let mut last: Option<PathBuf> = None;
+        for entry in entries {
+            match entry {
+                Err(error) => return Err(format!("Error reading directory {}: {}", path.display(), error)),
+                Ok(entry) => {
+                    sync_tree(&entry.path())?;
+                    last = Some(entry.path());
+                },
+            }
+        }
+        // Syncs the directory holding the entry, which is path
+        if let Some(last) = last {
+            output::sync_directory(&last);
+        }
+        return Ok(());
+    }
+
+    let result = std::fs::File::open(path).and_then(|file| file.sync_all());
+    return match result {
+        Err(error) => Err(format!("Error writing {}: {}", path.display(), error)),
+        Ok(()) => Ok(()),
+    }
+}
+
+// Entries may only name paths inside the directory they're extracted into
+fn validate_entry_path(path: &Path) -> Result<(), String> {
+    if path.as_os_str().is_empty() || path.components().any(|component| match component { Component::Normal(_) => false, _ => true }) {
+        return Err(format!("Unsafe path in archive: {}", path.display()));
+    }
+    return Ok(());
+}
+
+fn move_into_place(staging: &Path, destination: &Path, names: &[OsString], overwrite: bool) -> Result<Vec<PathBuf>, String> {
+    // Fail before moving anything if we would have to replace something
+    for name in names {
+        let target = destination.join(name);
+        output::check_destination(&target, overwrite)?;
+        if target.is_dir() {
+            return Err(format!("{}: {}", output::ALREADY_EXISTS, target.display()));
+        }
+    }
+
+    let mut moved: Vec<PathBuf> = Vec::new();
+    let mut replaced: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut result: Result<(), String> = Ok(());
+    for name in names {
+        let target = destination.join(name);
+        if target.symlink_metadata().is_ok() {
+            let aside = match output::hidden_path(&target, "orig") {
+                Err(message) => {
+                    result = Err(message);
+                    break;
+                },
+                Ok(aside) => aside,
+            };
+            if let Err(error) = std::fs::rename(&target, &aside) {
+                result = Err(format!("Error moving {} aside: {}", target.display(), error));
+                break;
+            }
+            replaced.push((target.clone(), aside));
+        }
+        if let Err(error) = std::fs::rename(staging.join(name), &target) {
+            result = Err(format!("Error writing {}: {}", target.display(), error));
+            break;
+        }
+        moved.push(target);
+    }
+
+    if let Err(message) = result {
+        for path in &moved {
+            let _ = if path.is_dir() { std::fs::remove_dir_all(path) } else { std::fs::remove_file(path) };
+        }
+        for (path, aside) in &replaced {
+            let _ = std::fs::rename(aside, path);
+        }
+        return Err(message);
+    }
+    for (_, aside) in &replaced {
+        let _ = std::fs::remove_file(aside);
+    }
+    if let Some(target) = moved.first() {
+        output::sync_directory(target);
+    }
+    return Ok(moved);
+}
+
+#[cfg(unix)]
+fn create_private_directory(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::DirBuilderExt;
+    return match std::fs::DirBuilder::new().mode(0o700).create(path) {
+        Err(error) => Err(format!("Error creating {}: {}", path.display(), error)),
+        Ok(()) => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn create_private_directory(path: &Path) -> Result<(), String> {
+    return match std::fs::create_dir(path) {
+        Err(error) => Err(format!("Error creating {}: {}", path.display(), error)),
+        Ok(()) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_directory() -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("russs-archive-{:016x}", random::<u64>()));
+        std::fs::create_dir(&directory).unwrap();
+        return directory;
+    }
+
+    #[test]
+    fn test_roundtrip_archive() {
+        let source = temporary_directory();
+        std::fs::create_dir_all(source.join("keys").join("nested")).unwrap();
+        std::fs::write(source.join("keys").join("id_ed25519"), b"private").unwrap();
+        std::fs::write(source.join("keys").join("nested").join("config"), b"config").unwrap();
+        std::fs::write(source.join("notes.txt"), b"notes").unwrap();
+
+        let archive = write_archive(&[source.join("keys"), source.join("notes.txt")], Vec::new()).unwrap();
+        assert!(write_archive(&[source.join("notes.txt"), source.join("keys").join("..").join("notes.txt")], Vec::new()).is_err());
+
+        let destination = temporary_directory();
+        let restored = extract_archive(&archive[..], &destination, false).unwrap();
+        assert_eq!(restored, vec![destination.join("keys"), destination.join("notes.txt")]);
+        assert_eq!(std::fs::read(destination.join("keys").join("nested").join("config")).unwrap(), b"config");
+        assert_eq!(std::fs::read(destination.join("keys").join("id_ed25519")).unwrap(), b"private");
+        assert_eq!(std::fs::read(destination.join("notes.txt")).unwrap(), b"notes");
+        // Only the restored entries, and no staging directory
+        assert_eq!(std::fs::read_dir(&destination).unwrap().count(), 2);
+
+        // Existing entries are never replaced by a second extraction
+        assert!(extract_archive(&archive[..], &destination, false).unwrap_err().starts_with(output::ALREADY_EXISTS));
+        assert!(extract_archive(&archive[..], &destination, true).unwrap_err().starts_with(output::ALREADY_EXISTS));
+        assert_eq!(std::fs::read_dir(&destination).unwrap().count(), 2);
+
+        std::fs::remove_dir_all(&source).unwrap();
+        std::fs::remove_dir_all(&destination).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_no_setuid_from_archive() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o4755);
+        header.set_entry_type(tar::EntryType::Regular);
+        builder.append_data(&mut header, "tool", &b"data"[..]).unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        let destination = temporary_directory();
+        extract_archive(&archive[..], &destination, false).unwrap();
+        assert_eq!(std::fs::metadata(destination.join("tool")).unwrap().permissions().mode() & 0o7777, 0o755);
+
+        std::fs::remove_dir_all(&destination).unwrap();
+    }
+
+    #[test]
+    fn test_move_into_place_rollback() {
+        let staging = temporary_directory();
+        let destination = temporary_directory();
+        std::fs::create_dir(staging.join("keys")).unwrap();
+        std::fs::write(staging.join("keys").join("id_ed25519"), b"private").unwrap();
+        std::fs::write(staging.join("notes.txt"), b"new").unwrap();
+        std::fs::write(destination.join("notes.txt"), b"old").unwrap();
+
+        // The last entry is missing from staging, so moving it fails after the others have gone
+        let names: Vec<OsString> = ["keys", "notes.txt", "missing"].iter().map(OsString::from).collect();
+        assert!(move_into_place(&staging, &destination, &names, true).is_err());
+        assert_eq!(std::fs::read(destination.join("notes.txt")).unwrap(), b"old");
+        assert_eq!(std::fs::read_dir(&destination).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&staging).unwrap();
+        std::fs::remove_dir_all(&destination).unwrap();
+    }
+
+    #[test]
+    fn test_reject_unsafe_archive() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o600);
+        header.set_entry_type(tar::EntryType::Regular);
+        // set_path refuses "..", so write the name directly
+        header.as_old_mut().name[..11].copy_from_slice(b"../escaped\0");
+        header.set_cksum();
+        builder.append(&header, &b"data"[..]).unwrap();
+        let archive = builder.into_inner().unwrap();
+
+        let destination = temporary_directory();
+        assert!(extract_archive(&archive[..], destination.join("inner").as_path(), false).is_err());
+        std::fs::create_dir(destination.join("inner")).unwrap();
+        assert!(extract_archive(&archive[..], destination.join("inner").as_path(), false).is_err());
+        assert!(!destination.join("escaped").exists());
+        assert_eq!(std::fs::read_dir(destination.join("inner")).unwrap().count(), 0);
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder.append_link(&mut header, "link", "/etc/passwd").unwrap();
+        let archive = builder.into_inner().unwrap();
+        assert!(extract_archive(&archive[..], destination.join("inner").as_path(), false).is_err());
+        assert_eq!(std::fs::read_dir(destination.join("inner")).unwrap().count(), 0);
+
+        std::fs::remove_dir_all(&destination).unwrap();
+    }
+}
@@ -0,0 +1,371 @@
+extern crate base64;
+
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+
+use crate::header::Header;
+use crate::sss;
+
+pub const BEGIN: &str = "-----BEGIN RUSSS SHARD-----";
+pub const END: &str = "-----END RUSSS SHARD-----";
+// Bytes encoded on each line, making lines of 64 characters
+const LINE_BYTES: usize = 48;
+const CRC24_INIT: u32 = 0xB704CE;
+const CRC24_POLYNOMIAL: u32 = 0x1864CFB;
+
+//# Armored pieces are text, so they survive email and can be pasted anywhere, in the style of OpenPGP:
+//# -----BEGIN RUSSS SHARD-----
+//# Field: value      (for people reading the piece; ignored when reading it back)
+//#                   (blank line)
+//# base64            (of the whole binary piece, header included, 64 characters per line)
+//# =crc              (base64 of the 24-bit OpenPGP CRC of the binary piece)
+//# -----END RUSSS SHARD-----
+//# Line endings, surrounding whitespace and rewrapping of the base64 are all tolerated.
+
+// Fields describing the piece with header, for the top of its armor
+pub fn header_fields(header: &Header) -> Vec<(String, String)> {
+    let mut fields = vec![
+        (String::from("Version"), header.version.to_string()),
+        (String::from("Index"), header.index.to_string()),
+        (String::from("Filename"), String::from_utf8_lossy(&header.filename).into_owned()),
+    ];
+    if let Some(required) = header.extension(sss::REQUIRED_KEY) {
+        fields.push((String::from("Required"), String::from(required)));
+    }
+    if let Some(set_id) = header.extension(sss::SET_ID_KEY) {
+        fields.push((String::from("Set"), String::from(set_id)));
+    }
+    return fields;
+}
+
+// Update crc, as used by OpenPGP (RFC 4880, section 6.1), with data
+fn crc24(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= (*byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= CRC24_POLYNOMIAL;
+            }
+        }
+    }
+    return crc & 0xFFFFFF;
+}
+
+fn crc_line(crc: u32) -> String {
+    return format!("={}", base64::encode_config(&crc.to_be_bytes()[1..], base64::STANDARD));
+}
+
+//# Writes a piece into sink, armored or not
+pub enum PieceWriter<W> {
+    Binary(W),
+    Armored(ArmorWriter<W>),
+}
+
+impl<W> PieceWriter<W>
+    where W: Write {
+    // Complete the piece, returning sink
+    pub fn finish(self: PieceWriter<W>) -> Result<W, String> {
+        return match self {
+            PieceWriter::Binary(sink) => Ok(sink),
+            PieceWriter::Armored(writer) => writer.finish(),
+        }
+    }
+}
+
+impl<W> Write for PieceWriter<W>
+    where W: Write {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        return match self {
+            PieceWriter::Binary(sink) => sink.write(data),
+            PieceWriter::Armored(writer) => writer.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return match self {
+            PieceWriter::Binary(sink) => sink.flush(),
+            PieceWriter::Armored(writer) => writer.flush(),
+        }
+    }
+}
+
+//# Armors everything written to it into sink
+//# finish must be called to write the CRC and end line.
+pub struct ArmorWriter<W> {
+    sink: W,
+    pending: Vec<u8>,
+    crc: u32,
+}
+
+impl<W> ArmorWriter<W>
+    where W: Write {
+    pub fn new(mut sink: W, fields: &[(String, String)]) -> Result<ArmorWriter<W>, String> {
+        let mut text = format!("{}\n", BEGIN);
+        for (key, value) in fields {
+            // Keep each field on its own line, whatever the value
+            text.push_str(&format!("{}: {}\n", key, value.replace(|c: char| c.is_control(), "?")));
+        }
+        text.push('\n');
+        write_text(&mut sink, &text)?;
+
+        return Ok(ArmorWriter { sink, pending: Vec::new(), crc: CRC24_INIT });
+    }
+
+    pub fn finish(mut self: ArmorWriter<W>) -> Result<W, String> {
+        let mut text = String::new();
+        if !self.pending.is_empty() {
+            text.push_str(&base64::encode_config(&self.pending, base64::STANDARD));
+            text.push('\n');
+        }
+        text.push_str(&format!("{}\n{}\n", crc_line(self.crc), END));
+        write_text(&mut self.sink, &text)?;
+
+        return Ok(self.sink);
+    }
+}
+
+impl<W> Write for ArmorWriter<W>
+    where W: Write {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.crc = crc24(self.crc, data);
+        self.pending.extend_from_slice(data);
+
+        let whole_lines = self.pending.len() / LINE_BYTES * LINE_BYTES;
+        let mut text = String::new();
+        for line in self.pending.drain(0..whole_lines).collect::<Vec<u8>>().chunks(LINE_BYTES) {
+            text.push_str(&base64::encode_config(line, base64::STANDARD));
+            text.push('\n');
+        }
+        self.sink.write_all(text.as_bytes())?;
+        return Ok(data.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return self.sink.flush();
+    }
+}
+
+fn write_text<W>(sink: &mut W, text: &str) -> Result<(), String>
+    where W: Write {
+    return match sink.write_all(text.as_bytes()) {
+        Err(error) => Err(format!("Error writing armored piece: {}", error)),
+        Ok(()) => Ok(()),
+    }
+}
+
+//# Reads a piece from source, whether it's armored or not
+pub enum PieceReader<R> {
+    Binary(R),
+    Armored(ArmorReader<R>),
+}
+
+impl<R> PieceReader<R>
+    where R: Read + Seek {
+    // Tell armored pieces from binary ones by how they start
+    pub fn detect(mut source: R) -> Result<PieceReader<R>, String> {
+        let mut start: Vec<u8> = Vec::new();
+        if let Err(error) = (&mut source).take(1024).read_to_end(&mut start) {
+            return Err(format!("Error reading piece: {}", error));
+        }
+        if let Err(error) = source.seek(SeekFrom::Start(0)) {
+            return Err(format!("Error reading piece: {}", error));
+        }
+
+        return Ok(if is_armored(&start) { PieceReader::Armored(ArmorReader::new(source)) } else { PieceReader::Binary(source) });
+    }
+}
+
+impl<R> PieceReader<R> {
+    pub fn is_armored(self: &PieceReader<R>) -> bool {
+        return match self {
+            PieceReader::Binary(_) => false,
+            PieceReader::Armored(_) => true,
+        }
+    }
+}
+
+impl<R> Read for PieceReader<R>
+    where R: Read {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        return match self {
+            PieceReader::Binary(source) => source.read(buffer),
+            PieceReader::Armored(reader) => reader.read(buffer),
+        }
+    }
+}
+
+// Whether data, the start of a piece, is armored
+pub fn is_armored(data: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(data);
+    return text.trim_start().starts_with(BEGIN);
+}
+
+//# Reads the binary piece out of the armored piece read from source
+//# Reaching the end checks the CRC, so damage to the text shows up as an error rather than a bad piece.
+pub struct ArmorReader<R> {
+    source: BufReader<R>,
+    started: bool,
+    ended: bool,
+    // Base64 read but not yet decoded, since lines may have been rewrapped
+    text: Vec<u8>,
+    decoded: Vec<u8>,
+    position: usize,
+    crc: u32,
+}
+
+impl<R> ArmorReader<R>
+    where R: Read {
+    pub fn new(source: R) -> ArmorReader<R> {
+        return ArmorReader {
+            source: BufReader::new(source),
+            started: false,
+            ended: false,
+            text: Vec::new(),
+            decoded: Vec::new(),
+            position: 0,
+            crc: CRC24_INIT,
+        };
+    }
+
+    fn read_line(self: &mut ArmorReader<R>) -> Result<String, String> {
+        let mut line: Vec<u8> = Vec::new();
+        return match self.source.read_until(b'\n', &mut line) {
+            Err(error) => Err(format!("Error reading armored piece: {}", error)),
+            Ok(0) => Err(String::from("Armored piece is truncated")),
+            Ok(_) => Ok(String::from_utf8_lossy(&line).trim().to_string()),
+        }
+    }
+
+    // Read and decode the next line, or check the CRC at the end
+    fn decode_line(self: &mut ArmorReader<R>) -> Result<(), String> {
+        while !self.started {
+            let line = self.read_line()?;
+            if line == BEGIN {
+                self.started = true;
+            } else if !line.is_empty() {
+                return Err(String::from("Malformed armored piece"));
+            }
+        }
+
+        let line = self.read_line()?;
+        if line.is_empty() || line.contains(':') {
+            // A blank line or a field
+            return Ok(());
+        }
+        if line.starts_with('=') || line == END {
+            self.decode_text(true)?;
+            return self.check_end(&line);
+        }
+
+        self.text.extend(line.bytes().filter(|byte| !byte.is_ascii_whitespace()));
+        return self.decode_text(false);
+    }
+
+    fn decode_text(self: &mut ArmorReader<R>, last: bool) -> Result<(), String> {
+        let length = if last { self.text.len() } else { self.text.len() / 4 * 4 };
+        let text: Vec<u8> = self.text.drain(0..length).collect();
+        let decoded = match base64::decode_config(&text, base64::STANDARD) {
+            Err(_) => return Err(String::from("Malformed base64 in armored piece")),
+            Ok(decoded) => decoded,
+        };
+        self.crc = crc24(self.crc, &decoded);
+        self.decoded.extend(decoded);
+        return Ok(());
+    }
+
+    fn check_end(self: &mut ArmorReader<R>, line: &str) -> Result<(), String> {
+        if line == END {
+            return Err(String::from("Armored piece has no CRC"));
+        }
+        if line != crc_line(self.crc) {
+            return Err(String::from("Armored piece failed its CRC check"));
+        }
+        if self.read_line()? != END {
+            return Err(String::from("Malformed armored piece"));
+        }
+        self.ended = true;
+        return Ok(());
+    }
+}
+
+impl<R> Read for ArmorReader<R>
+    where R: Read {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.decoded.len() && !self.ended {
+            self.decoded.clear();
+            self.position = 0;
+            if let Err(message) = self.decode_line() {
+                return Err(std::io::Error::new(ErrorKind::InvalidData, message));
+            }
+        }
+
+        let length = std::cmp::min(buffer.len(), self.decoded.len() - self.position);
+        buffer[0..length].copy_from_slice(&self.decoded[self.position..(self.position + length)]);
+        self.position += length;
+        return Ok(length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use std::io::Cursor;
+
+    fn armor(data: &[u8]) -> Vec<u8> {
+        let fields = header_fields(&Header::new(2, 5717, "secret\nname"));
+        let mut writer = ArmorWriter::new(Vec::new(), &fields).unwrap();
+        for block in data.chunks(100) {
+            writer.write_all(block).unwrap();
+        }
+        return writer.finish().unwrap();
+    }
+
+    fn dearmor(text: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut result: Vec<u8> = Vec::new();
+        ArmorReader::new(text).read_to_end(&mut result)?;
+        return Ok(result);
+    }
+
+    #[test]
+    fn test_crc24() {
+        // From RFC 4880's sample implementation, as computed by GnuPG
+        assert_eq!(crc24(CRC24_INIT, b""), 0xB704CE);
+        assert_eq!(crc24(CRC24_INIT, b"123456789"), 0x21CF02);
+    }
+
+    #[test]
+    fn test_roundtrip_armor() {
+        for length in [0, 1, LINE_BYTES, 1000] {
+            let data: Vec<u8> = (0..length).map(|_| random::<u8>()).collect();
+            let text = armor(&data);
+            let text_string = String::from_utf8(text.clone()).unwrap();
+            assert!(text_string.starts_with(&format!("{}\nVersion: ", BEGIN)));
+            assert!(text_string.contains("Filename: secret?name\n"));
+            assert!(text_string.ends_with(&format!("{}\n", END)));
+            assert!(text_string.lines().all(|line| line.len() <= 64));
+            assert!(is_armored(&text));
+            assert_eq!(dearmor(&text).unwrap(), data);
+
+            // As an email client might leave it
+            let mangled = format!("\r\n{}", text_string.replace("\n", "  \r\n"));
+            assert_eq!(dearmor(mangled.as_bytes()).unwrap(), data);
+        }
+
+        let data: Vec<u8> = (0..1000).map(|_| random::<u8>()).collect();
+        let mut text = armor(&data);
+        let position = text.len() / 2;
+        text[position] = if text[position] == b'A' { b'B' } else { b'A' };
+        assert!(dearmor(&text).is_err());
+
+        let text = armor(&data);
+        assert!(dearmor(&text[0..(text.len() - END.len() - 1)]).is_err());
+
+        let mut reader = PieceReader::detect(Cursor::new(armor(&data))).unwrap();
+        assert!(reader.is_armored());
+        let mut result: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut result).unwrap();
+        assert_eq!(result, data);
+        assert!(!PieceReader::detect(Cursor::new(data.clone())).unwrap().is_armored());
+    }
+}
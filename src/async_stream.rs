@@ -0,0 +1,226 @@
+extern crate tokio;
+
+use std::ffi::OsStr;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::header;
+use crate::header::Header;
+use crate::progress;
+use crate::progress::{Control, Phase, Progress};
+use crate::secret::SecretBuffer;
+use crate::sss;
+use crate::sss::BUFFER_SIZE;
+use crate::stream;
+use crate::stream::{Splitter, Combiner};
+
+//# Asynchronous counterparts to stream::split and stream::combine
+//# Pieces are written in the same format as sss::generate_file, so either can read the other's output.
+//# Every read and write is awaited before the next block is processed, so a slow sink or source
+//# holds back the whole operation instead of buffering without bound.
+
+async fn read_source<R>(source: &mut R, buffer: &mut [u8]) -> Result<usize, String>
+    where R: AsyncRead + Unpin {
+    return match source.read(buffer).await {
+        Err(error) => Err(format!("Error reading input: {}", error)),
+        Ok(read) => Ok(read),
+    }
+}
+
+async fn write_sink<W, T>(sink: &mut W, data: &T) -> Result<(), String>
+    where W: AsyncWrite + Unpin,
+        T: AsRef<[u8]> + ?Sized {
+    return match sink.write_all(data.as_ref()).await {
+        Err(error) => Err(format!("Error writing output: {}", error)),
+        Ok(()) => Ok(()),
+    }
+}
+
+async fn flush_sink<W>(sink: &mut W) -> Result<(), String>
+    where W: AsyncWrite + Unpin {
+    return match sink.flush().await {
+        Err(error) => Err(format!("Error writing output: {}", error)),
+        Ok(()) => Ok(()),
+    }
+}
+
+async fn read_header<R>(source: &mut R) -> Result<Header, String>
+    where R: AsyncRead + Unpin {
+    let mut lines: Vec<Vec<u8>> = Vec::new();
+
+    loop {
+        let mut line: Vec<u8> = Vec::new();
+        loop {
+            let byte = match source.read_u8().await {
+                Err(_) => return Err(String::from("Malformed header in input file")),
+                Ok(byte) => byte,
+            };
+            if header::push_line_byte(&mut line, byte)? {
+                break;
+            }
+        }
+
+        lines.push(line);
+        if let Some(header) = Header::parse(&lines)? {
+            return Ok(header);
+        }
+    }
+}
+
+//# Split everything read from secret into one piece per sink, each preceded by a header naming filename
+//# progress_callback may cancel the split, in which case the sinks are left incomplete
+pub async fn split<TName, R, W, TProgress, TControl>(secret: &mut R, sinks: &mut [W], required_pieces_count: i32, prime: i32, filename: &TName, progress_callback: TProgress) -> Result<u64, String>
+    where TName: AsRef<OsStr> + ?Sized,
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    return split_with_header(secret, sinks, required_pieces_count, &Header::new(0, prime, filename), progress_callback).await;
+}
+
+//# As split, but with every piece's header based on template, which supplies the prime, filename and extensions
+pub async fn split_with_header<R, W, TProgress, TControl>(secret: &mut R, sinks: &mut [W], required_pieces_count: i32, template: &Header, mut progress_callback: TProgress) -> Result<u64, String>
+    where R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    header::validate_filename(&template.filename_os())?;
+    let mut splitter = Splitter::for_header(sinks.len() as i32, required_pieces_count, template)?;
+    let mut buffer = SecretBuffer::zeroed(BUFFER_SIZE);
+    let mut progress: u64 = 0;
+
+    for index in 0..sinks.len() {
+        progress::report(&mut progress_callback, Progress::new(Phase::WritingHeaders, 0, None, Some(index)))?;
+        write_sink(&mut sinks[index], &stream::piece_header(template, index as i32 + 1, required_pieces_count).to_bytes()).await?;
+    }
+
+    loop {
+        let length = read_source(secret, &mut buffer[..]).await?;
+        if length == 0 {
+            break;
+        }
+
+        progress += length as u64;
+        for (index, (sink, piece)) in sinks.iter_mut().zip(splitter.update(&buffer[0..length])).enumerate() {
+            write_sink(sink, &piece).await?;
+            progress::report(&mut progress_callback, Progress::new(Phase::Splitting, progress, None, Some(index)))?;
+        }
+    }
+
+    for (sink, piece) in sinks.iter_mut().zip(splitter.finish()) {
+        write_sink(sink, &piece).await?;
+        flush_sink(sink).await?;
+    }
+
+    return Ok(progress);
+}
+
+//# Recover the secret from the pieces read from sources, writing it into sink
+//# Returns the header shared by the pieces
+//# progress_callback may cancel the combination, in which case sink is left incomplete
+pub async fn combine<R, W, TProgress, TControl>(sources: &mut [R], sink: &mut W, mut progress_callback: TProgress) -> Result<Header, String>
+    where R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    // Headers are read a byte at a time, so the sources are buffered, and read through the buffers from then on
+    let mut readers: Vec<BufReader<&mut R>> = sources.iter_mut().map(BufReader::new).collect();
+    let mut headers: Vec<Header> = Vec::new();
+    for (index, reader) in readers.iter_mut().enumerate() {
+        progress::report(&mut progress_callback, Progress::new(Phase::ReadingHeaders, 0, None, Some(index)))?;
+        headers.push(read_header(reader).await?);
+    }
+    if headers.is_empty() {
+        return Err(String::from("Insufficient number of inputs (0)"));
+    }
+    sss::validate_header(&headers)?;
+
+    let mut combiner = Combiner::for_headers(&headers)?;
+    let mut buffers: Vec<Vec<u8>> = readers.iter().map(|_| vec![0 as u8; BUFFER_SIZE]).collect();
+    let mut progress: u64 = 0;
+
+    loop {
+        let mut lengths: Vec<Option<usize>> = Vec::new();
+        for (index, (reader, buffer)) in readers.iter_mut().zip(buffers.iter_mut()).enumerate() {
+            lengths.push(if combiner.wants_input(index) { Some(read_source(reader, &mut buffer[..]).await?) } else { None });
+        }
+
+        let reads: Vec<Option<&[u8]>> = buffers.iter().zip(lengths.iter()).map(|(buffer, length)| length.map(|length| &buffer[0..length])).collect();
+        let (result, done) = combiner.step(&reads)?;
+        write_sink(sink, &result).await?;
+        progress += result.len() as u64;
+        if done {
+            break;
+        }
+        progress::report(&mut progress_callback, Progress::new(Phase::Combining, progress, None, None))?;
+    }
+    flush_sink(sink).await?;
+
+    return Ok(headers[0].clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use ed25519_dalek::SigningKey;
+    use rand::prelude::*;
+    use crate::signature;
+    use crate::signature::SignWriter;
+    use crate::stream;
+
+    #[tokio::test]
+    async fn test_roundtrip_async() {
+        let secret: Vec<u8> = (0..(BUFFER_SIZE + 17)).map(|_| random::<u8>()).collect();
+        let mut sinks: Vec<Vec<u8>> = (0..5).map(|_| Vec::new()).collect();
+        split(&mut &secret[..], &mut sinks, 3, 5717, "secret", |_|{}).await.unwrap();
+
+        let mut sources: Vec<&[u8]> = vec![&sinks[4][..], &sinks[1][..], &sinks[2][..]];
+        let mut result: Vec<u8> = Vec::new();
+        let header = combine(&mut sources, &mut result, |_|{}).await.unwrap();
+
+        assert_eq!(header.filename, b"secret");
+        assert_eq!(result, secret);
+    }
+
+    #[tokio::test]
+    async fn test_interchangeable_with_blocking() {
+        let secret: Vec<u8> = (0..100).map(|_| random::<u8>()).collect();
+
+        // Blocking split, asynchronous combine
+        let mut sinks: Vec<Vec<u8>> = (0..3).map(|_| Vec::new()).collect();
+        stream::split(&mut Cursor::new(&secret), &mut sinks, 2, 5717, "secret", |_|{}).unwrap();
+        let mut sources: Vec<&[u8]> = sinks.iter().map(|sink| &sink[..]).collect();
+        let mut result: Vec<u8> = Vec::new();
+        combine(&mut sources, &mut result, |_|{}).await.unwrap();
+        assert_eq!(result, secret);
+
+        // Asynchronous split, blocking combine
+        let mut sinks: Vec<Vec<u8>> = (0..3).map(|_| Vec::new()).collect();
+        split(&mut &secret[..], &mut sinks, 2, 5717, "secret", |_|{}).await.unwrap();
+        let mut sources: Vec<Cursor<&Vec<u8>>> = sinks.iter().map(|sink| Cursor::new(sink)).collect();
+        let mut result: Vec<u8> = Vec::new();
+        stream::combine(&mut sources, &mut result, |_|{}).unwrap();
+        assert_eq!(result, secret);
+    }
+
+    #[tokio::test]
+    async fn test_combine_signed_async() {
+        let secret: Vec<u8> = (0..(BUFFER_SIZE + 77)).map(|_| random::<u8>()).collect();
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut template = Header::new(0, 5717, "secret");
+        signature::add_to_header(&key, &mut template);
+        let mut sinks: Vec<SignWriter<Vec<u8>>> = (0..3).map(|_| SignWriter::new(Vec::new(), Some(&key))).collect();
+        stream::split_with_header(&mut Cursor::new(&secret), &mut sinks, 2, &template, |_|{}).unwrap();
+        let sinks: Vec<Vec<u8>> = sinks.into_iter().map(|sink| sink.finish().unwrap()).collect();
+
+        let mut sources: Vec<&[u8]> = vec![&sinks[2][..], &sinks[0][..]];
+        let mut result: Vec<u8> = Vec::new();
+        combine(&mut sources, &mut result, |_|{}).await.unwrap();
+        assert_eq!(result, secret);
+
+        let forged: Vec<u8> = sinks[0][0..(sinks[0].len() - 1)].to_vec();
+        let mut sources: Vec<&[u8]> = vec![&sinks[2][..], &forged[..]];
+        assert!(combine(&mut sources, &mut Vec::new(), |_|{}).await.is_err());
+    }
+}
@@ -0,0 +1,316 @@
+extern crate sha2;
+
+use std::collections::VecDeque;
+use std::convert::TryInto;
+
+use sha2::{Digest, Sha256};
+
+use crate::sss::BUFFER_SIZE;
+
+pub const HASH_LENGTH: usize = 32;
+// Each chunk holds the points for at most BUFFER_SIZE bytes of the secret
+pub const MAX_CHUNK_LENGTH: usize = BUFFER_SIZE * 2;
+// First version whose bodies are split into chunks
+pub const FIRST_CHUNKED_VERSION: i32 = 3;
+// First version whose chunks carry hashes
+pub const FIRST_HASHED_VERSION: i32 = 4;
+
+pub type Hash = [u8; HASH_LENGTH];
+
+//# Piece bodies are a series of chunks, each made up of:
+//# sequenceNumber (u32, little-endian, starting at 0)
+//# length         (u32, little-endian, bytes of point data that follow)
+//# hash           (32 bytes, from FIRST_HASHED_VERSION; see leaf_hash)
+//# points         (two bytes per byte of the secret)
+//# The last chunk is empty, marking the end of the piece, and its hash field holds the root of a Merkle tree
+//# over the hashes of the chunks before it. A piece that stops without one has been truncated, and one whose
+//# sequence numbers skip has had chunks lost or reordered.
+//# Versions before FIRST_CHUNKED_VERSION have bodies of bare points.
+
+fn header_length(hashed: bool) -> usize {
+    return if hashed { 8 + HASH_LENGTH } else { 8 };
+}
+
+// SHA-256 of a zero byte followed by the chunk's sequence number, length and points
+// The prefixes keep chunk hashes and tree nodes from being mistaken for each other, as in RFC 6962
+pub fn leaf_hash(sequence: u32, points: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(&[0]);
+    hasher.update(&sequence.to_le_bytes());
+    hasher.update(&(points.len() as u32).to_le_bytes());
+    hasher.update(points);
+    return hasher.finalize().into();
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(&[1]);
+    hasher.update(left);
+    hasher.update(right);
+    return hasher.finalize().into();
+}
+
+// Root of the Merkle tree over leaves, pairing them up level by level and promoting any odd one out
+pub fn merkle_root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return Sha256::digest(&[]).into();
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level.chunks(2).map(|pair| if pair.len() == 2 { node_hash(&pair[0], &pair[1]) } else { pair[0] }).collect();
+    }
+    return level[0];
+}
+
+//# Frames the points for one piece into chunks
+pub struct ChunkWriter {
+    hashed: bool,
+    sequence: u32,
+    leaves: Vec<Hash>,
+}
+
+impl ChunkWriter {
+    pub fn new(hashed: bool) -> ChunkWriter {
+        return ChunkWriter { hashed, sequence: 0, leaves: Vec::new() };
+    }
+
+    // points must not be empty, or longer than MAX_CHUNK_LENGTH
+    pub fn chunk(self: &mut ChunkWriter, points: &[u8]) -> Vec<u8> {
+        let hash = leaf_hash(self.sequence, points);
+        self.leaves.push(hash);
+        return self.frame(&hash, points);
+    }
+
+    // The end marker
+    pub fn end(mut self: ChunkWriter) -> Vec<u8> {
+        let root = merkle_root(&self.leaves);
+        return self.frame(&root, &[]);
+    }
+
+    fn frame(self: &mut ChunkWriter, hash: &Hash, points: &[u8]) -> Vec<u8> {
+        let mut result = Vec::with_capacity(header_length(self.hashed) + points.len());
+        result.extend_from_slice(&self.sequence.to_le_bytes());
+        result.extend_from_slice(&(points.len() as u32).to_le_bytes());
+        if self.hashed {
+            result.extend_from_slice(hash);
+        }
+        result.extend_from_slice(points);
+        self.sequence += 1;
+        return result;
+    }
+}
+
+//# Reassembles the chunks of one piece, however its data arrives, checking each against its hash
+//# A piece that can't be read any further records the problem rather than failing, since other
+//# pieces may be able to make up for it.
+//# This reads every chunked version; pieces from before FIRST_HASHED_VERSION are read with hashed false,
+//# which still catches truncation and lost or reordered chunks, but can't tell damaged chunks from good ones.
+pub struct ChunkReader {
+    piece: i32,
+    hashed: bool,
+    received: Vec<u8>,
+    sequence: u32,
+    leaves: Vec<Hash>,
+    // Points of each chunk read but not yet taken, or None for chunks that failed verification
+    chunks: VecDeque<Option<Vec<u8>>>,
+    // Sequence number of the end marker, once read and verified
+    end: Option<u32>,
+    problem: Option<String>,
+}
+
+impl ChunkReader {
+    pub fn new(piece: i32, hashed: bool) -> ChunkReader {
+        return ChunkReader {
+            piece,
+            hashed,
+            received: Vec::new(),
+            sequence: 0,
+            leaves: Vec::new(),
+            chunks: VecDeque::new(),
+            end: None,
+            problem: None,
+        };
+    }
+
+    pub fn update(self: &mut ChunkReader, data: &[u8]) {
+        if self.problem.is_some() {
+            return;
+        }
+        if self.end.is_some() {
+            if !data.is_empty() {
+                self.fail(format!("Unexpected data after the end of piece {}", self.piece));
+            }
+            return;
+        }
+        self.received.extend_from_slice(data);
+
+        let header_length = header_length(self.hashed);
+        while self.received.len() >= header_length {
+            let sequence = u32::from_le_bytes(self.received[0..4].try_into().unwrap());
+            let length = u32::from_le_bytes(self.received[4..8].try_into().unwrap()) as usize;
+            if sequence != self.sequence {
+                return self.fail(format!("Piece {} has chunk {} where chunk {} should be; chunks are missing or out of order", self.piece, sequence, self.sequence));
+            }
+            if length > MAX_CHUNK_LENGTH || length % 2 != 0 {
+                return self.fail(format!("Malformed chunk {} in piece {}", sequence, self.piece));
+            }
+            if self.received.len() < header_length + length {
+                break;
+            }
+
+            let hash: Option<Hash> = if self.hashed { Some(self.received[8..header_length].try_into().unwrap()) } else { None };
+            let points: Vec<u8> = self.received.drain(0..(header_length + length)).skip(header_length).collect();
+            self.sequence += 1;
+
+            if length == 0 {
+                // The tree covers the hashes as recorded, so damage to the data of a chunk doesn't stop the rest being trusted
+                if hash.is_some() && hash != Some(merkle_root(&self.leaves)) {
+                    return self.fail(format!("Piece {} failed verification", self.piece));
+                }
+                if !self.received.is_empty() {
+                    return self.fail(format!("Unexpected data after the end of piece {}", self.piece));
+                }
+                self.end = Some(sequence);
+                return;
+            }
+
+            let intact = match hash {
+                None => true,
+                Some(hash) => {
+                    self.leaves.push(hash);
+                    leaf_hash(sequence, &points) == hash
+                },
+            };
+            self.chunks.push_back(if intact { Some(points) } else { None });
+        }
+    }
+
+    // No more data is coming, so a piece that hasn't ended is truncated
+    pub fn finish(self: &mut ChunkReader) {
+        if !self.is_done() {
+            self.fail(format!("Piece {} is truncated", self.piece));
+        }
+    }
+
+    // Take the next chunk read: None if there isn't one, Some(None) if it failed verification
+    pub fn take(self: &mut ChunkReader) -> Option<Option<Vec<u8>>> {
+        return self.chunks.pop_front();
+    }
+
+    pub fn queued_chunks(self: &ChunkReader) -> usize {
+        return self.chunks.len();
+    }
+
+    pub fn queued_length(self: &ChunkReader) -> usize {
+        return self.chunks.iter().map(|chunk| chunk.as_ref().map(|points| points.len()).unwrap_or(0)).sum();
+    }
+
+    // Whether no more chunks will be read, because the piece has ended or can't be read any further
+    pub fn is_done(self: &ChunkReader) -> bool {
+        return self.end.is_some() || self.problem.is_some();
+    }
+
+    pub fn end(self: &ChunkReader) -> Option<u32> {
+        return self.end;
+    }
+
+    pub fn problem(self: &ChunkReader) -> Option<&str> {
+        return self.problem.as_ref().map(|problem| problem.as_str());
+    }
+
+    fn fail(self: &mut ChunkReader, problem: String) {
+        self.problem = Some(problem);
+        self.end = None;
+        self.received.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merkle_root() {
+        let leaves: Vec<Hash> = (0..3).map(|index| leaf_hash(index, &[index as u8; 4])).collect();
+        assert_eq!(merkle_root(&leaves[..1]), leaves[0]);
+        assert_eq!(merkle_root(&leaves[..2]), node_hash(&leaves[0], &leaves[1]));
+        assert_eq!(merkle_root(&leaves), node_hash(&node_hash(&leaves[0], &leaves[1]), &leaves[2]));
+        assert!(merkle_root(&[leaves[1], leaves[0], leaves[2]]) != merkle_root(&leaves));
+    }
+
+    fn write_chunks(chunks: &[&[u8]], hashed: bool) -> Vec<u8> {
+        let mut writer = ChunkWriter::new(hashed);
+        let mut body: Vec<u8> = Vec::new();
+        for chunk in chunks {
+            body.extend(writer.chunk(chunk));
+        }
+        body.extend(writer.end());
+        return body;
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let body = write_chunks(&[&[1, 2, 3, 4], &[5, 6]], true);
+
+        // A byte at a time
+        let mut reader = ChunkReader::new(1, true);
+        for byte in &body {
+            reader.update(&[*byte]);
+        }
+        assert_eq!(reader.take(), Some(Some(vec![1, 2, 3, 4])));
+        assert_eq!(reader.take(), Some(Some(vec![5, 6])));
+        assert_eq!(reader.take(), None);
+        assert_eq!(reader.end(), Some(2));
+        assert_eq!(reader.problem(), None);
+
+        // Damage to a chunk's points only affects that chunk
+        let mut damaged = body.clone();
+        damaged[8 + HASH_LENGTH] ^= 1;
+        let mut reader = ChunkReader::new(1, true);
+        reader.update(&damaged);
+        assert_eq!(reader.take(), Some(None));
+        assert_eq!(reader.take(), Some(Some(vec![5, 6])));
+        assert_eq!(reader.end(), Some(2));
+
+        // Damage to a recorded hash is caught by the tree
+        let mut damaged = body.clone();
+        damaged[8] ^= 1;
+        let mut reader = ChunkReader::new(1, true);
+        reader.update(&damaged);
+        assert_eq!(reader.take(), Some(None));
+        assert_eq!(reader.end(), None);
+        assert_eq!(reader.problem(), Some("Piece 1 failed verification"));
+
+        let mut reader = ChunkReader::new(1, true);
+        reader.update(&body[..(body.len() - 1)]);
+        reader.finish();
+        assert_eq!(reader.problem(), Some("Piece 1 is truncated"));
+    }
+
+    #[test]
+    fn test_read_unhashed_chunks() {
+        let body = write_chunks(&[&[1, 2, 3, 4], &[5, 6]], false);
+        assert_eq!(body.len(), 3 * 8 + 6);
+
+        let mut reader = ChunkReader::new(2, false);
+        reader.update(&body);
+        reader.finish();
+        assert_eq!(reader.take(), Some(Some(vec![1, 2, 3, 4])));
+        assert_eq!(reader.take(), Some(Some(vec![5, 6])));
+        assert_eq!(reader.end(), Some(2));
+        assert_eq!(reader.problem(), None);
+
+        // Dropping a chunk shows up in the sequence numbers
+        let mut skipped = body[0..12].to_vec();
+        skipped.extend_from_slice(&body[22..]);
+        let mut reader = ChunkReader::new(2, false);
+        reader.update(&skipped);
+        assert_eq!(reader.problem(), Some("Piece 2 has chunk 2 where chunk 1 should be; chunks are missing or out of order"));
+
+        let mut reader = ChunkReader::new(2, false);
+        reader.update(&body[..(body.len() - 8)]);
+        reader.finish();
+        assert_eq!(reader.problem(), Some("Piece 2 is truncated"));
+    }
+}
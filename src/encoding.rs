@@ -0,0 +1,176 @@
+//# Encodings for copying binary data out by hand, which point to where a transcription went wrong:
+//# words   one word per byte, from a list in which the first four letters of each word are enough to identify it
+//# base32  Crockford's base32, in groups of four characters each followed by a check character
+
+pub const WORDS: [&str; 256] = [
+    "acorn", "actor", "agent", "alarm", "album", "anchor", "angle", "apple", "arena", "armor", "arrow", "atom", "audio",
+    "bacon", "badge", "bamboo", "banana", "basket", "beach", "bean", "bird", "blanket", "board", "bottle", "brick",
+    "bridge", "butter", "cabin", "cactus", "camera", "candle", "canyon", "carpet", "castle", "cattle", "chair",
+    "cherry", "circle", "clock", "cloud", "coconut", "coffee", "comet", "copper", "cotton", "crane", "cube", "daisy",
+    "dance", "deer", "diamond", "dish", "doctor", "dolphin", "donkey", "dragon", "drum", "duck", "eagle", "earth",
+    "echo", "edge", "elbow", "elephant", "engine", "evening", "falcon", "farm", "feather", "fence", "ferry", "field",
+    "finger", "fire", "fish", "flag", "flower", "foam", "forest", "fox", "frog", "fruit", "galaxy", "garden", "garlic",
+    "gate", "giant", "ginger", "giraffe", "glass", "globe", "glove", "goat", "gold", "grape", "guitar", "hammer",
+    "harbor", "hawk", "helmet", "hero", "hill", "honey", "horse", "hotel", "ice", "idea", "igloo", "image", "ink",
+    "insect", "island", "ivory", "jacket", "jaguar", "jazz", "jelly", "jewel", "joke", "juice", "jungle", "kayak",
+    "kettle", "king", "kitchen", "kite", "knife", "koala", "ladder", "lake", "lamp", "laptop", "leaf", "lemon",
+    "leopard", "lion", "lizard", "lobster", "lock", "magnet", "mango", "maple", "marble", "meadow", "melody", "metal",
+    "mirror", "monkey", "moon", "motor", "mountain", "needle", "nest", "noodle", "north", "number", "oak", "ocean",
+    "olive", "onion", "orange", "orbit", "ostrich", "otter", "oven", "oyster", "palace", "panda", "paper", "parrot",
+    "pencil", "pepper", "piano", "picnic", "pillow", "pilot", "planet", "pocket", "poem", "pony", "potato", "pumpkin",
+    "puzzle", "quarter", "queen", "quilt", "rabbit", "radio", "rainbow", "raven", "ribbon", "river", "robot", "rocket",
+    "roof", "rose", "salmon", "scarf", "school", "seed", "shadow", "shell", "shoe", "silver", "siren", "skate", "smoke",
+    "snake", "soccer", "spider", "squirrel", "stamp", "summer", "sunset", "swan", "sword", "table", "tail", "tennis",
+    "thunder", "ticket", "tiger", "toast", "tomato", "tornado", "trumpet", "tulip", "tunnel", "turtle", "umbrella",
+    "uncle", "unicorn", "valley", "velvet", "video", "village", "violin", "visit", "volcano", "wagon", "walnut", "wasp",
+    "water", "wedding", "whale", "wheat", "window", "winter", "wizard", "wolf", "wood", "yacht", "yellow", "yoga",
+    "zebra", "zone",
+];
+// Letters of a word that identify it
+const PREFIX_LENGTH: usize = 4;
+
+const BASE32_ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+// Check characters are the group's value mod 37; as 37 is prime, they catch any one mistyped character or swapped pair
+const CHECK_ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ*~$=U";
+const GROUP_LENGTH: usize = 4;
+const GROUP_SEPARATOR: char = '-';
+
+pub fn encode_words(data: &[u8]) -> String {
+    return data.iter().map(|byte| WORDS[*byte as usize]).collect::<Vec<&str>>().join(" ");
+}
+
+pub fn decode_words(text: &str) -> Result<Vec<u8>, String> {
+    let mut result: Vec<u8> = Vec::new();
+    for (position, word) in text.split_whitespace().enumerate() {
+        match find_word(word) {
+            None => return Err(format!("Word {} (\"{}\") isn't in the word list", position + 1, word)),
+            Some(byte) => result.push(byte),
+        }
+    }
+    return Ok(result);
+}
+
+// Words are matched on their first letters, so misspellings after those don't matter
+fn find_word(word: &str) -> Option<u8> {
+    let my_word = word.to_lowercase();
+    let prefix: String = my_word.chars().take(PREFIX_LENGTH).collect();
+    return WORDS.iter().position(|candidate| {
+        if prefix.chars().count() < PREFIX_LENGTH { *candidate == my_word } else { candidate.starts_with(&prefix) }
+    }).map(|position| position as u8);
+}
+
+pub fn encode_base32(data: &[u8]) -> String {
+    let mut symbols: Vec<u8> = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            symbols.push(((buffer >> bits) & 31) as u8);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        symbols.push(((buffer << (5 - bits)) & 31) as u8);
+    }
+
+    return symbols.chunks(GROUP_LENGTH).map(|group| {
+        let mut text: String = group.iter().map(|symbol| BASE32_ALPHABET[*symbol as usize] as char).collect();
+        text.push(CHECK_ALPHABET[check_value(group)] as char);
+        text
+    }).collect::<Vec<String>>().join(&GROUP_SEPARATOR.to_string());
+}
+
+pub fn decode_base32(text: &str) -> Result<Vec<u8>, String> {
+    // Separators are only there for readability, so groups are found by counting
+    let characters: Vec<char> = text.chars().filter(|c| !c.is_whitespace() && *c != GROUP_SEPARATOR).collect();
+    let mut symbols: Vec<u8> = Vec::new();
+    for (index, group) in characters.chunks(GROUP_LENGTH + 1).enumerate() {
+        let group_text: String = group.iter().collect();
+        if group.len() < 2 {
+            return Err(format!("Group {} (\"{}\") is incomplete", index + 1, group_text));
+        }
+        let mut values: Vec<u8> = Vec::new();
+        for c in &group[..(group.len() - 1)] {
+            match decode_symbol(*c, BASE32_ALPHABET) {
+                None => return Err(format!("Group {} (\"{}\") has a character that isn't base32: '{}'", index + 1, group_text, c)),
+                Some(value) => values.push(value),
+            }
+        }
+        if decode_symbol(group[group.len() - 1], CHECK_ALPHABET) != Some(check_value(&values) as u8) {
+            return Err(format!("Group {} (\"{}\") failed its check; look for a typing mistake there", index + 1, group_text));
+        }
+        symbols.extend(values);
+    }
+
+    // Bits left over at the end are padding
+    let mut result: Vec<u8> = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for symbol in symbols {
+        buffer = (buffer << 5) | symbol as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    return Ok(result);
+}
+
+// Crockford's decoding is forgiving of case and of the letters most easily confused with digits
+fn decode_symbol(c: char, alphabet: &[u8]) -> Option<u8> {
+    let normalized = match c.to_ascii_uppercase() {
+        'O' => '0',
+        'I' | 'L' => '1',
+        other => other,
+    };
+    return alphabet.iter().position(|symbol| *symbol as char == normalized).map(|position| position as u8);
+}
+
+fn check_value(symbols: &[u8]) -> usize {
+    return symbols.iter().fold(0, |value, symbol| (value * 32 + *symbol as usize) % 37);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_list() {
+        let mut prefixes: Vec<String> = WORDS.iter().map(|word| word.chars().take(PREFIX_LENGTH).collect()).collect();
+        prefixes.dedup();
+        assert_eq!(prefixes.len(), WORDS.len());
+        assert!(WORDS.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_roundtrip_words() {
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode_words(&encode_words(&data)).unwrap(), data);
+        assert_eq!(decode_words("ACORN\n actr").unwrap_err(), "Word 2 (\"actr\") isn't in the word list");
+        assert_eq!(decode_words("acor agen zebras").unwrap(), vec![0, 2, 254]);
+    }
+
+    #[test]
+    fn test_roundtrip_base32() {
+        for length in 0..12 {
+            let data: Vec<u8> = (0..length).map(|index| (index * 37 + 11) as u8).collect();
+            let text = encode_base32(&data);
+            assert_eq!(decode_base32(&text).unwrap(), data);
+            assert_eq!(decode_base32(&text.to_lowercase().replace("-", " ")).unwrap(), data);
+        }
+        assert_eq!(encode_base32(b"hello"), "D1JP$-RV3F6");
+
+        // Mistakes are pinned to their group
+        let text = encode_base32(b"0123456789");
+        let mut mistyped: Vec<char> = text.chars().collect();
+        mistyped.swap(12, 13);
+        let mistyped: String = mistyped.into_iter().collect();
+        assert!(decode_base32(&mistyped).unwrap_err().starts_with("Group 3 "));
+        assert!(decode_base32(&text.replacen("-", "!", 1)).unwrap_err().contains("isn't base32"));
+    }
+}
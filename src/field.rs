@@ -0,0 +1,210 @@
+//# Arithmetic in the finite field of integers modulo a prime below 2^16
+//# Operations on values run in constant time: there are no branches, table lookups or divisions
+//# that depend on them. Reduction uses Barrett's method with a masked final subtraction, and
+//# inversion uses Fermat's little theorem with a fixed sequence of multiplications.
+//# The prime itself, and the x-coordinates of points, are treated as public.
+
+pub const MAX_PRIME: u32 = 1 << 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Field {
+    prime: u32,
+    // floor(2^32 / prime)
+    barrett: u64,
+}
+
+impl Field {
+    // prime must be an odd prime below MAX_PRIME; see validate_prime
+    pub fn new(prime: u32) -> Field {
+        return Field {
+            prime,
+            barrett: (1u64 << 32) / prime as u64,
+        };
+    }
+
+    pub fn prime(self: &Field) -> u32 {
+        return self.prime;
+    }
+
+    // Subtract prime from value if value >= prime, for value < 2 * prime
+    fn subtract_if_needed(self: &Field, value: u32) -> u32 {
+        let difference = value.wrapping_sub(self.prime);
+        // All ones if the subtraction borrowed, i.e. value < prime
+        let mask = 0u32.wrapping_sub(difference >> 31);
+        return (value & mask) | (difference & !mask);
+    }
+
+    // Reduce any 32-bit value into the field
+    pub fn reduce(self: &Field, value: u32) -> u32 {
+        let quotient = ((value as u64 * self.barrett) >> 32) as u32;
+        // The estimated quotient is at most one too small, so one subtraction finishes the job
+        return self.subtract_if_needed(value.wrapping_sub(quotient.wrapping_mul(self.prime)));
+    }
+
+    // Reduce a signed public value, such as an x-coordinate, into the field
+    pub fn from_public(self: &Field, value: i64) -> u32 {
+        return value.rem_euclid(self.prime as i64) as u32;
+    }
+
+    pub fn add(self: &Field, a: u32, b: u32) -> u32 {
+        return self.subtract_if_needed(a + b);
+    }
+
+    pub fn subtract(self: &Field, a: u32, b: u32) -> u32 {
+        return self.subtract_if_needed(a + self.prime - b);
+    }
+
+    pub fn multiply(self: &Field, a: u32, b: u32) -> u32 {
+        return self.reduce(a * b);
+    }
+
+    // Raise base to exponent, doing the same work for every base
+    pub fn power(self: &Field, base: u32, exponent: u32) -> u32 {
+        let mut result = 1;
+        for bit in (0..32).rev() {
+            result = self.multiply(result, result);
+            let product = self.multiply(result, base);
+            let mask = 0u32.wrapping_sub((exponent >> bit) & 1);
+            result = (product & mask) | (result & !mask);
+        }
+        return result;
+    }
+
+    // Multiplicative inverse of value, or 0 if value is 0
+    pub fn inverse(self: &Field, value: u32) -> u32 {
+        return self.power(value, self.prime - 2);
+    }
+
+    // Evaluate the polynomial constant + coefficients[0] * x + coefficients[1] * x^2 + ... at x
+    pub fn evaluate<T>(self: &Field, constant: u32, coefficients: &T, x: u32) -> u32
+        where T: AsRef<[u32]> + ?Sized {
+        // Horner's method, from the highest order coefficient down
+        let mut result = 0;
+        for coefficient in coefficients.as_ref().iter().rev() {
+            result = self.multiply(self.add(result, *coefficient), x);
+        }
+        return self.add(result, constant);
+    }
+
+    // Lagrange basis polynomials for x_values, evaluated at 0
+    // The secret is the sum of each y-value multiplied by the corresponding basis value
+    pub fn lagrange_basis_at_zero<T>(self: &Field, x_values: &T) -> Result<Vec<u32>, String>
+        where T: AsRef<[u32]> + ?Sized {
+        let my_x_values = x_values.as_ref();
+        let mut basis: Vec<u32> = Vec::new();
+
+        for (index, this_x) in my_x_values.iter().enumerate() {
+            let mut numerator = 1;
+            let mut denominator = 1;
+            for (other_index, other_x) in my_x_values.iter().enumerate() {
+                if other_index != index {
+                    numerator = self.multiply(numerator, *other_x);
+                    denominator = self.multiply(denominator, self.subtract(*other_x, *this_x));
+                }
+            }
+            // x-values are public, so this check doesn't leak anything
+            if denominator == 0 {
+                return Err(String::from("Duplicate indices in input"));
+            }
+            basis.push(self.multiply(numerator, self.inverse(denominator)));
+        }
+
+        return Ok(basis);
+    }
+
+    // Sum of the products of y_values and basis, as produced by lagrange_basis_at_zero
+    pub fn combine<TY, TBasis>(self: &Field, y_values: &TY, basis: &TBasis) -> u32
+        where TY: AsRef<[u32]> + ?Sized,
+            TBasis: AsRef<[u32]> + ?Sized {
+        return y_values.as_ref().iter().zip(basis.as_ref().iter()).fold(0, |total, (y, weight)| {
+            self.add(total, self.multiply(*y, *weight))
+        });
+    }
+}
+
+pub fn validate_prime(prime: i32) -> Result<(), String> {
+    if prime < 3 || prime as u32 >= MAX_PRIME {
+        return Err(format!("Prime {} must be between 3 and {}", prime, MAX_PRIME));
+    }
+    if (2..prime).take_while(|divisor| divisor * divisor <= prime).any(|divisor| prime % divisor == 0) {
+        return Err(format!("{} is not prime", prime));
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_reduce() {
+        let field = Field::new(5717);
+        for _ in 0..10000 {
+            let value = random::<u32>();
+            assert_eq!(field.reduce(value), value % 5717);
+        }
+        assert_eq!(field.reduce(u32::max_value()), u32::max_value() % 5717);
+        assert_eq!(field.reduce(5717), 0);
+        assert_eq!(field.reduce(5716), 5716);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let field = Field::new(65521);
+        for _ in 0..10000 {
+            let a = thread_rng().gen_range(0, 65521);
+            let b = thread_rng().gen_range(0, 65521);
+            assert_eq!(field.add(a, b), (a + b) % 65521);
+            assert_eq!(field.subtract(a, b), (a + 65521 - b) % 65521);
+            assert_eq!(field.multiply(a, b), ((a as u64 * b as u64) % 65521) as u32);
+        }
+    }
+
+    //    it "calculates modular multiplicative inverse given known inputs" do
+    #[test]
+    fn test_inverse() {
+        let test_data = [
+            ((-4, 3617), 904),
+            ((-4, 7211), -1803),
+        ];
+
+        for test_datum in &test_data {
+            let field = Field::new((test_datum.0).1);
+            let value = field.from_public((test_datum.0).0);
+            let inverse = field.inverse(value);
+            assert_eq!(inverse, field.from_public(test_datum.1));
+            assert_eq!(field.multiply(value, inverse), 1);
+        }
+
+        assert_eq!(Field::new(5717).inverse(0), 0);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        // https://en.wikipedia.org/wiki/Shamir%27s_Secret_Sharing
+        let field = Field::new(1613);
+        let expected_y_values = [1494, 329, 965, 176, 1188, 775];
+        for x in 1..7 {
+            assert_eq!(field.evaluate(1234, &[166, 94], x), expected_y_values[x as usize - 1]);
+        }
+    }
+
+    #[test]
+    fn test_lagrange() {
+        let field = Field::new(1613);
+        let basis = field.lagrange_basis_at_zero(&[2, 4, 5]).unwrap();
+        assert_eq!(field.combine(&[329, 176, 1188], &basis), 1234);
+        assert!(field.lagrange_basis_at_zero(&[2, 4, 2]).is_err());
+    }
+
+    #[test]
+    fn test_validate_prime() {
+        assert!(validate_prime(5717).is_ok());
+        assert!(validate_prime(65521).is_ok());
+        assert!(validate_prime(5719).is_err());
+        assert!(validate_prime(2).is_err());
+        assert!(validate_prime(65537).is_err());
+    }
+}
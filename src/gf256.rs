@@ -0,0 +1,99 @@
+//# Arithmetic in GF(256) modulo x^8 + x^4 + x^3 + x + 1, the field of AES, as SLIP-0039 and Vault share over it
+//# Multiplication takes the same steps whatever the values, with no branches or table lookups that depend on them,
+//# so that how long arithmetic on a secret takes doesn't give the secret away.
+
+use crate::secret::SecretBuffer;
+
+// The low byte of the field's polynomial, which is added whenever a product overflows
+const AES_REDUCTION: u8 = 0x1b;
+
+pub fn multiply(a: u8, b: u8) -> u8 {
+    return multiply_modulo(a, b, AES_REDUCTION);
+}
+
+pub fn inverse(a: u8) -> u8 {
+    return inverse_modulo(a, AES_REDUCTION);
+}
+
+//# Multiply in GF(256) modulo x^8 plus the polynomial whose coefficients are the bits of reduction
+pub fn multiply_modulo(a: u8, b: u8, reduction: u8) -> u8 {
+    let (mut a, mut result) = (a, 0);
+    for bit in 0..8 {
+        // Masks of all ones or all zeros stand in for testing each bit
+        result ^= a & 0u8.wrapping_sub((b >> bit) & 1);
+        a = (a << 1) ^ (reduction & 0u8.wrapping_sub(a >> 7));
+    }
+    return result;
+}
+
+//# The inverse of a in GF(256) modulo x^8 plus reduction, or 0 for 0
+pub fn inverse_modulo(a: u8, reduction: u8) -> u8 {
+    // a^254, as a^255 is 1 for all non-zero a: the product of a^2, a^4, ... a^128
+    let (mut power, mut result) = (a, 1);
+    for _ in 1..8 {
+        power = multiply_modulo(power, power, reduction);
+        result = multiply_modulo(result, power, reduction);
+    }
+    return result;
+}
+
+// The values of the polynomial through shares at x, byte by byte
+pub fn interpolate<T>(shares: &[(u8, T)], x: u8) -> SecretBuffer
+    where T: AsRef<[u8]> {
+    if let Some(share) = shares.iter().find(|share| share.0 == x) {
+        return SecretBuffer::from_slice(&share.1);
+    }
+
+    let mut result = SecretBuffer::zeroed(shares[0].1.as_ref().len());
+    for (index, share) in shares.iter().enumerate() {
+        // Subtraction is exclusive or
+        let basis = shares.iter().enumerate().filter(|(other_index, _)| *other_index != index).fold(1, |basis, (_, other)| {
+            multiply(basis, multiply(x ^ other.0, inverse(share.0 ^ other.0)))
+        });
+        for (value, y) in result.iter_mut().zip(share.1.as_ref()) {
+            *value ^= multiply(basis, *y);
+        }
+    }
+    return result;
+}
+
+// The value at x of the polynomial with coefficients, constant term first
+pub fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    return coefficients.iter().rev().fold(0, |value, coefficient| multiply(value, x) ^ coefficient);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field() {
+        assert_eq!(multiply(0x57, 0x83), 0xc1);
+        for value in 1..=255u8 {
+            assert_eq!(multiply(value, inverse(value)), 1);
+        }
+        assert_eq!(inverse(0), 0);
+        // The shift-and-add of the textbook, with its branches, as a reference
+        let reference = |mut a: u8, mut b: u8| {
+            let mut result = 0;
+            while b != 0 {
+                if b & 1 != 0 {
+                    result ^= a;
+                }
+                a = (a << 1) ^ if a & 0x80 != 0 { AES_REDUCTION } else { 0 };
+                b >>= 1;
+            }
+            result
+        };
+        for a in 0..=255u8 {
+            for b in 0..=255u8 {
+                assert_eq!(multiply(a, b), reference(a, b));
+            }
+        }
+
+        let coefficients = [0x42, 0x80, 0x07];
+        let shares: Vec<(u8, Vec<u8>)> = (1..4).map(|x| (x, vec![evaluate(&coefficients, x)])).collect();
+        assert_eq!(interpolate(&shares, 0).as_slice(), &[0x42]);
+        assert_eq!(interpolate(&shares, 9).as_slice(), &[evaluate(&coefficients, 9)]);
+    }
+}
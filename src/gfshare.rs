@@ -0,0 +1,199 @@
+use std::ffi::{OsStr, OsString};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use rand::prelude::*;
+
+use crate::gf256;
+use crate::progress;
+use crate::progress::{Control, Phase, Progress};
+use crate::secret::SecretBuffer;
+use crate::sss::BUFFER_SIZE;
+use crate::stream;
+
+//# Pieces in the format of gfsplit and gfcombine, from libgfshare
+//# Each piece is nothing but its share of each byte of the secret, over GF(256) modulo x^8 + x^4 + x^3 + x^2 + 1,
+//# in a file named after the secret with the x coordinate of the piece as a three digit extension, e.g. notes.txt.042.
+//# Nothing else is recorded: not the threshold, so combining too few pieces gives garbage rather than an error, nor
+//# any metadata of the secret file.
+
+// The low byte of the field's polynomial
+const REDUCTION: u8 = 0x1d;
+const EXTENSION_LENGTH: usize = 3;
+pub const MAX_PIECES_COUNT: usize = 255;
+
+fn multiply(a: u8, b: u8) -> u8 {
+    return gf256::multiply_modulo(a, b, REDUCTION);
+}
+
+fn divide(a: u8, b: u8) -> u8 {
+    return multiply(a, gf256::inverse_modulo(b, REDUCTION));
+}
+
+//# Name of the piece of secret_name at x coordinate share_number
+pub fn piece_name(secret_name: &OsStr, share_number: u8) -> OsString {
+    let mut name = secret_name.to_os_string();
+    name.push(format!(".{:03}", share_number));
+    return name;
+}
+
+//# The x coordinate of a piece from its name, if it has the extension of one
+pub fn share_number<P: AsRef<Path>>(path: P) -> Option<u8> {
+    let extension = path.as_ref().extension()?.to_str()?;
+    if extension.len() != EXTENSION_LENGTH || !extension.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    return extension.parse::<u8>().ok().filter(|number| *number > 0);
+}
+
+//# Distinct random x coordinates for pieces_count pieces, as gfsplit chooses them
+pub fn generate_share_numbers(pieces_count: usize) -> Result<Vec<u8>, String> {
+    if pieces_count > MAX_PIECES_COUNT {
+        return Err(format!("gfshare supports at most {} pieces", MAX_PIECES_COUNT));
+    }
+    let mut numbers: Vec<u8> = (1..=(MAX_PIECES_COUNT as u8)).collect();
+    numbers.shuffle(&mut thread_rng());
+    numbers.truncate(pieces_count);
+    return Ok(numbers);
+}
+
+//# Split everything read from secret into a piece per sink, at the x coordinates in share_numbers
+pub fn split<R, W, TProgress, TControl>(secret: &mut R, sinks: &mut [W], share_numbers: &[u8], required_pieces_count: usize, mut progress_callback: TProgress) -> Result<u64, String>
+    where R: Read,
+        W: Write,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    if required_pieces_count < 1 || required_pieces_count > sinks.len() || sinks.len() != share_numbers.len() {
+        return Err(String::from("Invalid number of pieces"));
+    }
+
+    let mut buffer = SecretBuffer::zeroed(BUFFER_SIZE);
+    let mut progress: u64 = 0;
+    loop {
+        let length = stream::read_source(secret, &mut buffer[..])?;
+        if length == 0 {
+            break;
+        }
+
+        // As libgfshare, the secret is the last coefficient, so the constant term
+        let mut coefficients: Vec<Vec<u8>> = (1..required_pieces_count).map(|_| (0..length).map(|_| thread_rng().gen::<u8>()).collect()).collect();
+        coefficients.push(buffer[0..length].to_vec());
+
+        progress += length as u64;
+        for (index, (sink, share_number)) in sinks.iter_mut().zip(share_numbers).enumerate() {
+            let mut piece = coefficients[0].clone();
+            for coefficient in &coefficients[1..] {
+                for (value, term) in piece.iter_mut().zip(coefficient) {
+                    *value = multiply(*value, *share_number) ^ term;
+                }
+            }
+            stream::write_sink(sink, &piece)?;
+            progress::report(&mut progress_callback, Progress::new(Phase::Splitting, progress, None, Some(index)))?;
+        }
+        coefficients.last_mut().unwrap().iter_mut().for_each(|value| *value = 0);
+    }
+
+    for sink in sinks.iter_mut() {
+        stream::flush_sink(sink)?;
+    }
+    return Ok(progress);
+}
+
+//# Recover the secret from the pieces read from sources, at the x coordinates in share_numbers, writing it into sink
+pub fn combine<R, W, TProgress, TControl>(sources: &mut [R], share_numbers: &[u8], sink: &mut W, mut progress_callback: TProgress) -> Result<u64, String>
+    where R: Read,
+        W: Write,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    if sources.is_empty() || sources.len() != share_numbers.len() {
+        return Err(String::from("Insufficient number of inputs (0)"));
+    }
+    if share_numbers.iter().enumerate().any(|(index, number)| share_numbers[..index].contains(number)) {
+        return Err(String::from("Input pieces have duplicate share numbers"));
+    }
+
+    // Lagrange basis polynomials at 0; subtraction is exclusive or
+    let basis: Vec<u8> = share_numbers.iter().enumerate().map(|(index, x)| {
+        share_numbers.iter().enumerate().filter(|(other, _)| *other != index).fold(1, |basis, (_, other)| {
+            multiply(basis, divide(*other, x ^ other))
+        })
+    }).collect();
+
+    let mut buffers: Vec<Vec<u8>> = vec![vec![0; BUFFER_SIZE]; sources.len()];
+    let mut secret = SecretBuffer::zeroed(BUFFER_SIZE);
+    let mut progress: u64 = 0;
+    loop {
+        let mut lengths: Vec<usize> = Vec::new();
+        for (source, buffer) in sources.iter_mut().zip(buffers.iter_mut()) {
+            lengths.push(stream::fill_buffer(source, buffer)?);
+        }
+        if lengths.iter().any(|length| *length != lengths[0]) {
+            return Err(String::from("Input pieces are of differing lengths"));
+        }
+        let length = lengths[0];
+        if length == 0 {
+            break;
+        }
+
+        secret[0..length].iter_mut().for_each(|value| *value = 0);
+        for (buffer, factor) in buffers.iter().zip(&basis) {
+            for (value, share) in secret[0..length].iter_mut().zip(buffer) {
+                *value ^= multiply(*factor, *share);
+            }
+        }
+        stream::write_sink(sink, &secret[0..length])?;
+        progress += length as u64;
+        progress::report(&mut progress_callback, Progress::new(Phase::Combining, progress, None, None))?;
+    }
+
+    stream::flush_sink(sink)?;
+    return Ok(progress);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_share_names() {
+        assert_eq!(piece_name(OsStr::new("notes.txt"), 42), OsString::from("notes.txt.042"));
+        assert_eq!(share_number("notes.txt.042"), Some(42));
+        assert_eq!(share_number("notes.txt.256"), None);
+        assert_eq!(share_number("notes.txt.000"), None);
+        assert_eq!(share_number("notes.txt"), None);
+    }
+
+    #[test]
+    fn test_roundtrip_gfshare() {
+        let secret: Vec<u8> = (0..(BUFFER_SIZE * 2 + 100)).map(|index| (index * 7) as u8).collect();
+        let share_numbers = generate_share_numbers(5).unwrap();
+        let mut pieces: Vec<Vec<u8>> = vec![Vec::new(); 5];
+        split(&mut &secret[..], &mut pieces, &share_numbers, 3, |_: &Progress| {}).unwrap();
+        assert!(pieces.iter().all(|piece| piece.len() == secret.len()));
+
+        for selection in &[[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let mut sources: Vec<&[u8]> = selection.iter().map(|index| &pieces[*index][..]).collect();
+            let numbers: Vec<u8> = selection.iter().map(|index| share_numbers[*index]).collect();
+            let mut combined: Vec<u8> = Vec::new();
+            combine(&mut sources, &numbers, &mut combined, |_: &Progress| {}).unwrap();
+            assert_eq!(combined, secret);
+        }
+
+        // More pieces than needed still lie on the same polynomial
+        let mut sources: Vec<&[u8]> = pieces.iter().map(|piece| &piece[..]).collect();
+        let mut combined: Vec<u8> = Vec::new();
+        combine(&mut sources, &share_numbers, &mut combined, |_: &Progress| {}).unwrap();
+        assert_eq!(combined, secret);
+    }
+
+    // gfshare's arithmetic: the generator 2 has order 255 modulo its polynomial
+    #[test]
+    fn test_field() {
+        assert_eq!(multiply(0x80, 2), 0x1d);
+        assert_eq!((0..254).fold(1, |power, _| multiply(power, 2)), 0x8e);
+        assert_eq!((0..255).fold(1, |power, _| multiply(power, 2)), 1);
+        for value in 1..=255u8 {
+            assert_eq!(multiply(value, divide(1, value)), 1);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::ffi::{OsStr, OsString};
+use std::io::{ErrorKind, Read, Write};
+
+use crate::sss::{VERSION, MAX_SECRET_FILENAME_LENGTH};
+
+// Limit on extension lines, so a malformed header can't make us read forever
+pub const MAX_EXTENSIONS: usize = 1024;
+
+//# Text header preceding the binary body of every piece
+//# Format:
+//# version\n          (text)
+//# pieceIndex\n       (text)
+//# prime\n            (text)
+//# originalFilename\n (raw bytes of the file name, which need not be valid UTF-8)
+//# extensionCount\n   (text, version 2 and later)
+//# key=value\n        (text, once per extension)
+//# Extensions carry optional information such as file metadata. The same key may appear more than once,
+//# and readers ignore keys they don't recognise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub version: i32,
+    pub index: i32,
+    pub prime: i32,
+    pub filename: Vec<u8>,
+    pub extensions: Vec<(String, String)>,
+}
+
+impl Header {
+    pub fn new<T>(index: i32, prime: i32, filename: &T) -> Header
+        where T: AsRef<OsStr> + ?Sized {
+        return Header {
+            version: VERSION,
+            index,
+            prime,
+            filename: os_str_to_bytes(filename.as_ref()),
+            extensions: Vec::new(),
+        };
+    }
+
+    // key must not contain '=', and neither key nor value may contain line breaks
+    pub fn add_extension(self: &mut Header, key: &str, value: String) {
+        self.extensions.push((String::from(key), value));
+    }
+
+    // The first value for key, if any
+    pub fn extension(self: &Header, key: &str) -> Option<&str> {
+        return self.extensions.iter().find(|extension| extension.0 == key).map(|extension| extension.1.as_str());
+    }
+
+    pub fn extension_values<'a>(self: &'a Header, key: &'a str) -> impl Iterator<Item = &'a str> {
+        return self.extensions.iter().filter(move |extension| extension.0 == key).map(|extension| extension.1.as_str());
+    }
+
+    // The original filename, exactly as it was on the system that split the secret
+    pub fn filename_os(self: &Header) -> OsString {
+        return bytes_to_os_string(&self.filename);
+    }
+
+    pub fn to_bytes(self: &Header) -> Vec<u8> {
+        let mut bytes = format!("{}\n{}\n{}\n", self.version, self.index, self.prime).into_bytes();
+        bytes.extend_from_slice(&self.filename);
+        bytes.push('\n' as u8);
+        if self.version >= 2 {
+            bytes.extend_from_slice(format!("{}\n", self.extensions.len()).as_bytes());
+            for (key, value) in &self.extensions {
+                bytes.extend_from_slice(format!("{}={}\n", key, value).as_bytes());
+            }
+        }
+        return bytes;
+    }
+
+    pub fn write<W>(self: &Header, sink: &mut W) -> Result<(), String>
+        where W: Write {
+        return match sink.write_all(&self.to_bytes()) {
+            Err(error) => Err(format!("Error writing header: {}", error)),
+            Ok(()) => Ok(()),
+        }
+    }
+
+    // Read a header from source, leaving source positioned at the start of the body
+    pub fn read<R>(source: &mut R) -> Result<Header, String>
+        where R: Read {
+        let mut lines: Vec<Vec<u8>> = Vec::new();
+
+        loop {
+            lines.push(read_line(source)?);
+            if let Some(header) = Header::parse(&lines)? {
+                return Ok(header);
+            }
+        }
+    }
+
+    // Parse the header lines read so far, returning None if more lines are needed
+    pub fn parse(lines: &[Vec<u8>]) -> Result<Option<Header>, String> {
+        if lines.len() < 4 {
+            return Ok(None);
+        }
+
+        let version = match String::from_utf8_lossy(&lines[0]).parse::<i32>() {
+            Err(error) => return Err(format!("Error parsing header version: {}", error)),
+            Ok(version) => version,
+        };
+        let index = match String::from_utf8_lossy(&lines[1]).parse::<i32>() {
+            Err(error) => return Err(format!("Error parsing header index: {}", error)),
+            Ok(index) => index,
+        };
+        let prime = match String::from_utf8_lossy(&lines[2]).parse::<i32>() {
+            Err(error) => return Err(format!("Error parsing header prime: {}", error)),
+            Ok(prime) => prime,
+        };
+        let filename = lines[3].clone();
+
+        let mut extensions: Vec<(String, String)> = Vec::new();
+        if version >= 2 {
+            if lines.len() < 5 {
+                return Ok(None);
+            }
+            let count = match String::from_utf8_lossy(&lines[4]).parse::<usize>() {
+                Err(error) => return Err(format!("Error parsing header extension count: {}", error)),
+                Ok(count) => count,
+            };
+            if count > MAX_EXTENSIONS {
+                return Err(format!("Too many header extensions: {}", count));
+            }
+            if lines.len() < 5 + count {
+                return Ok(None);
+            }
+
+            for line in &lines[5..(5 + count)] {
+                let text = match std::str::from_utf8(line) {
+                    Err(_) => return Err(String::from("Malformed header extension in input file")),
+                    Ok(text) => text,
+                };
+                match text.find('=') {
+                    None => return Err(String::from("Malformed header extension in input file")),
+                    Some(position) => extensions.push((String::from(&text[..position]), String::from(&text[(position + 1)..]))),
+                }
+            }
+        }
+
+        return Ok(Some(Header { version, index, prime, filename, extensions }));
+    }
+}
+
+// Check that filename can be stored in a header and read back unchanged
+pub fn validate_filename<T>(filename: &T) -> Result<(), String>
+    where T: AsRef<OsStr> + ?Sized {
+    let bytes = os_str_to_bytes(filename.as_ref());
+    if bytes.contains(&('\n' as u8)) {
+        return Err(format!("File name contains a line break: {}", filename.as_ref().to_string_lossy()));
+    }
+    if bytes.len() > MAX_SECRET_FILENAME_LENGTH {
+        return Err(format!("File name is too long: {}", bytes.len()));
+    }
+    return Ok(());
+}
+
+#[cfg(unix)]
+fn os_str_to_bytes(name: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    return name.as_bytes().to_vec();
+}
+
+#[cfg(unix)]
+fn bytes_to_os_string(bytes: &[u8]) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    return OsString::from_vec(bytes.to_vec());
+}
+
+// Elsewhere, file names are Unicode and stored as UTF-8
+#[cfg(not(unix))]
+fn os_str_to_bytes(name: &OsStr) -> Vec<u8> {
+    return name.to_string_lossy().into_owned().into_bytes();
+}
+
+#[cfg(not(unix))]
+fn bytes_to_os_string(bytes: &[u8]) -> OsString {
+    return OsString::from(String::from_utf8_lossy(bytes).into_owned());
+}
+
+// Add byte to the header line being read, returning whether the line is complete
+pub fn push_line_byte(line: &mut Vec<u8>, byte: u8) -> Result<bool, String> {
+    if byte == '\n' as u8 {
+        return Ok(true);
+    }
+    if line.len() > MAX_SECRET_FILENAME_LENGTH {
+        return Err(String::from("Malformed header in input file"));
+    }
+    line.push(byte);
+    return Ok(false);
+}
+
+// Read a single header line a byte at a time, so that nothing past the header is consumed
+fn read_line<R>(source: &mut R) -> Result<Vec<u8>, String>
+    where R: Read {
+    let mut line: Vec<u8> = Vec::new();
+    let mut byte = [0 as u8; 1];
+
+    loop {
+        match source.read(&mut byte) {
+            Err(ref error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(format!("Error reading header: {}", error)),
+            Ok(0) => return Err(String::from("Malformed header in input file")),
+            Ok(_) => (),
+        }
+        if push_line_byte(&mut line, byte[0])? {
+            return Ok(line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_roundtrip_header() {
+        let header = Header::new(3, 5717, "secret.txt");
+        let mut data: Vec<u8> = Vec::new();
+        header.write(&mut data).unwrap();
+        data.extend_from_slice(&[1, 2, 3]);
+
+        let mut source = Cursor::new(data);
+        assert_eq!(Header::read(&mut source).unwrap(), header);
+
+        // The body must be left unread
+        let mut body: Vec<u8> = Vec::new();
+        source.read_to_end(&mut body).unwrap();
+        assert_eq!(body, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_extensions() {
+        let mut header = Header::new(2, 5717, "secret.txt");
+        header.add_extension("mode", String::from("600"));
+        header.add_extension("xattr", String::from("a:b"));
+        header.add_extension("xattr", String::from("c=d"));
+
+        let read = Header::read(&mut Cursor::new(header.to_bytes())).unwrap();
+        assert_eq!(read, header);
+        assert_eq!(read.extension("mode"), Some("600"));
+        assert_eq!(read.extension_values("xattr").collect::<Vec<&str>>(), vec!["a:b", "c=d"]);
+        assert_eq!(read.extension("owner"), None);
+
+        // Version 1 headers have no extensions
+        let read = Header::read(&mut Cursor::new(b"1\n2\n5717\nsecret\nbody")).unwrap();
+        assert!(read.extensions.is_empty());
+        assert_eq!(read.to_bytes(), b"1\n2\n5717\nsecret\n");
+
+        assert!(Header::read(&mut Cursor::new(b"2\n2\n5717\nsecret\n1\nnoequals\n")).is_err());
+        assert!(Header::read(&mut Cursor::new(b"2\n2\n5717\nsecret\n100000\n")).is_err());
+    }
+
+    #[test]
+    fn test_read_malformed_header() {
+        assert!(Header::read(&mut Cursor::new(b"1\n2\n")).is_err());
+        assert!(Header::read(&mut Cursor::new(b"1\nx\n5717\nsecret\n")).is_err());
+        assert!(Header::read(&mut Cursor::new(vec!['a' as u8; MAX_SECRET_FILENAME_LENGTH * 2])).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_filename() {
+        use std::os::unix::ffi::OsStrExt;
+
+        // "café" in Latin-1
+        let filename = OsStr::from_bytes(b"caf\xe9.txt");
+        let header = Header::new(1, 5717, filename);
+        let read = Header::read(&mut Cursor::new(header.to_bytes())).unwrap();
+        assert_eq!(read.filename, b"caf\xe9.txt");
+        assert_eq!(read.filename_os(), filename);
+
+        assert!(validate_filename(filename).is_ok());
+        assert!(validate_filename("two\nlines").is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use std::ffi::OsStr;
+use std::io::{Read, Write};
+
+use crate::header::Header;
+use crate::progress;
+use crate::progress::{Control, Phase, Progress};
+use crate::sss;
+use crate::sss::BUFFER_SIZE;
+use crate::stream;
+
+//# Pieces in the format of Hiss, the Ruby implementation russs is based on
+//# Each piece is a version 1 header (see header::Header), then the point for each byte of the secret as a
+//# big-endian i16, then a line break. russs's own version 1 pieces have the same header, but little-endian
+//# points and no line break, so Hiss pieces are told apart by the odd length of their bodies and the line break.
+//# Nothing else is recorded: not the threshold, so every piece given is needed, nor any file metadata.
+//# The layout is checked against a single piece written by Hiss, in both directions; no set of pieces from Hiss
+//# is at hand, so combining pieces Hiss wrote, or Hiss combining ours, hasn't been tried.
+
+pub const VERSION: i32 = 1;
+const TRAILER: u8 = '\n' as u8;
+
+//# Whether a piece with header, a body of body_length bytes, and last_byte at its end was written by Hiss
+//# A russs version 1 piece cut short by a byte has an odd-length body too, but rarely ends with the line break.
+pub fn is_hiss_piece(header: &Header, body_length: u64, last_byte: Option<u8>) -> bool {
+    return header.version == VERSION && body_length % 2 == 1 && last_byte == Some(TRAILER);
+}
+
+//# Split everything read from secret into a Hiss piece per sink, each naming filename
+pub fn split<TName, R, W, TProgress, TControl>(secret: &mut R, sinks: &mut [W], required_pieces_count: i32, prime: i32, filename: &TName, mut progress_callback: TProgress) -> Result<u64, String>
+    where TName: AsRef<OsStr> + ?Sized,
+        R: Read,
+        W: Write,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    // Hiss has the same limits on the pieces and the prime as we do
+    stream::Splitter::new(sinks.len() as i32, required_pieces_count, prime)?;
+
+    let mut header = Header::new(0, prime, filename);
+    header.version = VERSION;
+    for (index, sink) in sinks.iter_mut().enumerate() {
+        header.index = index as i32 + 1;
+        stream::write_sink(sink, &header.to_bytes())?;
+    }
+
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut progress: u64 = 0;
+    loop {
+        let length = stream::fill_buffer(secret, &mut buffer)?;
+        if length == 0 {
+            break;
+        }
+
+        let pieces = sss::generate_string(&buffer[0..length], sinks.len() as i32, required_pieces_count, prime, |_|{});
+        buffer.iter_mut().for_each(|value| *value = 0);
+        progress += length as u64;
+        for (index, (sink, mut piece)) in sinks.iter_mut().zip(pieces).enumerate() {
+            piece.1.chunks_mut(2).for_each(|point| point.swap(0, 1));
+            stream::write_sink(sink, &piece.1)?;
+            progress::report(&mut progress_callback, Progress::new(Phase::Splitting, progress, None, Some(index)))?;
+        }
+    }
+
+    for sink in sinks.iter_mut() {
+        stream::write_sink(sink, &[TRAILER])?;
+        stream::flush_sink(sink)?;
+    }
+    return Ok(progress);
+}
+
+//# Recover the secret from the bodies of the Hiss pieces read from sources, whose headers have already been read
+pub fn combine<R, W, TProgress, TControl>(sources: &mut [R], headers: &[Header], sink: &mut W, mut progress_callback: TProgress) -> Result<u64, String>
+    where R: Read,
+        W: Write,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    if sources.len() < 2 || sources.len() != headers.len() {
+        return Err(format!("Insufficient number of inputs ({})", sources.len()));
+    }
+
+    let mut buffers: Vec<Vec<u8>> = vec![vec![0; BUFFER_SIZE * 2]; sources.len()];
+    let mut progress: u64 = 0;
+    loop {
+        let mut lengths: Vec<usize> = Vec::new();
+        for (source, buffer) in sources.iter_mut().zip(buffers.iter_mut()) {
+            lengths.push(stream::fill_buffer(source, buffer)?);
+        }
+        if lengths.iter().any(|length| *length != lengths[0]) {
+            return Err(String::from("Mismatched buffer sizes in input files"));
+        }
+
+        // Only the last read of each piece comes up short, and it ends with the line break
+        let mut length = lengths[0];
+        let last = length < BUFFER_SIZE * 2;
+        if last {
+            if length % 2 != 1 || buffers.iter().any(|buffer| buffer[length - 1] != TRAILER) {
+                return Err(String::from("Hiss pieces are truncated"));
+            }
+            length -= 1;
+        }
+
+        let points: Vec<(i32, Vec<i16>)> = headers.iter().zip(buffers.iter()).map(|(header, buffer)| {
+            (header.index, buffer[0..length].chunks(2).map(|point| i16::from_be_bytes([point[0], point[1]])).collect())
+        }).collect();
+        if length > 0 {
+            stream::write_sink(sink, &sss::interpolate_buffer(&points, headers[0].prime, |_|{})?)?;
+            progress += length as u64 / 2;
+            progress::report(&mut progress_callback, Progress::new(Phase::Combining, progress, None, None))?;
+        }
+        if last {
+            break;
+        }
+    }
+
+    stream::flush_sink(sink)?;
+    return Ok(progress);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::path::Path;
+
+    // A piece checked in with the tests ported from Hiss, whose points are only within the field read big-endian
+    #[test]
+    fn test_read_hiss_piece() {
+        let data = Path::new(file!()).parent().unwrap().parent().unwrap().join("tests").join("data");
+        let secret_length = std::fs::metadata(data.join("testInput")).unwrap().len();
+        let mut source = File::open(data.join("testInput-differingFilename.shard")).unwrap();
+
+        let header = Header::read(&mut source).unwrap();
+        assert_eq!(header, Header { version: 1, index: 1, prime: 5717, filename: b"testInput".to_vec(), extensions: Vec::new() });
+        let mut body: Vec<u8> = Vec::new();
+        source.read_to_end(&mut body).unwrap();
+        assert!(is_hiss_piece(&header, body.len() as u64, body.last().cloned()));
+        assert_eq!(body.pop(), Some(TRAILER));
+        // A russs version 1 piece cut short by a byte ends in half a point instead
+        assert!(!is_hiss_piece(&header, body.len() as u64 - 1, Some(body[body.len() - 2])));
+        assert_eq!(body.len() as u64, secret_length * 2);
+
+        assert!(body.chunks(2).all(|point| (0..header.prime).contains(&(i16::from_be_bytes([point[0], point[1]]) as i32))));
+        assert!(sss::binary_buffer_to_points(&body).iter().any(|point| !(0..header.prime).contains(&(*point as i32))));
+    }
+
+    // Hiss picks its coefficients at random, so only the layout of the piece it wrote can be matched
+    #[test]
+    fn test_write_hiss_piece() {
+        let data = Path::new(file!()).parent().unwrap().parent().unwrap().join("tests").join("data");
+        let hiss_piece = std::fs::read(data.join("testInput-differingFilename.shard")).unwrap();
+        let hiss_header = Header::read(&mut hiss_piece.as_slice()).unwrap();
+
+        let mut pieces: Vec<Vec<u8>> = vec![Vec::new(); 2];
+        split(&mut File::open(data.join("testInput")).unwrap(), &mut pieces, 2, 5717, "testInput", |_|{}).unwrap();
+        let header_length = hiss_header.to_bytes().len();
+        assert_eq!(pieces[0][0..header_length], hiss_piece[0..header_length]);
+        assert_eq!(pieces[0].len(), hiss_piece.len());
+        assert_eq!(pieces[0].last(), hiss_piece.last());
+        assert!(pieces[0][header_length..(pieces[0].len() - 1)].chunks(2).all(|point| (0..5717).contains(&i16::from_be_bytes([point[0], point[1]]))));
+    }
+
+    #[test]
+    fn test_roundtrip_hiss() {
+        // Long enough that the line break is read on its own
+        let secret: Vec<u8> = (0..(BUFFER_SIZE * 2)).map(|index| (index * 7) as u8).collect();
+        let mut pieces: Vec<Vec<u8>> = vec![Vec::new(); 3];
+        split(&mut secret.as_slice(), &mut pieces, 2, 5717, "secret", |_|{}).unwrap();
+        assert!(pieces.iter().all(|piece| piece.starts_with(b"1\n") && piece.ends_with(b"\n")));
+
+        let mut sources: Vec<&[u8]> = pieces[1..].iter().map(|piece| piece.as_slice()).collect();
+        let headers = sss::read_headers(&mut sources, |_|{}).unwrap();
+        let mut result: Vec<u8> = Vec::new();
+        assert_eq!(combine(&mut sources, &headers, &mut result, |_|{}).unwrap(), secret.len() as u64);
+        assert_eq!(result, secret);
+
+        let mut truncated: Vec<&[u8]> = pieces[1..].iter().map(|piece| &piece[..(piece.len() - 1)]).collect();
+        let headers = sss::read_headers(&mut truncated, |_|{}).unwrap();
+        assert!(combine(&mut truncated, &headers, &mut Vec::new(), |_|{}).is_err());
+    }
+}
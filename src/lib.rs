@@ -0,0 +1,26 @@
+pub mod sss;
+pub mod archive;
+pub mod armor;
+pub mod chunk;
+pub mod encoding;
+pub mod field;
+pub mod gf256;
+pub mod gfshare;
+pub mod header;
+pub mod hiss;
+pub mod metadata;
+pub mod stream;
+pub mod progress;
+pub mod recipient;
+pub mod output;
+pub mod parity;
+pub mod passphrase;
+pub mod policy;
+pub mod secret;
+pub mod share;
+pub mod signature;
+pub mod slip39;
+pub mod ssss;
+pub mod vault;
+#[cfg(feature = "async")]
+pub mod async_stream;
@@ -1,10 +1,15 @@
 extern crate gtk;
+extern crate russs;
 
 mod ui;
-mod sss;
 use crate::ui::UI;
+use russs::secret;
 
 fn main() {
+    if let Err(message) = secret::disable_core_dumps() {
+        println!("{}", message);
+    }
+
     if gtk::init().is_err() {
         println!("Failed to initialize GTK.");
         return;
@@ -12,4 +17,3 @@ fn main() {
 
     UI::run();
 }
-
@@ -0,0 +1,230 @@
+extern crate base64;
+extern crate filetime;
+#[cfg(unix)]
+extern crate xattr;
+
+use std::fs::File;
+use std::path::Path;
+
+use filetime::FileTime;
+
+use crate::header::Header;
+use crate::sss::MAX_SECRET_FILENAME_LENGTH;
+
+const MODE_KEY: &str = "mode";
+const MODIFIED_KEY: &str = "modified";
+const OWNER_KEY: &str = "owner";
+const XATTR_KEY: &str = "xattr";
+
+//# File metadata recorded in piece headers, so that reconstructed files match the original
+//# Header extensions:
+//# mode=permissionBits           (octal)
+//# modified=seconds.nanoseconds  (since the Unix epoch)
+//# owner=uid:gid
+//# xattr=name:value              (URL-safe base64, once per extended attribute)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    // Permission bits, including the setuid, setgid and sticky bits
+    pub mode: Option<u32>,
+    // Modification time as seconds and nanoseconds since the Unix epoch
+    pub modified: Option<(i64, u32)>,
+    // User and group IDs
+    pub owner: Option<(u32, u32)>,
+    // Extended attribute names and values
+    pub xattrs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Metadata {
+    // Mode and modification time are always read; owner and extended attributes only on request
+    pub fn read<P>(path: P, include_owner: bool, include_xattrs: bool) -> Result<Metadata, String>
+        where P: AsRef<Path> {
+        let my_path = path.as_ref();
+        let file_metadata = match my_path.metadata() {
+            Err(error) => return Err(format!("Error reading metadata for {}: {}", my_path.display(), error)),
+            Ok(file_metadata) => file_metadata,
+        };
+
+        let modified = FileTime::from_last_modification_time(&file_metadata);
+        let mut metadata = Metadata {
+            modified: Some((modified.unix_seconds(), modified.nanoseconds())),
+            ..Metadata::default()
+        };
+        read_unix_metadata(&mut metadata, my_path, &file_metadata, include_owner, include_xattrs)?;
+
+        return Ok(metadata);
+    }
+
+    pub fn add_to_header(self: &Metadata, header: &mut Header) -> Result<(), String> {
+        if let Some(mode) = self.mode {
+            header.add_extension(MODE_KEY, format!("{:o}", mode));
+        }
+        if let Some((seconds, nanoseconds)) = self.modified {
+            header.add_extension(MODIFIED_KEY, format!("{}.{:09}", seconds, nanoseconds));
+        }
+        if let Some((uid, gid)) = self.owner {
+            header.add_extension(OWNER_KEY, format!("{}:{}", uid, gid));
+        }
+        for (name, value) in &self.xattrs {
+            let encoded = format!("{}:{}", base64::encode_config(name, base64::URL_SAFE), base64::encode_config(value, base64::URL_SAFE));
+            // Each extension has to fit on a single header line
+            if encoded.len() + XATTR_KEY.len() + 1 > MAX_SECRET_FILENAME_LENGTH {
+                return Err(format!("Extended attribute {} is too large to record", String::from_utf8_lossy(name)));
+            }
+            header.add_extension(XATTR_KEY, encoded);
+        }
+
+        return Ok(());
+    }
+
+    pub fn from_header(header: &Header) -> Result<Metadata, String> {
+        let mut metadata = Metadata::default();
+
+        if let Some(mode) = header.extension(MODE_KEY) {
+            metadata.mode = match u32::from_str_radix(mode, 8) {
+                Ok(mode) if mode <= 0o7777 => Some(mode),
+                _ => return Err(format!("Error parsing file mode: {}", mode)),
+            };
+        }
+        if let Some(modified) = header.extension(MODIFIED_KEY) {
+            metadata.modified = match split_pair(modified, '.') {
+                Some((Ok(seconds), Ok(nanoseconds))) if nanoseconds < 1_000_000_000 => Some((seconds, nanoseconds)),
+                _ => return Err(format!("Error parsing modification time: {}", modified)),
+            };
+        }
+        if let Some(owner) = header.extension(OWNER_KEY) {
+            metadata.owner = match split_pair(owner, ':') {
+                Some((Ok(uid), Ok(gid))) => Some((uid, gid)),
+                _ => return Err(format!("Error parsing file owner: {}", owner)),
+            };
+        }
+        for xattr in header.extension_values(XATTR_KEY) {
+            let decoded = xattr.find(':').map(|position| {
+                (base64::decode_config(&xattr[..position], base64::URL_SAFE), base64::decode_config(&xattr[(position + 1)..], base64::URL_SAFE))
+            });
+            match decoded {
+                Some((Ok(name), Ok(value))) => metadata.xattrs.push((name, value)),
+                _ => return Err(format!("Error parsing extended attribute: {}", xattr)),
+            }
+        }
+
+        return Ok(metadata);
+    }
+
+    //# Apply everything recorded here to file
+    //# The setuid and setgid bits are left off unless the owner is also recorded, as they would otherwise grant
+    //# the privileges of whoever reconstructed the file.
+    //# The modification time is set last, so file must not be written to afterwards
+    pub fn apply(self: &Metadata, file: &File) -> Result<(), String> {
+        apply_unix_metadata(self, file)?;
+
+        if let Some((seconds, nanoseconds)) = self.modified {
+            if let Err(error) = filetime::set_file_handle_times(file, None, Some(FileTime::from_unix_time(seconds, nanoseconds))) {
+                return Err(format!("Error restoring modification time: {}", error));
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+fn split_pair<A, B>(text: &str, separator: char) -> Option<(Result<A, A::Err>, Result<B, B::Err>)>
+    where A: std::str::FromStr,
+        B: std::str::FromStr {
+    return text.find(separator).map(|position| (text[..position].parse::<A>(), text[(position + 1)..].parse::<B>()));
+}
+
+#[cfg(unix)]
+fn read_unix_metadata(metadata: &mut Metadata, path: &Path, file_metadata: &std::fs::Metadata, include_owner: bool, include_xattrs: bool) -> Result<(), String> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.mode = Some(file_metadata.mode() & 0o7777);
+    if include_owner {
+        metadata.owner = Some((file_metadata.uid(), file_metadata.gid()));
+    }
+
+    if include_xattrs {
+        let names = match xattr::list(path) {
+            Err(error) => return Err(format!("Error listing extended attributes for {}: {}", path.display(), error)),
+            Ok(names) => names,
+        };
+        for name in names {
+            match xattr::get(path, &name) {
+                Err(error) => return Err(format!("Error reading extended attribute {} for {}: {}", name.to_string_lossy(), path.display(), error)),
+                // Removed since it was listed
+                Ok(None) => (),
+                Ok(Some(value)) => metadata.xattrs.push((name.as_bytes().to_vec(), value)),
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+#[cfg(not(unix))]
+fn read_unix_metadata(_metadata: &mut Metadata, _path: &Path, _file_metadata: &std::fs::Metadata, _include_owner: bool, _include_xattrs: bool) -> Result<(), String> {
+    return Ok(());
+}
+
+#[cfg(unix)]
+fn apply_unix_metadata(metadata: &Metadata, file: &File) -> Result<(), String> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::io::AsRawFd;
+    use xattr::FileExt;
+
+    // Changing the owner clears the setuid and setgid bits, so it has to come before the mode
+    if let Some((uid, gid)) = metadata.owner {
+        if unsafe { libc::fchown(file.as_raw_fd(), uid, gid) } != 0 {
+            return Err(format!("Error restoring file owner: {}", std::io::Error::last_os_error()));
+        }
+    }
+    for (name, value) in &metadata.xattrs {
+        if let Err(error) = file.set_xattr(OsStr::from_bytes(name), value) {
+            return Err(format!("Error restoring extended attribute {}: {}", String::from_utf8_lossy(name), error));
+        }
+    }
+    if let Some(mode) = metadata.mode {
+        let mode = if metadata.owner.is_some() { mode } else { mode & !0o6000 };
+        if let Err(error) = file.set_permissions(std::fs::Permissions::from_mode(mode)) {
+            return Err(format!("Error restoring file mode: {}", error));
+        }
+    }
+
+    return Ok(());
+}
+
+#[cfg(not(unix))]
+fn apply_unix_metadata(_metadata: &Metadata, _file: &File) -> Result<(), String> {
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_header() {
+        let metadata = Metadata {
+            mode: Some(0o4750),
+            modified: Some((-86400, 5)),
+            owner: Some((1000, 100)),
+            xattrs: vec![(b"user.comment".to_vec(), b"a:b\nc".to_vec()), (b"user.empty".to_vec(), Vec::new())],
+        };
+        let mut header = Header::new(1, 5717, "secret");
+        metadata.add_to_header(&mut header).unwrap();
+        assert_eq!(header.extension(MODE_KEY), Some("4750"));
+        assert_eq!(header.extension(MODIFIED_KEY), Some("-86400.000000005"));
+        assert_eq!(Metadata::from_header(&header).unwrap(), metadata);
+
+        assert_eq!(Metadata::from_header(&Header::new(1, 5717, "secret")).unwrap(), Metadata::default());
+
+        let mut header = Header::new(1, 5717, "secret");
+        header.add_extension(MODE_KEY, String::from("99999"));
+        assert!(Metadata::from_header(&header).is_err());
+
+        let too_large = Metadata { xattrs: vec![(b"user.large".to_vec(), vec![0; MAX_SECRET_FILENAME_LENGTH])], ..Metadata::default() };
+        assert!(too_large.add_to_header(&mut Header::new(1, 5717, "secret")).is_err());
+    }
+}
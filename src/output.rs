@@ -0,0 +1,237 @@
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use rand::prelude::*;
+
+pub const ALREADY_EXISTS: &str = "Output file already exists";
+
+//# Output file that only appears at its destination once it is complete
+//# Data is written to a temporary file next to the destination, readable only by its owner,
+//# which commit syncs to disk and renames into place. Dropping an uncommitted OutputFile removes
+//# the temporary file, so failed or cancelled operations leave nothing behind.
+//# Unless overwrite is set, an existing file at the destination is never replaced.
+pub struct OutputFile {
+    file: Option<File>,
+    path: PathBuf,
+    temporary_path: PathBuf,
+    overwrite: bool,
+}
+
+impl OutputFile {
+    pub fn create<P>(path: P, overwrite: bool) -> Result<OutputFile, String>
+        where P: AsRef<Path> {
+        let my_path = path.as_ref();
+        check_destination(my_path, overwrite)?;
+
+        let temporary_path = hidden_path(my_path, "tmp")?;
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        set_private_mode(&mut options);
+
+        return match options.open(&temporary_path) {
+            Err(error) => Err(format!("Error opening {}: {}", temporary_path.display(), error)),
+            Ok(file) => Ok(OutputFile {
+                file: Some(file),
+                path: my_path.to_path_buf(),
+                temporary_path,
+                overwrite,
+            }),
+        }
+    }
+
+    pub fn path(self: &OutputFile) -> &Path {
+        return &self.path;
+    }
+
+    // Whether commit may replace an existing file at path
+    pub fn overwrite(self: &OutputFile) -> bool {
+        return self.overwrite;
+    }
+
+    // The temporary file, for setting metadata before it is committed
+    pub fn file(self: &OutputFile) -> &File {
+        return self.file.as_ref().unwrap();
+    }
+
+    // Sync the data to disk and move it to its destination, returning the destination path
+    pub fn commit(mut self: OutputFile) -> Result<PathBuf, String> {
+        let result = self.move_into_place();
+        if result.is_err() {
+            let _ = std::fs::remove_file(&self.temporary_path);
+        }
+        return result;
+    }
+
+    fn move_into_place(self: &mut OutputFile) -> Result<PathBuf, String> {
+        let file = self.file.take().unwrap();
+        if let Err(error) = file.sync_all() {
+            return Err(format!("Error writing {}: {}", self.path.display(), error));
+        }
+        drop(file);
+
+        if self.overwrite {
+            if let Err(error) = std::fs::rename(&self.temporary_path, &self.path) {
+                return Err(format!("Error writing {}: {}", self.path.display(), error));
+            }
+        } else {
+            // Linking fails if the destination exists, so nothing created in the meantime gets replaced
+            match std::fs::hard_link(&self.temporary_path, &self.path) {
+                Err(ref error) if error.kind() == ErrorKind::AlreadyExists => return Err(format!("{}: {}", ALREADY_EXISTS, self.path.display())),
+                Err(_) => {
+                    // Not every filesystem supports hard links
+                    check_destination(&self.path, false)?;
+                    if let Err(error) = std::fs::rename(&self.temporary_path, &self.path) {
+                        return Err(format!("Error writing {}: {}", self.path.display(), error));
+                    }
+                },
+                Ok(()) => {
+                    let _ = std::fs::remove_file(&self.temporary_path);
+                },
+            }
+        }
+
+        sync_directory(&self.path);
+        return Ok(self.path.clone());
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        return self.file.as_mut().unwrap().write(data);
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return self.file.as_mut().unwrap().flush();
+    }
+}
+
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        // Only uncommitted files still hold their temporary file open
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(&self.temporary_path);
+        }
+    }
+}
+
+// A hidden path next to path, with a random part so that it doesn't collide with anything, ending in extension
+pub fn hidden_path(path: &Path, extension: &str) -> Result<PathBuf, String> {
+    let file_name = match path.file_name() {
+        None => return Err(format!("Error parsing file name: {}", path.display())),
+        Some(name) => name.to_string_lossy().into_owned(),
+    };
+    return Ok(path.with_file_name(format!(".{}.{:016x}.{}", file_name, thread_rng().gen::<u64>(), extension)));
+}
+
+pub fn check_destination<P>(path: P, overwrite: bool) -> Result<(), String>
+    where P: AsRef<Path> {
+    let my_path = path.as_ref();
+    if !overwrite && (my_path.exists() || my_path.symlink_metadata().is_ok()) {
+        return Err(format!("{}: {}", ALREADY_EXISTS, my_path.display()));
+    }
+    return Ok(());
+}
+
+#[cfg(unix)]
+fn set_private_mode(options: &mut OpenOptions) {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.mode(0o600);
+}
+
+#[cfg(not(unix))]
+fn set_private_mode(_options: &mut OpenOptions) {}
+
+// Make the rename of path itself durable, by syncing the directory holding it
+#[cfg(unix)]
+pub fn sync_directory(path: &Path) {
+    if let Some(parent) = path.parent() {
+        let directory = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+        if let Ok(directory) = File::open(directory) {
+            let _ = directory.sync_all();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn sync_directory(_path: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn temporary_directory() -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("russs-output-{:016x}", random::<u64>()));
+        std::fs::create_dir(&directory).unwrap();
+        return directory;
+    }
+
+    fn read(path: &Path) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::new();
+        File::open(path).unwrap().read_to_end(&mut data).unwrap();
+        return data;
+    }
+
+    #[test]
+    fn test_commit() {
+        let directory = temporary_directory();
+        let path = directory.join("output");
+
+        let mut output = OutputFile::create(&path, false).unwrap();
+        output.write_all(b"data").unwrap();
+        assert!(!path.exists());
+        assert_eq!(output.commit().unwrap(), path);
+        assert_eq!(read(&path), b"data");
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(path.metadata().unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_no_clobber() {
+        let directory = temporary_directory();
+        let path = directory.join("output");
+        std::fs::write(&path, b"original").unwrap();
+
+        assert!(OutputFile::create(&path, false).err().unwrap().starts_with(ALREADY_EXISTS));
+
+        // Created before the destination appeared
+        let other_path = directory.join("other");
+        let mut output = OutputFile::create(&other_path, false).unwrap();
+        output.write_all(b"replacement").unwrap();
+        std::fs::write(&other_path, b"original").unwrap();
+        assert!(output.commit().unwrap_err().starts_with(ALREADY_EXISTS));
+        assert_eq!(read(&other_path), b"original");
+
+        let mut output = OutputFile::create(&path, true).unwrap();
+        output.write_all(b"replacement").unwrap();
+        output.commit().unwrap();
+        assert_eq!(read(&path), b"replacement");
+
+        // Only the two destinations should remain
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 2);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_abandon() {
+        let directory = temporary_directory();
+        let path = directory.join("output");
+
+        let mut output = OutputFile::create(&path, false).unwrap();
+        output.write_all(b"partial").unwrap();
+        drop(output);
+
+        assert!(!path.exists());
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 0);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
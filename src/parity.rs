@@ -0,0 +1,338 @@
+extern crate reed_solomon_erasure;
+extern crate sha2;
+
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use sha2::{Digest, Sha256};
+
+use crate::header::Header;
+
+// Header extension recording the parity layout of each piece's body
+pub const PARITY_KEY: &str = "parity";
+// Bytes in each block; small enough that a bad sector only damages one or two
+pub const BLOCK_LENGTH: usize = 512;
+// Data blocks in each full stripe
+pub const DATA_BLOCKS: usize = 64;
+// Parity as a percentage of the body
+pub const MAX_OVERHEAD: u32 = 200;
+// Bytes of SHA-256 kept for each block, to find the damaged ones
+const CHECKSUM_LENGTH: usize = 8;
+
+//# Reed–Solomon parity over a piece's body, so that it can repair itself without any other piece
+//# The body is split into stripes, each made up of:
+//# data        (up to dataBlocks blocks of the body; only the last stripe may be shorter)
+//# parity      (parityBlocks blocks, computed over the data blocks, the last padded with zeros)
+//# checksums   (the first 8 bytes of the SHA-256 of each data and parity block)
+//# Blocks whose checksums don't match are treated as lost, and up to parityBlocks of them can be rebuilt.
+//# Shorter last stripes have proportionally fewer parity blocks, and are told apart by their length.
+//# Header extension:
+//# parity=blockLength:dataBlocks:parityBlocks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub block_length: usize,
+    pub data_blocks: usize,
+    pub parity_blocks: usize,
+}
+
+impl Layout {
+    // The layout adding overhead percent of parity, or None for no parity
+    pub fn for_overhead(overhead: u32) -> Result<Option<Layout>, String> {
+        if overhead == 0 {
+            return Ok(None);
+        }
+        if overhead > MAX_OVERHEAD {
+            return Err(format!("Parity overhead must be at most {}% ({}%)", MAX_OVERHEAD, overhead));
+        }
+
+        let parity_blocks = (DATA_BLOCKS * overhead as usize + 99) / 100;
+        return Ok(Some(Layout { block_length: BLOCK_LENGTH, data_blocks: DATA_BLOCKS, parity_blocks }));
+    }
+
+    pub fn add_to_header(self: &Layout, header: &mut Header) {
+        header.extensions.retain(|extension| extension.0 != PARITY_KEY);
+        header.add_extension(PARITY_KEY, format!("{}:{}:{}", self.block_length, self.data_blocks, self.parity_blocks));
+    }
+
+    pub fn from_header(header: &Header) -> Result<Option<Layout>, String> {
+        let value = match header.extension(PARITY_KEY) {
+            None => return Ok(None),
+            Some(value) => value,
+        };
+
+        let fields: Vec<Result<usize, _>> = value.split(':').map(|field| field.parse::<usize>()).collect();
+        return match fields[..] {
+            [Ok(block_length), Ok(data_blocks), Ok(parity_blocks)]
+                if block_length > 0 && block_length <= 65536 && data_blocks > 0 && parity_blocks > 0 && data_blocks + parity_blocks <= 256 => {
+                Ok(Some(Layout { block_length, data_blocks, parity_blocks }))
+            },
+            _ => Err(format!("Error parsing parity layout: {}", value)),
+        }
+    }
+
+    // Parity blocks for a stripe of data_blocks blocks
+    fn parity_blocks_for(self: &Layout, data_blocks: usize) -> usize {
+        return std::cmp::max(1, (data_blocks * self.parity_blocks + self.data_blocks - 1) / self.data_blocks);
+    }
+
+    // Bytes following the data in a stripe of data_blocks blocks
+    fn overhead_for(self: &Layout, data_blocks: usize) -> usize {
+        let parity_blocks = self.parity_blocks_for(data_blocks);
+        return parity_blocks * self.block_length + (data_blocks + parity_blocks) * CHECKSUM_LENGTH;
+    }
+
+    fn full_stripe_length(self: &Layout) -> usize {
+        return self.data_blocks * self.block_length + self.overhead_for(self.data_blocks);
+    }
+
+    // Data blocks and data length of a last stripe of length bytes, if it's a possible length
+    fn last_stripe(self: &Layout, length: usize) -> Option<(usize, usize)> {
+        return (1..=self.data_blocks).find_map(|data_blocks| {
+            let overhead = self.overhead_for(data_blocks);
+            if length <= overhead {
+                return None;
+            }
+            let data_length = length - overhead;
+            if (data_length + self.block_length - 1) / self.block_length == data_blocks { Some((data_blocks, data_length)) } else { None }
+        });
+    }
+
+    // Padded copies of the blocks of data
+    fn blocks(self: &Layout, data: &[u8]) -> Vec<Vec<u8>> {
+        return data.chunks(self.block_length).map(|block| {
+            let mut padded = block.to_vec();
+            padded.resize(self.block_length, 0);
+            padded
+        }).collect();
+    }
+
+    fn codec(self: &Layout, data_blocks: usize) -> ReedSolomon {
+        // The layout has already been checked, so this can't fail
+        return ReedSolomon::new(data_blocks, self.parity_blocks_for(data_blocks)).unwrap();
+    }
+}
+
+fn checksum(block: &[u8]) -> [u8; CHECKSUM_LENGTH] {
+    let mut result = [0; CHECKSUM_LENGTH];
+    result.copy_from_slice(&Sha256::digest(block)[0..CHECKSUM_LENGTH]);
+    return result;
+}
+
+//# Incrementally adds parity to a piece's body
+pub struct ParityEncoder {
+    layout: Layout,
+    codec: ReedSolomon,
+    pending: Vec<u8>,
+}
+
+impl ParityEncoder {
+    pub fn new(layout: Layout) -> ParityEncoder {
+        return ParityEncoder { layout, codec: layout.codec(layout.data_blocks), pending: Vec::new() };
+    }
+
+    pub fn update(self: &mut ParityEncoder, data: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(data);
+
+        let stripe_data_length = self.layout.data_blocks * self.layout.block_length;
+        let mut result: Vec<u8> = Vec::new();
+        while self.pending.len() >= stripe_data_length {
+            let data: Vec<u8> = self.pending.drain(0..stripe_data_length).collect();
+            result.extend(self.stripe(&self.codec, &data));
+        }
+        return result;
+    }
+
+    // The rest of the body, as a shorter last stripe
+    pub fn finish(self: ParityEncoder) -> Vec<u8> {
+        if self.pending.is_empty() {
+            return Vec::new();
+        }
+        let data_blocks = (self.pending.len() + self.layout.block_length - 1) / self.layout.block_length;
+        return self.stripe(&self.layout.codec(data_blocks), &self.pending);
+    }
+
+    fn stripe(self: &ParityEncoder, codec: &ReedSolomon, data: &[u8]) -> Vec<u8> {
+        let mut blocks = self.layout.blocks(data);
+        blocks.extend((0..codec.parity_shard_count()).map(|_| vec![0 as u8; self.layout.block_length]));
+        codec.encode(&mut blocks).unwrap();
+
+        let mut result = data.to_vec();
+        for block in &blocks[codec.data_shard_count()..] {
+            result.extend_from_slice(block);
+        }
+        for block in &blocks {
+            result.extend_from_slice(&checksum(block));
+        }
+        return result;
+    }
+}
+
+//# Incrementally strips the parity from a piece's body, repairing damaged blocks where it can
+//# Stripes too badly damaged to repair are passed on as they are, for later checks to catch.
+pub struct ParityDecoder {
+    layout: Layout,
+    codec: ReedSolomon,
+    pending: Vec<u8>,
+    repaired_blocks: u64,
+    unrepairable_stripes: u64,
+}
+
+impl ParityDecoder {
+    pub fn new(layout: Layout) -> ParityDecoder {
+        return ParityDecoder { layout, codec: layout.codec(layout.data_blocks), pending: Vec::new(), repaired_blocks: 0, unrepairable_stripes: 0 };
+    }
+
+    pub fn update(self: &mut ParityDecoder, data: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(data);
+
+        let stripe_length = self.layout.full_stripe_length();
+        let mut result: Vec<u8> = Vec::new();
+        while self.pending.len() >= stripe_length {
+            let stripe: Vec<u8> = self.pending.drain(0..stripe_length).collect();
+            let decoded = decode(&self.layout, &self.codec, &stripe, self.layout.data_blocks * self.layout.block_length);
+            result.extend(self.count(decoded));
+        }
+        return result;
+    }
+
+    // The rest of the body, from the shorter last stripe
+    pub fn finish(self: &mut ParityDecoder) -> Vec<u8> {
+        let stripe: Vec<u8> = self.pending.drain(..).collect();
+        if stripe.is_empty() {
+            return stripe;
+        }
+        return match self.layout.last_stripe(stripe.len()) {
+            // Cut short, so there's nothing to go on
+            None => self.count((Vec::new(), None)),
+            Some((data_blocks, data_length)) => {
+                let decoded = decode(&self.layout, &self.layout.codec(data_blocks), &stripe, data_length);
+                self.count(decoded)
+            },
+        }
+    }
+
+    // Number of damaged blocks rebuilt so far
+    pub fn repaired_blocks(self: &ParityDecoder) -> u64 {
+        return self.repaired_blocks;
+    }
+
+    // Number of stripes with more damaged blocks than parity blocks so far
+    pub fn unrepairable_stripes(self: &ParityDecoder) -> u64 {
+        return self.unrepairable_stripes;
+    }
+
+    fn count(self: &mut ParityDecoder, decoded: (Vec<u8>, Option<usize>)) -> Vec<u8> {
+        match decoded.1 {
+            None => self.unrepairable_stripes += 1,
+            Some(repaired) => self.repaired_blocks += repaired as u64,
+        }
+        return decoded.0;
+    }
+}
+
+// The data of stripe, and how many of its blocks were repaired, or None if it couldn't be
+fn decode(layout: &Layout, codec: &ReedSolomon, stripe: &[u8], data_length: usize) -> (Vec<u8>, Option<usize>) {
+    let block_count = codec.total_shard_count();
+    let checksums_offset = stripe.len() - block_count * CHECKSUM_LENGTH;
+
+    let mut blocks = layout.blocks(&stripe[0..data_length]);
+    blocks.extend(stripe[data_length..checksums_offset].chunks(layout.block_length).map(|block| block.to_vec()));
+    let mut shards: Vec<Option<Vec<u8>>> = blocks.into_iter().zip(stripe[checksums_offset..].chunks(CHECKSUM_LENGTH)).map(|(block, expected)| {
+        if checksum(&block)[..] == *expected { Some(block) } else { None }
+    }).collect();
+
+    let damaged = shards[0..codec.data_shard_count()].iter().filter(|shard| shard.is_none()).count();
+    if damaged == 0 {
+        return (stripe[0..data_length].to_vec(), Some(0));
+    }
+    if codec.reconstruct_data(&mut shards).is_err() {
+        return (stripe[0..data_length].to_vec(), None);
+    }
+
+    let mut result: Vec<u8> = Vec::with_capacity(data_length);
+    for shard in &shards[0..codec.data_shard_count()] {
+        result.extend_from_slice(shard.as_ref().unwrap());
+    }
+    result.truncate(data_length);
+    return (result, Some(damaged));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    fn encode(layout: Layout, body: &[u8]) -> Vec<u8> {
+        let mut encoder = ParityEncoder::new(layout);
+        let mut encoded: Vec<u8> = Vec::new();
+        for block in body.chunks(1000) {
+            encoded.extend(encoder.update(block));
+        }
+        encoded.extend(encoder.finish());
+        return encoded;
+    }
+
+    fn decode(layout: Layout, encoded: &[u8]) -> (Vec<u8>, u64, u64) {
+        let mut decoder = ParityDecoder::new(layout);
+        let mut decoded: Vec<u8> = Vec::new();
+        for block in encoded.chunks(777) {
+            decoded.extend(decoder.update(block));
+        }
+        decoded.extend(decoder.finish());
+        return (decoded, decoder.repaired_blocks(), decoder.unrepairable_stripes());
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(Layout::for_overhead(0).unwrap(), None);
+        assert!(Layout::for_overhead(MAX_OVERHEAD + 1).is_err());
+
+        let layout = Layout::for_overhead(10).unwrap().unwrap();
+        assert_eq!(layout.parity_blocks, 7);
+        let mut header = Header::new(1, 5717, "secret");
+        layout.add_to_header(&mut header);
+        assert_eq!(header.extension(PARITY_KEY), Some("512:64:7"));
+        assert_eq!(Layout::from_header(&header).unwrap(), Some(layout));
+
+        header.extensions.clear();
+        header.add_extension(PARITY_KEY, String::from("512:200:100"));
+        assert!(Layout::from_header(&header).is_err());
+
+        for length in 1..(DATA_BLOCKS * BLOCK_LENGTH) {
+            let data_blocks = (length + BLOCK_LENGTH - 1) / BLOCK_LENGTH;
+            assert_eq!(layout.last_stripe(length + layout.overhead_for(data_blocks)), Some((data_blocks, length)));
+        }
+    }
+
+    #[test]
+    fn test_repair() {
+        let layout = Layout::for_overhead(10).unwrap().unwrap();
+        for length in [0, 1, BLOCK_LENGTH, DATA_BLOCKS * BLOCK_LENGTH, DATA_BLOCKS * BLOCK_LENGTH * 2 + 1234] {
+            let body: Vec<u8> = (0..length).map(|_| random::<u8>()).collect();
+            let encoded = encode(layout, &body);
+            assert_eq!(decode(layout, &encoded), (body.clone(), 0, 0));
+        }
+
+        // Scattered bad sectors, including in the parity and checksums
+        let body: Vec<u8> = (0..(DATA_BLOCKS * BLOCK_LENGTH * 2 + 1234)).map(|_| random::<u8>()).collect();
+        let mut encoded = encode(layout, &body);
+        for offset in [0, 5120, 20480, 33000, layout.full_stripe_length() - 1, layout.full_stripe_length() + 10, encoded.len() - 1] {
+            let end = std::cmp::min(offset + BLOCK_LENGTH, encoded.len());
+            for byte in &mut encoded[offset..end] {
+                *byte = 0;
+            }
+        }
+        let (decoded, repaired, unrepairable) = decode(layout, &encoded);
+        assert_eq!(decoded, body);
+        assert!(repaired > 0);
+        assert_eq!(unrepairable, 0);
+
+        // More damage than parity is passed on as it is
+        let mut encoded = encode(layout, &body);
+        for byte in &mut encoded[0..(BLOCK_LENGTH * 8)] {
+            *byte ^= 1;
+        }
+        let (decoded, _, unrepairable) = decode(layout, &encoded);
+        assert_eq!(decoded.len(), body.len());
+        assert_eq!(&decoded[(BLOCK_LENGTH * 8)..], &body[(BLOCK_LENGTH * 8)..]);
+        assert_eq!(unrepairable, 1);
+    }
+}
@@ -0,0 +1,337 @@
+extern crate argon2;
+extern crate base64;
+extern crate chacha20poly1305;
+
+use std::convert::TryInto;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::prelude::*;
+use zeroize::Zeroize;
+
+use crate::secret::{SecretBuffer, SecretString};
+
+//# Pieces locked with a passphrase, so that a holder can keep theirs somewhere it might be lost, such as a USB stick
+//# Format:
+//# magic       "russs-passphrase\n"
+//# version     (u8, 1)
+//# salt        (16 random bytes)
+//# memoryCost  (u32, big-endian, KiB)
+//# timeCost    (u32, big-endian)
+//# parallelism (u32, big-endian)
+//# then the piece in chunks of CHUNK_LENGTH bytes, the last one shorter or empty, each sealed with ChaCha20-Poly1305
+//# under the key Argon2id derives from the passphrase with the parameters above. The header is the associated data
+//# of every chunk, and the nonce is the chunk's sequence number followed by a byte that is 1 for the last chunk only,
+//# so chunks can't be reordered, dropped or cut off without it showing.
+//# Text shares are locked the same way, and written as TEXT_PREFIX followed by the locked bytes in URL-safe base64.
+
+pub const MAGIC: &[u8] = b"russs-passphrase\n";
+pub const TEXT_PREFIX: &str = "russs-locked:";
+const VERSION: u8 = 1;
+const SALT_LENGTH: usize = 16;
+const HEADER_LENGTH: usize = 17 + 1 + SALT_LENGTH + 12;
+const KEY_LENGTH: usize = 32;
+const TAG_LENGTH: usize = 16;
+pub const CHUNK_LENGTH: usize = 65536;
+// The costs recommended by OWASP for Argon2id
+const MEMORY_COST: u32 = 19456;
+const TIME_COST: u32 = 2;
+const PARALLELISM: u32 = 1;
+// Beyond these a damaged or malicious header could make us allocate more memory than anyone has, or spend hours
+// deriving the key
+const MAX_MEMORY_COST: u32 = 1 << 21;
+const MAX_TIME_COST: u32 = 16;
+const MAX_PARALLELISM: u32 = 16;
+
+//# Whether data, the start of a piece, is locked with a passphrase
+pub fn is_locked(data: &[u8]) -> bool {
+    return data.starts_with(MAGIC);
+}
+
+//# Whether text is a locked text share
+pub fn is_locked_text(text: &str) -> bool {
+    return text.trim_start().starts_with(TEXT_PREFIX);
+}
+
+fn derive_cipher(passphrase: &SecretString, header: &[u8]) -> Result<ChaCha20Poly1305, String> {
+    let value = |offset: usize| u32::from_be_bytes(header[offset..(offset + 4)].try_into().unwrap());
+    let salt_offset = MAGIC.len() + 1;
+    let (memory_cost, time_cost, parallelism) = (value(salt_offset + SALT_LENGTH), value(salt_offset + SALT_LENGTH + 4), value(salt_offset + SALT_LENGTH + 8));
+    if memory_cost > MAX_MEMORY_COST {
+        return Err(format!("Locked piece asks for too much memory ({} KiB)", memory_cost));
+    }
+    if time_cost > MAX_TIME_COST {
+        return Err(format!("Locked piece asks for too many passes ({})", time_cost));
+    }
+    if parallelism > MAX_PARALLELISM {
+        return Err(format!("Locked piece asks for too many lanes ({})", parallelism));
+    }
+    let params = match Params::new(memory_cost, time_cost, parallelism, Some(KEY_LENGTH)) {
+        Err(error) => return Err(format!("Unsupported passphrase parameters: {}", error)),
+        Ok(params) => params,
+    };
+
+    let mut key = SecretBuffer::zeroed(KEY_LENGTH);
+    if let Err(error) = Argon2::new(Algorithm::Argon2id, Version::V0x13, params).hash_password_into(passphrase.as_bytes(), &header[salt_offset..(salt_offset + SALT_LENGTH)], &mut key) {
+        return Err(format!("Error deriving key from passphrase: {}", error));
+    }
+    return Ok(ChaCha20Poly1305::new(Key::from_slice(&key)));
+}
+
+fn nonce(sequence: u64, last: bool) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[3..11].copy_from_slice(&sequence.to_be_bytes());
+    nonce[11] = last as u8;
+    return nonce;
+}
+
+//# Locks everything written to it with passphrase into sink
+//# finish must be called to write the last chunk.
+pub struct LockWriter<W> {
+    sink: W,
+    cipher: ChaCha20Poly1305,
+    header: Vec<u8>,
+    pending: SecretBuffer,
+    sequence: u64,
+}
+
+impl<W> LockWriter<W>
+    where W: Write {
+    pub fn new(mut sink: W, passphrase: &SecretString) -> Result<LockWriter<W>, String> {
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+        let mut salt = [0; SALT_LENGTH];
+        thread_rng().fill(&mut salt);
+        header.extend_from_slice(&salt);
+        for value in &[MEMORY_COST, TIME_COST, PARALLELISM] {
+            header.extend_from_slice(&value.to_be_bytes());
+        }
+
+        let cipher = derive_cipher(passphrase, &header)?;
+        if let Err(error) = sink.write_all(&header) {
+            return Err(format!("Error writing locked piece: {}", error));
+        }
+        return Ok(LockWriter { sink, cipher, header, pending: SecretBuffer::with_capacity(CHUNK_LENGTH), sequence: 0 });
+    }
+
+    fn seal(self: &mut LockWriter<W>, length: usize, last: bool) -> std::io::Result<()> {
+        let payload = Payload { msg: &self.pending[0..length], aad: &self.header };
+        let sealed = self.cipher.encrypt(Nonce::from_slice(&nonce(self.sequence, last)), payload).map_err(|_| std::io::Error::new(ErrorKind::Other, "Error locking piece"))?;
+        self.sink.write_all(&sealed)?;
+        self.sequence += 1;
+
+        let rest = SecretBuffer::from_slice(&self.pending[length..]);
+        self.pending = rest;
+        return Ok(());
+    }
+
+    pub fn finish(mut self: LockWriter<W>) -> Result<W, String> {
+        let length = self.pending.len();
+        if let Err(error) = self.seal(length, true) {
+            return Err(format!("Error writing locked piece: {}", error));
+        }
+        return Ok(self.sink);
+    }
+}
+
+impl<W> Write for LockWriter<W>
+    where W: Write {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.pending.extend_from_slice(data);
+        // Only seal a full chunk once there's more to come, so that the last chunk is never missing
+        while self.pending.len() > CHUNK_LENGTH {
+            self.seal(CHUNK_LENGTH, false)?;
+        }
+        return Ok(data.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return self.sink.flush();
+    }
+}
+
+//# Reads the piece locked in source, checking each chunk as it's read
+//# Seeking only reads the chunks needed, so armored pieces can be told apart without unlocking the whole piece.
+pub struct UnlockReader<R> {
+    source: R,
+    cipher: ChaCha20Poly1305,
+    header: Vec<u8>,
+    chunk_count: u64,
+    length: u64,
+    position: u64,
+    // The chunk last unlocked, and its sequence number
+    chunk: Option<(u64, SecretBuffer)>,
+}
+
+impl<R> UnlockReader<R>
+    where R: Read + Seek {
+    // Fails if passphrase isn't the one the piece was locked with
+    pub fn new(mut source: R, passphrase: &SecretString) -> Result<UnlockReader<R>, String> {
+        let mut header = vec![0; HEADER_LENGTH];
+        let total = source.seek(SeekFrom::End(0)).and_then(|total| source.seek(SeekFrom::Start(0)).map(|_| total));
+        let total = match total.and_then(|total| source.read_exact(&mut header).map(|_| total)) {
+            Err(error) => return Err(format!("Error reading locked piece: {}", error)),
+            Ok(total) => total,
+        };
+        if !is_locked(&header) {
+            return Err(String::from("Piece isn't locked with a passphrase"));
+        }
+        if header[MAGIC.len()] != VERSION {
+            return Err(format!("Unsupported locked piece version: {}", header[MAGIC.len()]));
+        }
+
+        let body = total - HEADER_LENGTH as u64;
+        let sealed_length = (CHUNK_LENGTH + TAG_LENGTH) as u64;
+        let chunk_count = std::cmp::max(1, (body + sealed_length - 1) / sealed_length);
+        if body < chunk_count * TAG_LENGTH as u64 {
+            return Err(String::from("Locked piece is truncated"));
+        }
+        let cipher = derive_cipher(passphrase, &header)?;
+        let mut reader = UnlockReader { source, cipher, header, chunk_count, length: body - chunk_count * TAG_LENGTH as u64, position: 0, chunk: None };
+        if let Err(error) = reader.unlock(0) {
+            return Err(error.to_string());
+        }
+        return Ok(reader);
+    }
+
+    fn unlock(self: &mut UnlockReader<R>, sequence: u64) -> std::io::Result<()> {
+        if self.chunk.as_ref().map(|chunk| chunk.0) == Some(sequence) {
+            return Ok(());
+        }
+        let sealed_length = (CHUNK_LENGTH + TAG_LENGTH) as u64;
+        let last = sequence + 1 == self.chunk_count;
+        let length = if last { self.length + self.chunk_count * TAG_LENGTH as u64 - sequence * sealed_length } else { sealed_length };
+        let mut sealed = vec![0; length as usize];
+        self.source.seek(SeekFrom::Start(HEADER_LENGTH as u64 + sequence * sealed_length))?;
+        self.source.read_exact(&mut sealed)?;
+
+        let payload = Payload { msg: &sealed, aad: &self.header };
+        let mut chunk = match self.cipher.decrypt(Nonce::from_slice(&nonce(sequence, last)), payload) {
+            Err(_) => return Err(std::io::Error::new(ErrorKind::InvalidData, "Wrong passphrase, or the locked piece is damaged")),
+            Ok(chunk) => chunk,
+        };
+        self.chunk = Some((sequence, SecretBuffer::from_slice(&chunk)));
+        chunk.zeroize();
+        return Ok(());
+    }
+
+    pub fn len(self: &UnlockReader<R>) -> u64 {
+        return self.length;
+    }
+}
+
+impl<R> Read for UnlockReader<R>
+    where R: Read + Seek {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        if self.position >= self.length || buffer.is_empty() {
+            return Ok(0);
+        }
+        let sequence = self.position / CHUNK_LENGTH as u64;
+        self.unlock(sequence)?;
+        let chunk = &self.chunk.as_ref().unwrap().1;
+        let offset = (self.position - sequence * CHUNK_LENGTH as u64) as usize;
+        let length = std::cmp::min(buffer.len(), chunk.len() - offset);
+        buffer[0..length].copy_from_slice(&chunk[offset..(offset + length)]);
+        self.position += length as u64;
+        return Ok(length);
+    }
+}
+
+impl<R> Seek for UnlockReader<R>
+    where R: Read + Seek {
+    fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => (self.length as i64).checked_add(offset).filter(|target| *target >= 0).map(|target| target as u64),
+            SeekFrom::Current(offset) => (self.position as i64).checked_add(offset).filter(|target| *target >= 0).map(|target| target as u64),
+        };
+        return match target {
+            None => Err(std::io::Error::new(ErrorKind::InvalidInput, "Invalid seek to a negative position")),
+            Some(target) => {
+                self.position = target;
+                Ok(target)
+            },
+        }
+    }
+}
+
+//# Lock a text share with passphrase
+pub fn lock_text(text: &str, passphrase: &SecretString) -> Result<String, String> {
+    let mut writer = LockWriter::new(Vec::new(), passphrase)?;
+    if let Err(error) = writer.write_all(text.as_bytes()) {
+        return Err(format!("Error locking text share: {}", error));
+    }
+    return Ok(format!("{}{}", TEXT_PREFIX, base64::encode_config(&writer.finish()?, base64::URL_SAFE_NO_PAD)));
+}
+
+//# Unlock a text share locked by lock_text
+pub fn unlock_text(text: &str, passphrase: &SecretString) -> Result<SecretString, String> {
+    let my_text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !my_text.starts_with(TEXT_PREFIX) {
+        return Err(String::from("Text share isn't locked with a passphrase"));
+    }
+    let data = match base64::decode_config(&my_text[TEXT_PREFIX.len()..], base64::URL_SAFE_NO_PAD) {
+        Err(_) => return Err(String::from("Malformed locked text share")),
+        Ok(data) => data,
+    };
+
+    let mut reader = UnlockReader::new(std::io::Cursor::new(data), passphrase)?;
+    let mut result = SecretBuffer::zeroed(reader.len() as usize);
+    if let Err(error) = reader.read_exact(&mut result) {
+        return Err(error.to_string());
+    }
+    return SecretString::from_buffer(result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_roundtrip_locked() {
+        let passphrase = SecretString::from("correct horse battery staple");
+        // Just over two chunks, so that seeking has to find the right one
+        let piece: Vec<u8> = (0..(CHUNK_LENGTH * 2 + 3)).map(|index| (index % 251) as u8).collect();
+        let mut writer = LockWriter::new(Vec::new(), &passphrase).unwrap();
+        writer.write_all(&piece).unwrap();
+        let locked = writer.finish().unwrap();
+        assert!(is_locked(&locked));
+        assert_eq!(locked.len(), HEADER_LENGTH + piece.len() + 3 * TAG_LENGTH);
+
+        let mut reader = UnlockReader::new(Cursor::new(&locked), &passphrase).unwrap();
+        assert_eq!(reader.len(), piece.len() as u64);
+        let mut unlocked: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut unlocked).unwrap();
+        assert_eq!(unlocked, piece);
+        reader.seek(SeekFrom::Start(CHUNK_LENGTH as u64 - 1)).unwrap();
+        let mut bytes = [0; 2];
+        reader.read_exact(&mut bytes).unwrap();
+        assert_eq!(&bytes, &piece[(CHUNK_LENGTH - 1)..(CHUNK_LENGTH + 1)]);
+
+        assert!(UnlockReader::new(Cursor::new(&locked), &SecretString::from("wrong")).is_err());
+        // Dropping the last chunk leaves a full chunk that isn't marked as the last
+        let truncated = &locked[..(HEADER_LENGTH + 2 * (CHUNK_LENGTH + TAG_LENGTH))];
+        let mut reader = UnlockReader::new(Cursor::new(truncated), &passphrase).unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+
+        // Costs beyond the limits are refused before any key is derived
+        let cost_offset = MAGIC.len() + 1 + SALT_LENGTH;
+        for (offset, cost) in [(0, MAX_MEMORY_COST + 1), (4, MAX_TIME_COST + 1), (8, MAX_PARALLELISM + 1), (4, u32::MAX)].iter() {
+            let mut costly = locked.clone();
+            costly[(cost_offset + offset)..(cost_offset + offset + 4)].copy_from_slice(&cost.to_be_bytes());
+            assert!(UnlockReader::new(Cursor::new(&costly), &passphrase).err().unwrap().starts_with("Locked piece asks for too"));
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_locked_text() {
+        let passphrase = SecretString::from("hunter2");
+        let locked = lock_text("russs:1:share", &passphrase).unwrap();
+        assert!(is_locked_text(&locked));
+        assert_eq!(unlock_text(&format!("{}\n", locked), &passphrase).unwrap().as_str(), "russs:1:share");
+        assert!(unlock_text(&locked, &SecretString::from("hunter3")).is_err());
+    }
+}
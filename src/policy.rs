@@ -0,0 +1,417 @@
+extern crate serde;
+extern crate serde_json;
+extern crate sha2;
+extern crate toml;
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::output::OutputFile;
+use crate::progress::{Control, Progress};
+use crate::sss;
+use crate::sss::GenerateOptions;
+
+//# Split policies, describing who holds each piece of a secret, and the manifests recording where the pieces went
+//# A policy is a TOML file, or JSON if its name ends in .json, such as:
+//#
+//#     threshold = 2
+//#     scheme = "russs"    # or "hiss" or "gfshare"; russs if left out
+//#     prime = 7919        # DEFAULT_PRIME if left out
+//#     manifest = "vault.manifest.json"
+//#
+//#     [[holders]]
+//#     name = "Alice"
+//#     contact = "alice@example.com"
+//#     public_key = "age1..."    # to encrypt the piece to its holder; every holder or none
+//#     output = "/media/alice"   # directory to write the piece to; next to the secret if left out
+//#
+//# There is a piece for each holder, labelled with their name, except that gfshare pieces are named by their share
+//# numbers, as gfcombine needs. Relative paths are relative to the policy file.
+//# The manifest is JSON listing each piece's holder and SHA-256, and nothing about the secret but its name.
+
+pub const DEFAULT_PRIME: i32 = 7919;
+pub const POLICY_NAME_TEMPLATE: &str = "{stem}-{index}-{label}.shard";
+pub const POLICY_ARMORED_NAME_TEMPLATE: &str = "{stem}-{index}-{label}.shard.txt";
+const MANIFEST_EXTENSION: &str = "manifest.json";
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    Russs,
+    Hiss,
+    Gfshare,
+}
+
+impl Default for Scheme {
+    fn default() -> Scheme {
+        return Scheme::Russs;
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Holder {
+    pub name: String,
+    #[serde(default)]
+    pub contact: Option<String>,
+    // age recipient to encrypt the holder's piece to; see recipient
+    #[serde(default)]
+    pub public_key: Option<String>,
+    // Directory to write the holder's piece to
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    pub threshold: i32,
+    #[serde(default)]
+    pub scheme: Scheme,
+    #[serde(default)]
+    pub prime: Option<i32>,
+    // Where to write the manifest; next to the secret, named after it, if None
+    #[serde(default)]
+    pub manifest: Option<PathBuf>,
+    pub holders: Vec<Holder>,
+}
+
+impl Policy {
+    //# Read the policy in the file at path
+    pub fn read<P>(path: P) -> Result<Policy, String>
+        where P: AsRef<Path> {
+        let my_path = path.as_ref();
+        let text = match std::fs::read_to_string(my_path) {
+            Err(error) => return Err(format!("Error reading policy {}: {}", my_path.display(), error)),
+            Ok(text) => text,
+        };
+        let is_json = my_path.extension().map_or(false, |extension| extension == "json");
+        let mut policy = match Policy::parse(&text, is_json) {
+            Err(message) => return Err(format!("Error reading policy {}: {}", my_path.display(), message)),
+            Ok(policy) => policy,
+        };
+
+        let directory = my_path.parent().unwrap_or(Path::new(""));
+        policy.manifest = policy.manifest.map(|manifest| directory.join(manifest));
+        for holder in &mut policy.holders {
+            holder.output = holder.output.as_ref().map(|output| directory.join(output));
+        }
+        return Ok(policy);
+    }
+
+    //# Parse a policy from TOML, or JSON if is_json
+    pub fn parse(text: &str, is_json: bool) -> Result<Policy, String> {
+        let policy: Policy = match is_json {
+            true => serde_json::from_str(text).map_err(|error| error.to_string())?,
+            false => toml::from_str(text).map_err(|error| error.to_string())?,
+        };
+        policy.validate()?;
+        return Ok(policy);
+    }
+
+    fn validate(self: &Policy) -> Result<(), String> {
+        if self.holders.is_empty() {
+            return Err(String::from("Policy has no holders"));
+        }
+        if self.threshold < 1 || self.threshold > self.holders.len() as i32 {
+            return Err(format!("Policy threshold {} isn't between 1 and its {} holders", self.threshold, self.holders.len()));
+        }
+        if let Some(holder) = self.holders.iter().find(|holder| holder.name.trim().is_empty()) {
+            return Err(format!("Policy holder {} has no name", holder.contact.as_ref().map(|contact| contact.as_str()).unwrap_or("")));
+        }
+        let names: Vec<&str> = self.holders.iter().map(|holder| holder.name.as_str()).collect();
+        if (1..names.len()).any(|index| names[index..].contains(&names[index - 1])) {
+            return Err(String::from("Policy names the same holder more than once"));
+        }
+        if self.holders.iter().any(|holder| holder.public_key.is_some() != self.holders[0].public_key.is_some()) {
+            return Err(String::from("Policy gives public keys for some holders but not others"));
+        }
+        if self.scheme != Scheme::Russs && self.holders[0].public_key.is_some() {
+            return Err(String::from("Policy gives public keys, but only russs pieces can be encrypted to their holders"));
+        }
+        return Ok(());
+    }
+
+    pub fn prime(self: &Policy) -> i32 {
+        return self.prime.unwrap_or(DEFAULT_PRIME);
+    }
+
+    //# Options for splitting secret_path by the policy, on top of options
+    pub fn generate_options(self: &Policy, secret_path: &Path, options: &GenerateOptions) -> GenerateOptions {
+        let default_directory = secret_path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let output_directories = match self.holders.iter().any(|holder| holder.output.is_some()) {
+            false => options.output_directories.clone(),
+            true => self.holders.iter().map(|holder| holder.output.clone().unwrap_or(default_directory.clone())).collect(),
+        };
+        let template = if options.armor { POLICY_ARMORED_NAME_TEMPLATE } else { POLICY_NAME_TEMPLATE };
+        let gfshare = self.scheme == Scheme::Gfshare;
+
+        return GenerateOptions {
+            output_directories,
+            name_template: if gfshare { None } else { Some(options.name_template.clone().unwrap_or(String::from(template))) },
+            labels: if gfshare { Vec::new() } else { self.holders.iter().map(|holder| holder.name.clone()).collect() },
+            recipients: self.holders.iter().filter_map(|holder| holder.public_key.clone()).collect(),
+            gfshare,
+            hiss: self.scheme == Scheme::Hiss,
+            set_id: Some(options.set_id.clone().unwrap_or_else(sss::generate_set_id)),
+            ..options.clone()
+        };
+    }
+
+    fn manifest_path(self: &Policy, secret_path: &Path) -> Result<PathBuf, String> {
+        if let Some(ref manifest) = self.manifest {
+            return Ok(manifest.clone());
+        }
+        return match secret_path.file_stem() {
+            None => Err(format!("Error parsing file name: {}", secret_path.display())),
+            Some(stem) => Ok(secret_path.with_file_name(format!("{}.{}", stem.to_string_lossy(), MANIFEST_EXTENSION))),
+        }
+    }
+}
+
+//# What became of each piece of a secret split by a policy, for the records of whoever keeps track of them
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Manifest {
+    pub set_id: String,
+    // When the secret was split, as UTC in RFC 3339
+    pub date: String,
+    // File name of the secret
+    pub secret: String,
+    pub scheme: Scheme,
+    pub threshold: i32,
+    pub prime: i32,
+    pub shards: Vec<ManifestShard>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ManifestShard {
+    pub index: usize,
+    pub holder: String,
+    pub contact: Option<String>,
+    pub public_key: Option<String>,
+    pub file: PathBuf,
+    // SHA-256 of the piece file as written, in hex
+    pub sha256: String,
+}
+
+impl Manifest {
+    pub fn read<P>(path: P) -> Result<Manifest, String>
+        where P: AsRef<Path> {
+        let my_path = path.as_ref();
+        let result = std::fs::read_to_string(my_path).map_err(|error| error.to_string()).and_then(|text| {
+            serde_json::from_str(&text).map_err(|error| error.to_string())
+        });
+        return result.map_err(|message| format!("Error reading manifest {}: {}", my_path.display(), message));
+    }
+}
+
+//# Split the file or directory at secret_path into a piece for each holder in policy, and write its manifest
+//# Returns the paths of the pieces and of the manifest. The manifest only appears once the pieces are all in
+//# place, and the pieces are removed again if it can't be written.
+pub fn split<P, TProgress, TControl>(secret_path: P, policy: &Policy, options: &GenerateOptions, progress_callback: TProgress) -> Result<(Vec<PathBuf>, PathBuf), String>
+    where P: AsRef<Path>,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    let my_secret_path = secret_path.as_ref();
+    let secret_name = match my_secret_path.file_name() {
+        None => return Err(format!("Error parsing file name: {}", my_secret_path.display())),
+        Some(name) => name.to_string_lossy().into_owned(),
+    };
+    let manifest_file = OutputFile::create(policy.manifest_path(my_secret_path)?, options.overwrite)?;
+    let policy_options = policy.generate_options(my_secret_path, options);
+    let pieces_count = policy.holders.len() as i32;
+    let pieces = match my_secret_path.is_dir() {
+        true => sss::generate_archive_with_options(&[my_secret_path], my_secret_path, pieces_count, policy.threshold, policy.prime(), &policy_options, progress_callback)?,
+        false => sss::generate_file_with_options(my_secret_path, pieces_count, policy.threshold, policy.prime(), &policy_options, progress_callback)?,
+    };
+
+    let result = write_manifest(manifest_file, policy, &pieces, &secret_name, policy_options.set_id.as_ref().unwrap());
+    if result.is_err() {
+        for piece in &pieces {
+            let _ = std::fs::remove_file(piece);
+        }
+    }
+    return Ok((pieces, result?));
+}
+
+fn write_manifest(mut file: OutputFile, policy: &Policy, pieces: &[PathBuf], secret_name: &str, set_id: &str) -> Result<PathBuf, String> {
+    let mut shards: Vec<ManifestShard> = Vec::new();
+    for (index, (piece, holder)) in pieces.iter().zip(policy.holders.iter()).enumerate() {
+        shards.push(ManifestShard {
+            index: index + 1,
+            holder: holder.name.clone(),
+            contact: holder.contact.clone(),
+            public_key: holder.public_key.clone(),
+            file: piece.clone(),
+            sha256: hash_file(piece)?,
+        });
+    }
+    let manifest = Manifest {
+        set_id: String::from(set_id),
+        date: format_date(SystemTime::now()),
+        secret: String::from(secret_name),
+        scheme: policy.scheme,
+        threshold: policy.threshold,
+        prime: policy.prime(),
+        shards,
+    };
+
+    let mut text = match serde_json::to_string_pretty(&manifest) {
+        Err(error) => return Err(format!("Error writing manifest: {}", error)),
+        Ok(text) => text,
+    };
+    text.push('\n');
+    if let Err(error) = file.write_all(text.as_bytes()) {
+        return Err(format!("Error writing {}: {}", file.path().display(), error));
+    }
+    return file.commit();
+}
+
+fn hash_file(path: &Path) -> Result<String, String> {
+    let mut digest = Sha256::new();
+    let result = File::open(path).and_then(|mut file| std::io::copy(&mut file, &mut digest));
+    if let Err(error) = result {
+        return Err(format!("Error reading {}: {}", path.display(), error));
+    }
+    return Ok(digest.finalize().iter().map(|byte| format!("{:02x}", byte)).collect());
+}
+
+// RFC 3339 in UTC, to the second
+fn format_date(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+    let (days, second_of_day) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // Civil date from days since the epoch, in 400-year eras starting on the 1st of March
+    let shifted = days + 719468;
+    let era = shifted.div_euclid(146097);
+    let day_of_era = shifted.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    return format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, second_of_day / 3600, second_of_day / 60 % 60, second_of_day % 60);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_policy() {
+        let toml = "threshold = 2\nscheme = \"hiss\"\n\n[[holders]]\nname = \"Alice\"\ncontact = \"alice@example.com\"\n\n[[holders]]\nname = \"Bob\"\noutput = \"/media/bob\"\n";
+        let policy = Policy::parse(toml, false).unwrap();
+        assert_eq!((policy.threshold, policy.scheme, policy.prime()), (2, Scheme::Hiss, DEFAULT_PRIME));
+        assert_eq!(policy.holders[0], Holder { name: String::from("Alice"), contact: Some(String::from("alice@example.com")), ..Holder::default() });
+
+        let json = r#"{"threshold": 2, "prime": 5717, "holders": [{"name": "Alice"}, {"name": "Bob", "output": "/media/bob"}]}"#;
+        let policy = Policy::parse(json, true).unwrap();
+        assert_eq!((policy.scheme, policy.prime()), (Scheme::Russs, 5717));
+        let options = policy.generate_options(Path::new("/secrets/vault.key"), &GenerateOptions::default());
+        assert_eq!(options.output_directories, vec![PathBuf::from("/secrets"), PathBuf::from("/media/bob")]);
+        assert_eq!(options.labels, vec!["Alice", "Bob"]);
+        assert!(options.set_id.is_some() && options.recipients.is_empty());
+
+        assert!(Policy::parse("threshold = 3\n[[holders]]\nname = \"Alice\"\n[[holders]]\nname = \"Bob\"\n", false).is_err());
+        assert!(Policy::parse("threshold = 1\n[[holders]]\nname = \"Alice\"\n[[holders]]\nname = \"Alice\"\n", false).is_err());
+        assert!(Policy::parse("threshold = 1\n[[holders]]\nname = \"Alice\"\npublic_key = \"age1\"\n[[holders]]\nname = \"Bob\"\n", false).is_err());
+        assert!(Policy::parse("threshold = 1\nshares = 2\n[[holders]]\nname = \"Alice\"\n", false).is_err());
+        assert!(Policy::parse("threshold = 1\nscheme = \"hiss\"\n[[holders]]\nname = \"Alice\"\npublic_key = \"age1\"\n", false).is_err());
+    }
+
+    #[test]
+    fn test_split_by_policy() {
+        let source = Path::new(file!()).parent().unwrap().parent().unwrap().join("tests").join("data").join("testInput");
+        let directory = std::env::temp_dir().join(format!("russs-test-{:016x}", rand::random::<u64>()));
+        std::fs::create_dir_all(directory.join("bob")).unwrap();
+        std::fs::copy(&source, directory.join("testInput")).unwrap();
+        let policy_path = directory.join("policy.toml");
+        std::fs::write(&policy_path, "threshold = 2\n[[holders]]\nname = \"Alice\"\ncontact = \"alice@example.com\"\n[[holders]]\nname = \"Bob\"\noutput = \"bob\"\n[[holders]]\nname = \"Carol\"\n").unwrap();
+
+        let policy = Policy::read(&policy_path).unwrap();
+        let (pieces, manifest_path) = split(directory.join("testInput"), &policy, &GenerateOptions::default(), |_|{}).unwrap();
+        assert_eq!(pieces, vec![directory.join("testInput-1-Alice.shard"), directory.join("bob").join("testInput-2-Bob.shard"), directory.join("testInput-3-Carol.shard")]);
+        assert_eq!(manifest_path, directory.join("testInput.manifest.json"));
+
+        let manifest = Manifest::read(&manifest_path).unwrap();
+        assert_eq!((manifest.secret.as_str(), manifest.threshold, manifest.prime), ("testInput", 2, DEFAULT_PRIME));
+        assert_eq!(manifest.shards.iter().map(|shard| (shard.index, shard.holder.as_str(), shard.file.clone())).collect::<Vec<_>>(),
+                   vec![(1, "Alice", pieces[0].clone()), (2, "Bob", pieces[1].clone()), (3, "Carol", pieces[2].clone())]);
+        assert_eq!(manifest.shards[0].contact.as_deref(), Some("alice@example.com"));
+        assert_eq!(manifest.shards[1].sha256, hash_file(&pieces[1]).unwrap());
+        let header = crate::header::Header::read(&mut File::open(&pieces[0]).unwrap()).unwrap();
+        assert_eq!(header.extension(sss::SET_ID_KEY), Some(manifest.set_id.as_str()));
+
+        // Nothing about the secret but its name goes in the manifest, and it isn't replaced by another split
+        let secret = std::fs::read(&source).unwrap();
+        let text = std::fs::read(&manifest_path).unwrap();
+        assert!(!text.windows(16).any(|window| secret.windows(16).any(|other| other == window)));
+        assert!(split(directory.join("testInput"), &policy, &GenerateOptions::default(), |_|{}).is_err());
+
+        std::fs::remove_file(directory.join("testInput")).unwrap();
+        let output = sss::interpolate_file(&pieces[1..3], &directory, |_|{}).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), secret);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    // Split a copy of testInput by a policy for Alice, Bob and Carol with scheme, returning the pieces and where the copy was
+    fn split_by_scheme(scheme: &str) -> (Vec<PathBuf>, PathBuf) {
+        let source = Path::new(file!()).parent().unwrap().parent().unwrap().join("tests").join("data").join("testInput");
+        let directory = std::env::temp_dir().join(format!("russs-test-{:016x}", rand::random::<u64>()));
+        std::fs::create_dir(&directory).unwrap();
+        std::fs::copy(&source, directory.join("testInput")).unwrap();
+        let text = format!("threshold = 2\nscheme = \"{}\"\n[[holders]]\nname = \"Alice\"\n[[holders]]\nname = \"Bob\"\n[[holders]]\nname = \"Carol\"\n", scheme);
+        let policy = Policy::parse(&text, false).unwrap();
+
+        let (pieces, manifest_path) = split(directory.join("testInput"), &policy, &GenerateOptions::default(), |_|{}).unwrap();
+        let manifest = Manifest::read(&manifest_path).unwrap();
+        assert_eq!(manifest.scheme, policy.scheme);
+        assert_eq!(manifest.shards.iter().map(|shard| (shard.holder.as_str(), shard.file.clone())).collect::<Vec<_>>(),
+                   vec![("Alice", pieces[0].clone()), ("Bob", pieces[1].clone()), ("Carol", pieces[2].clone())]);
+        return (pieces, directory.join("testInput"));
+    }
+
+    #[test]
+    fn test_split_by_hiss_policy() {
+        let (pieces, input) = split_by_scheme("hiss");
+        let directory = input.parent().unwrap().to_path_buf();
+        assert_eq!(pieces[1], directory.join("testInput-2-Bob.shard"));
+        let secret = std::fs::read(&input).unwrap();
+
+        std::fs::remove_file(&input).unwrap();
+        let output = sss::interpolate_file(&pieces[0..2], &directory, |_|{}).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), secret);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_split_by_gfshare_policy() {
+        let (pieces, input) = split_by_scheme("gfshare");
+        let directory = input.parent().unwrap().to_path_buf();
+        assert!(pieces.iter().all(|piece| piece.file_stem() == input.file_name()));
+        let secret = std::fs::read(&input).unwrap();
+
+        std::fs::remove_file(&input).unwrap();
+        let output = sss::interpolate_file(&pieces[1..3], &directory, |_|{}).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), secret);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(format_date(UNIX_EPOCH + Duration::from_secs(951782400 + 3661)), "2000-02-29T01:01:01Z");
+        assert_eq!(format_date(UNIX_EPOCH + Duration::from_secs(1792368000)), "2026-10-19T00:00:00Z");
+    }
+}
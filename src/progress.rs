@@ -0,0 +1,84 @@
+//# Structured progress reporting for long-running operations
+//# Callbacks receive a Progress event and may return Control::Cancel to stop the operation,
+//# or nothing at all to let it continue
+
+pub const CANCELLED: &str = "Operation cancelled";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    WritingHeaders,
+    Splitting,
+    ReadingHeaders,
+    Combining,
+    // Counting bytes of the piece rather than of the secret
+    Repairing,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Progress {
+    pub phase: Phase,
+    // Bytes of the secret processed so far
+    pub bytes_done: u64,
+    // Total bytes of the secret, when known
+    pub total_bytes: Option<u64>,
+    // Index into the pieces of the piece currently being processed, if any
+    pub shard: Option<usize>,
+}
+
+impl Progress {
+    pub fn new(phase: Phase, bytes_done: u64, total_bytes: Option<u64>, shard: Option<usize>) -> Progress {
+        return Progress { phase, bytes_done, total_bytes, shard };
+    }
+
+    // Fraction of the operation completed, or 0 if the total is unknown
+    pub fn fraction(self: &Progress) -> f64 {
+        return match self.total_bytes {
+            Some(total) if total > 0 => (self.bytes_done as f64 / total as f64).min(1.0),
+            _ => 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Control {
+    Continue,
+    Cancel,
+}
+
+impl From<()> for Control {
+    fn from(_: ()) -> Control {
+        return Control::Continue;
+    }
+}
+
+// Deliver progress to callback, turning a cancellation into an error
+pub fn report<TProgress, TControl>(progress_callback: &mut TProgress, progress: Progress) -> Result<(), String>
+    where TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    return match progress_callback(&progress).into() {
+        Control::Continue => Ok(()),
+        Control::Cancel => Err(String::from(CANCELLED)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut events = 0;
+        assert!(report(&mut |_: &Progress| events += 1, Progress::new(Phase::Splitting, 1, None, None)).is_ok());
+        assert_eq!(events, 1);
+
+        let result = report(&mut |_: &Progress| Control::Cancel, Progress::new(Phase::Splitting, 1, None, None));
+        assert_eq!(result, Err(String::from(CANCELLED)));
+    }
+
+    #[test]
+    fn test_fraction() {
+        assert_eq!(Progress::new(Phase::Combining, 5, Some(10), None).fraction(), 0.5);
+        assert_eq!(Progress::new(Phase::Combining, 5, None, None).fraction(), 0.0);
+        assert_eq!(Progress::new(Phase::Combining, 20, Some(10), None).fraction(), 1.0);
+    }
+}
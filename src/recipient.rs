@@ -0,0 +1,227 @@
+extern crate age;
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use age::stream::{StreamReader, StreamWriter};
+use age::x25519;
+use age::{Decryptor, Encryptor, Identity, IdentityFile};
+
+use crate::passphrase;
+use crate::passphrase::{LockWriter, UnlockReader};
+use crate::secret::SecretString;
+
+//# Pieces encrypted to their holders with age (https://age-encryption.org), so that whoever writes the pieces
+//# out or passes them on can't read them. The whole piece, header and all, is the plaintext of an age file
+//# encrypted to the holder's X25519 recipient (age1...). It's read back with the holder's identity file, or
+//# decrypted by the holder beforehand with age itself, in which case it's an ordinary piece again.
+//# Pieces may instead be locked with a passphrase of the holder's choosing; see passphrase.
+
+// Every age file starts with this line
+const MAGIC: &[u8] = b"age-encryption.org/v1\n";
+
+//# Parse an age X25519 recipient, as printed by age-keygen
+pub fn parse_recipient(text: &str) -> Result<x25519::Recipient, String> {
+    return x25519::Recipient::from_str(text.trim()).map_err(|error| format!("Invalid recipient {}: {}", text.trim(), error));
+}
+
+//# Read every identity in a set of age identity files
+pub fn read_identities<T, P>(paths: &T) -> Result<Vec<Box<dyn Identity>>, String>
+    where T: AsRef<[P]> + ?Sized,
+        P: AsRef<Path> {
+    let mut identities: Vec<Box<dyn Identity>> = Vec::new();
+    for path in paths.as_ref() {
+        let my_path = path.as_ref();
+        let file = match File::open(my_path) {
+            Err(error) => return Err(format!("Error opening identity file {}: {}", my_path.display(), error)),
+            Ok(file) => file,
+        };
+        match IdentityFile::from_buffer(BufReader::new(file)).map_err(|error| error.to_string()).and_then(|file| file.into_identities().map_err(|error| error.to_string())) {
+            Err(message) => return Err(format!("Error reading identity file {}: {}", my_path.display(), message)),
+            Ok(mut file_identities) => identities.append(&mut file_identities),
+        }
+    }
+    return Ok(identities);
+}
+
+//# Writes a piece into sink, encrypted to its holder, locked with their passphrase, or neither
+//# finish must be called, or an encrypted or locked piece is left truncated.
+pub enum PieceSink<W>
+    where W: Write {
+    Plain(W),
+    Encrypted(StreamWriter<W>),
+    Locked(LockWriter<W>),
+}
+
+impl<W> PieceSink<W>
+    where W: Write {
+    pub fn new(sink: W, recipient: Option<&x25519::Recipient>, passphrase: Option<&SecretString>) -> Result<PieceSink<W>, String> {
+        let recipient = match (recipient, passphrase) {
+            (None, None) => return Ok(PieceSink::Plain(sink)),
+            (None, Some(passphrase)) => return Ok(PieceSink::Locked(LockWriter::new(sink, passphrase)?)),
+            (Some(_), Some(_)) => return Err(String::from("A piece can't be both encrypted to a recipient and locked with a passphrase")),
+            (Some(recipient), None) => recipient,
+        };
+        let encryptor = match Encryptor::with_recipients(std::iter::once(recipient as &dyn age::Recipient)) {
+            Err(error) => return Err(format!("Error encrypting piece: {}", error)),
+            Ok(encryptor) => encryptor,
+        };
+        return match encryptor.wrap_output(sink) {
+            Err(error) => Err(format!("Error encrypting piece: {}", error)),
+            Ok(writer) => Ok(PieceSink::Encrypted(writer)),
+        }
+    }
+
+    // Complete the piece, returning sink
+    pub fn finish(self: PieceSink<W>) -> Result<W, String> {
+        return match self {
+            PieceSink::Plain(sink) => Ok(sink),
+            PieceSink::Encrypted(writer) => writer.finish().map_err(|error| format!("Error encrypting piece: {}", error)),
+            PieceSink::Locked(writer) => writer.finish(),
+        }
+    }
+}
+
+impl<W> Write for PieceSink<W>
+    where W: Write {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        return match self {
+            PieceSink::Plain(sink) => sink.write(data),
+            PieceSink::Encrypted(writer) => writer.write(data),
+            PieceSink::Locked(writer) => writer.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return match self {
+            PieceSink::Plain(sink) => sink.flush(),
+            PieceSink::Encrypted(writer) => writer.flush(),
+            PieceSink::Locked(writer) => writer.flush(),
+        }
+    }
+}
+
+//# Reads a piece from source, decrypting or unlocking it if need be
+pub enum PieceSource<R>
+    where R: Read {
+    Plain(R),
+    Decrypted(StreamReader<BufReader<R>>),
+    Unlocked(UnlockReader<R>),
+}
+
+impl<R> PieceSource<R>
+    where R: Read + Seek {
+    // Tell encrypted and locked pieces from others by how they start, and decrypt them with whichever of identities
+    // they were encrypted to, or unlock them with passphrase
+    pub fn open(mut source: R, identities: &[Box<dyn Identity>], passphrase: Option<&SecretString>) -> Result<PieceSource<R>, String> {
+        let start = read_start(&mut source)?;
+        if passphrase::is_locked(&start) {
+            return match passphrase {
+                None => Err(String::from("Piece is locked; give the passphrase of its holder")),
+                Some(passphrase) => Ok(PieceSource::Unlocked(UnlockReader::new(source, passphrase)?)),
+            }
+        }
+        if !start.starts_with(MAGIC) {
+            return Ok(PieceSource::Plain(source));
+        }
+
+        if identities.is_empty() {
+            return Err(String::from("Piece is encrypted; give the identity file of its holder, or have them decrypt it"));
+        }
+        let result = Decryptor::new_buffered(BufReader::new(source)).and_then(|decryptor| {
+            decryptor.decrypt(identities.iter().map(|identity| identity.as_ref()))
+        });
+        return match result {
+            Err(age::DecryptError::NoMatchingKeys) => Err(String::from("Piece is encrypted to a holder whose identity file wasn't given")),
+            Err(error) => Err(format!("Error decrypting piece: {}", error)),
+            Ok(reader) => Ok(PieceSource::Decrypted(reader)),
+        }
+    }
+
+    pub fn is_encrypted(self: &PieceSource<R>) -> bool {
+        return match self {
+            PieceSource::Plain(_) => false,
+            PieceSource::Decrypted(_) | PieceSource::Unlocked(_) => true,
+        }
+    }
+
+    // Length of the piece once decrypted or unlocked
+    pub fn length(self: &mut PieceSource<R>) -> Result<u64, String> {
+        let result = self.seek(SeekFrom::End(0)).and_then(|length| self.seek(SeekFrom::Start(0)).map(|_| length));
+        return result.map_err(|error| format!("Error reading piece: {}", error));
+    }
+}
+
+impl<R> Read for PieceSource<R>
+    where R: Read + Seek {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        return match self {
+            PieceSource::Plain(source) => source.read(buffer),
+            PieceSource::Decrypted(reader) => reader.read(buffer),
+            PieceSource::Unlocked(reader) => reader.read(buffer),
+        }
+    }
+}
+
+impl<R> Seek for PieceSource<R>
+    where R: Read + Seek {
+    fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+        return match self {
+            PieceSource::Plain(source) => source.seek(position),
+            PieceSource::Decrypted(reader) => reader.seek(position),
+            PieceSource::Unlocked(reader) => reader.seek(position),
+        }
+    }
+}
+
+//# Whether the piece read from source is locked with a passphrase, so that its holder can be asked for it
+pub fn is_locked<R>(source: &mut R) -> Result<bool, String>
+    where R: Read + Seek {
+    return Ok(passphrase::is_locked(&read_start(source)?));
+}
+
+// Enough of the start of source to tell what it is, leaving source where it was
+fn read_start<R>(source: &mut R) -> Result<Vec<u8>, String>
+    where R: Read + Seek {
+    let mut start: Vec<u8> = Vec::new();
+    if let Err(error) = (&mut *source).take(MAGIC.len().max(passphrase::MAGIC.len()) as u64).read_to_end(&mut start) {
+        return Err(format!("Error reading piece: {}", error));
+    }
+    if let Err(error) = source.seek(SeekFrom::Start(0)) {
+        return Err(format!("Error reading piece: {}", error));
+    }
+    return Ok(start);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_roundtrip_encrypted_piece() {
+        let identity = x25519::Identity::generate();
+        let recipient = parse_recipient(&format!(" {}\n", identity.to_public())).unwrap();
+        let mut sink = PieceSink::new(Vec::new(), Some(&recipient), None).unwrap();
+        sink.write_all(b"1\n1\n5717\nsecret\n").unwrap();
+        let encrypted = sink.finish().unwrap();
+        assert!(encrypted.starts_with(MAGIC));
+
+        let identities: Vec<Box<dyn Identity>> = vec![Box::new(x25519::Identity::from_str(identity.to_string().expose_secret()).unwrap())];
+        let mut source = PieceSource::open(Cursor::new(&encrypted), &identities, None).unwrap();
+        assert!(source.is_encrypted());
+        assert_eq!(source.length().unwrap(), 16);
+        let mut decrypted: Vec<u8> = Vec::new();
+        source.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, b"1\n1\n5717\nsecret\n");
+
+        let others: Vec<Box<dyn Identity>> = vec![Box::new(x25519::Identity::generate())];
+        assert!(PieceSource::open(Cursor::new(&encrypted), &others, None).is_err());
+        assert!(PieceSource::open(Cursor::new(&encrypted), &[], None).is_err());
+        assert!(!PieceSource::open(Cursor::new(b"1\n1\n5717\nsecret\n"), &[], None).unwrap().is_encrypted());
+        assert!(parse_recipient("age1notarecipient").is_err());
+    }
+}
@@ -0,0 +1,335 @@
+extern crate libc;
+extern crate zeroize;
+
+#[cfg(unix)]
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::sync::Mutex;
+use std::sync::Once;
+
+use zeroize::Zeroize;
+
+//# Buffer for plaintext secret data
+//# The contents are wiped when the buffer is dropped or outgrows its allocation, the allocation is
+//# locked into memory where the platform allows it so it can't be swapped out, and Debug output is redacted
+pub struct SecretBuffer {
+    data: Vec<u8>,
+}
+
+impl SecretBuffer {
+    pub fn new() -> SecretBuffer {
+        return SecretBuffer::with_capacity(0);
+    }
+
+    pub fn with_capacity(capacity: usize) -> SecretBuffer {
+        protect_process();
+        let buffer = SecretBuffer { data: Vec::with_capacity(capacity) };
+        lock_memory(&buffer.data);
+        return buffer;
+    }
+
+    pub fn zeroed(length: usize) -> SecretBuffer {
+        let mut buffer = SecretBuffer::with_capacity(length);
+        buffer.data.resize(length, 0);
+        return buffer;
+    }
+
+    pub fn from_slice<T>(data: &T) -> SecretBuffer
+        where T: AsRef<[u8]> + ?Sized {
+        let mut buffer = SecretBuffer::with_capacity(data.as_ref().len());
+        buffer.data.extend_from_slice(data.as_ref());
+        return buffer;
+    }
+
+    pub fn as_slice(self: &SecretBuffer) -> &[u8] {
+        return &self.data;
+    }
+
+    pub fn push(self: &mut SecretBuffer, value: u8) {
+        self.reserve(1);
+        self.data.push(value);
+    }
+
+    pub fn extend_from_slice<T>(self: &mut SecretBuffer, data: &T)
+        where T: AsRef<[u8]> + ?Sized {
+        self.reserve(data.as_ref().len());
+        self.data.extend_from_slice(data.as_ref());
+    }
+
+    pub fn truncate(self: &mut SecretBuffer, length: usize) {
+        if length < self.data.len() {
+            self.data[length..].zeroize();
+            self.data.truncate(length);
+        }
+    }
+
+    // Grow into a new allocation ourselves, so the old one can be wiped rather than freed as-is
+    fn reserve(self: &mut SecretBuffer, additional: usize) {
+        let required = self.data.len() + additional;
+        if required <= self.data.capacity() {
+            return;
+        }
+
+        let mut replacement = SecretBuffer::with_capacity(std::cmp::max(required, self.data.capacity() * 2));
+        replacement.data.extend_from_slice(&self.data);
+        std::mem::swap(self, &mut replacement);
+    }
+}
+
+impl Default for SecretBuffer {
+    fn default() -> SecretBuffer {
+        return SecretBuffer::new();
+    }
+}
+
+impl Drop for SecretBuffer {
+    fn drop(&mut self) {
+        self.data.zeroize();
+        unlock_memory(&self.data);
+    }
+}
+
+impl Clone for SecretBuffer {
+    fn clone(&self) -> SecretBuffer {
+        return SecretBuffer::from_slice(&self.data);
+    }
+}
+
+impl Deref for SecretBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        return &self.data;
+    }
+}
+
+impl DerefMut for SecretBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        return &mut self.data;
+    }
+}
+
+impl AsRef<[u8]> for SecretBuffer {
+    fn as_ref(&self) -> &[u8] {
+        return &self.data;
+    }
+}
+
+impl AsMut<[u8]> for SecretBuffer {
+    fn as_mut(&mut self) -> &mut [u8] {
+        return &mut self.data;
+    }
+}
+
+impl fmt::Debug for SecretBuffer {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return write!(formatter, "SecretBuffer([REDACTED])");
+    }
+}
+
+// Compare without exiting early, so the time taken doesn't reveal where the buffers differ
+impl PartialEq for SecretBuffer {
+    fn eq(&self, other: &SecretBuffer) -> bool {
+        if self.data.len() != other.data.len() {
+            return false;
+        }
+        return self.data.iter().zip(other.data.iter()).fold(0 as u8, |difference, (a, b)| difference | (a ^ b)) == 0;
+    }
+}
+
+//# UTF-8 text held in a SecretBuffer
+#[derive(Clone, Default, PartialEq)]
+pub struct SecretString {
+    buffer: SecretBuffer,
+}
+
+impl SecretString {
+    pub fn from_buffer(buffer: SecretBuffer) -> Result<SecretString, String> {
+        if std::str::from_utf8(&buffer).is_err() {
+            return Err(String::from("Secret is not valid UTF-8 text"));
+        }
+        return Ok(SecretString { buffer });
+    }
+
+    pub fn as_str(self: &SecretString) -> &str {
+        return std::str::from_utf8(&self.buffer).unwrap();
+    }
+
+    pub fn as_bytes(self: &SecretString) -> &[u8] {
+        return &self.buffer;
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(text: &str) -> SecretString {
+        return SecretString { buffer: SecretBuffer::from_slice(text) };
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return write!(formatter, "SecretString([REDACTED])");
+    }
+}
+
+// Number of live buffers on each locked page, by page number
+// mlock and munlock work on whole pages, and buffers share pages with each other, so a page is only unlocked
+// once the last buffer on it has gone, rather than when any of them does.
+#[cfg(unix)]
+static LOCKED_PAGES: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+#[cfg(unix)]
+fn page_size() -> usize {
+    return unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+}
+
+// The pages data's allocation lies on
+#[cfg(unix)]
+fn pages(data: &Vec<u8>, page_size: usize) -> std::ops::Range<usize> {
+    let start = data.as_ptr() as usize;
+    return (start / page_size)..((start + data.capacity() + page_size - 1) / page_size);
+}
+
+#[cfg(unix)]
+fn lock_memory(data: &Vec<u8>) {
+    if data.capacity() == 0 {
+        return;
+    }
+    let page_size = page_size();
+    let mut locked = LOCKED_PAGES.lock().unwrap_or_else(|error| error.into_inner());
+    for page in pages(data, page_size) {
+        let count = locked.entry(page).or_insert(0);
+        if *count == 0 {
+            // Best effort: this fails if RLIMIT_MEMLOCK is exhausted, which shouldn't stop us working
+            unsafe { libc::mlock((page * page_size) as *const libc::c_void, page_size); }
+        }
+        *count += 1;
+    }
+}
+
+#[cfg(unix)]
+fn unlock_memory(data: &Vec<u8>) {
+    if data.capacity() == 0 {
+        return;
+    }
+    let page_size = page_size();
+    let mut locked = LOCKED_PAGES.lock().unwrap_or_else(|error| error.into_inner());
+    for page in pages(data, page_size) {
+        let count = match locked.get_mut(&page) {
+            None => continue,
+            Some(count) => count,
+        };
+        *count -= 1;
+        if *count == 0 {
+            locked.remove(&page);
+            unsafe { libc::munlock((page * page_size) as *const libc::c_void, page_size); }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn lock_memory(_data: &Vec<u8>) {}
+
+#[cfg(not(unix))]
+fn unlock_memory(_data: &Vec<u8>) {}
+
+//# Stop the process from writing plaintext secrets out in a core dump
+//# Call this before handling any secret; it lasts for the rest of the process' lifetime. russs also does so itself
+//# the first time it takes in plaintext (see protect_process), but can't report failure from there.
+#[cfg(target_os = "linux")]
+pub fn disable_core_dumps() -> Result<(), String> {
+    let limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+        return Err(format!("Error disabling core dumps: {}", std::io::Error::last_os_error()));
+    }
+    // Also covers dumps requested by other means, and stops ptrace attachment by unprivileged processes
+    if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 {
+        return Err(format!("Error disabling core dumps: {}", std::io::Error::last_os_error()));
+    }
+
+    return Ok(());
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn disable_core_dumps() -> Result<(), String> {
+    let limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+        return Err(format!("Error disabling core dumps: {}", std::io::Error::last_os_error()));
+    }
+
+    return Ok(());
+}
+
+#[cfg(not(unix))]
+pub fn disable_core_dumps() -> Result<(), String> {
+    return Ok(());
+}
+
+// Disable core dumps once, for programs that use russs as a library without calling disable_core_dumps
+// Called wherever plaintext is first taken in: buffers holding it, and splitting what callers hold.
+pub(crate) fn protect_process() {
+    static PROTECTED: Once = Once::new();
+    PROTECTED.call_once(|| {
+        // Best effort, as with locking memory
+        let _ = disable_core_dumps();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacted_debug() {
+        let buffer = SecretBuffer::from_slice("hunter2");
+        assert!(!format!("{:?}", buffer).contains("hunter2"));
+        assert!(!format!("{:?}", SecretString::from("hunter2")).contains("hunter2"));
+    }
+
+    #[test]
+    fn test_growth() {
+        let mut buffer = SecretBuffer::with_capacity(2);
+        for value in 0..100 {
+            buffer.push(value);
+        }
+        buffer.extend_from_slice(&[100, 101]);
+        assert_eq!(buffer.as_slice(), &(0..102).collect::<Vec<u8>>()[..]);
+
+        buffer.truncate(3);
+        assert_eq!(buffer.as_slice(), &[0, 1, 2]);
+        assert_eq!(buffer, SecretBuffer::from_slice(&[0, 1, 2]));
+        assert!(buffer != SecretBuffer::from_slice(&[0, 1, 3]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shared_pages_stay_locked() {
+        // Small buffers are usually allocated on the same page; dropping one mustn't unlock the other
+        let first = SecretBuffer::from_slice(&[1; 16]);
+        let second = SecretBuffer::from_slice(&[2; 16]);
+        let page_size = page_size();
+        let count = |buffer: &SecretBuffer| {
+            let locked = LOCKED_PAGES.lock().unwrap();
+            return pages(&buffer.data, page_size).map(|page| *locked.get(&page).unwrap_or(&0)).min().unwrap();
+        };
+        assert!(count(&first) >= 1);
+        assert!(count(&second) >= 1);
+        drop(first);
+        assert!(count(&second) >= 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_protect_process() {
+        let _buffer = SecretBuffer::from_slice("hunter2");
+        assert_eq!(unsafe { libc::prctl(libc::PR_GET_DUMPABLE, 0, 0, 0, 0) }, 0);
+    }
+
+    #[test]
+    fn test_secret_string() {
+        assert!(SecretString::from_buffer(SecretBuffer::from_slice(&[0xff, 0xfe])).is_err());
+        assert_eq!(SecretString::from_buffer(SecretBuffer::from_slice("text")).unwrap().as_str(), "text");
+    }
+}
@@ -0,0 +1,208 @@
+extern crate base64;
+extern crate sha2;
+
+use std::convert::{TryFrom, TryInto};
+
+use sha2::{Digest, Sha256};
+
+use crate::encoding;
+
+pub const PREFIX: &str = "russs";
+pub const TEXT_SHARE_VERSION: i32 = 1;
+const SEPARATOR: char = ':';
+// Bytes of SHA-256 in the checksum
+const CHECKSUM_LENGTH: usize = 4;
+// Version, set ID, index, threshold and prime, in the binary form
+const BINARY_HEADER_LENGTH: usize = 15;
+
+//# How a text share is written out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    // The russs:... form below
+    Base64,
+    // The binary form below, in encoding::WORDS
+    Words,
+    // The binary form below, in Crockford's base32
+    Base32,
+}
+
+impl Default for Encoding {
+    fn default() -> Encoding {
+        return Encoding::Base64;
+    }
+}
+
+//# A text share carries everything needed to use it, so nothing has to be typed in separately:
+//# russs:version:setId:index:threshold:prime:data:checksum
+//# setId     identifies the shares generated together, so shares from different splits aren't mixed
+//# threshold the number of shares required
+//# data      the points, in URL-safe base64 without padding
+//# checksum  the first 4 bytes of the SHA-256 of everything before it, in hex, to catch typing mistakes
+//# The words and base32 encodings instead carry a binary form, which parse tells apart by its characters:
+//# version (u8), setId (u64), index, threshold, prime (u16 each), data, checksum (first 4 bytes of SHA-256)
+//# All integers are big-endian, and setId is the 16 hex digits of generate_text_shares.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextShare {
+    pub set_id: String,
+    pub index: i32,
+    pub threshold: i32,
+    pub prime: i32,
+    pub data: Vec<u8>,
+}
+
+impl TextShare {
+    pub fn encode(self: &TextShare) -> String {
+        let body = format!("{}{sep}{}{sep}{}{sep}{}{sep}{}{sep}{}{sep}{}", PREFIX, TEXT_SHARE_VERSION, self.set_id, self.index, self.threshold, self.prime,
+                           base64::encode_config(&self.data, base64::URL_SAFE_NO_PAD), sep = SEPARATOR);
+        return format!("{}{}{}", body, SEPARATOR, checksum(&body));
+    }
+
+    // The words and base32 encodings fail for shares that don't fit the binary form
+    pub fn encode_as(self: &TextShare, encoding: Encoding) -> Result<String, String> {
+        return match encoding {
+            Encoding::Base64 => Ok(self.encode()),
+            Encoding::Words => Ok(encoding::encode_words(&self.to_bytes()?)),
+            Encoding::Base32 => Ok(encoding::encode_base32(&self.to_bytes()?)),
+        }
+    }
+
+    // Accepts any of the encodings, telling them apart by the prefix of the first and the digits or separators of base32
+    pub fn parse(text: &str) -> Result<TextShare, String> {
+        let my_text = text.trim_start();
+        if my_text.len() >= PREFIX.len() && my_text.is_char_boundary(PREFIX.len()) && my_text[..PREFIX.len()].eq_ignore_ascii_case(PREFIX) {
+            return TextShare::parse_base64(my_text);
+        }
+        if my_text.chars().any(|c| c.is_ascii_digit() || "-*~$=".contains(c)) {
+            return TextShare::from_bytes(&encoding::decode_base32(my_text)?);
+        }
+        return TextShare::from_bytes(&encoding::decode_words(my_text)?);
+    }
+
+    fn parse_base64(text: &str) -> Result<TextShare, String> {
+        // Shares are often copied with a stray space or line break
+        let my_text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let (body, expected) = match my_text.rfind(SEPARATOR) {
+            None => return Err(String::from("Malformed text share")),
+            Some(position) => (&my_text[..position], &my_text[(position + 1)..]),
+        };
+
+        let fields: Vec<&str> = body.split(SEPARATOR).collect();
+        if fields.len() != 7 || fields[0] != PREFIX {
+            return Err(String::from("Malformed text share"));
+        }
+        if fields[1] != TEXT_SHARE_VERSION.to_string() {
+            return Err(format!("Unsupported text share version: {}", fields[1]));
+        }
+        if !expected.eq_ignore_ascii_case(&checksum(body)) {
+            return Err(String::from("Text share failed its checksum; check it for typing mistakes"));
+        }
+        if parse_set_id(fields[2]).is_none() {
+            return Err(format!("Malformed text share set ID: {}", fields[2]));
+        }
+
+        return match (fields[3].parse::<i32>(), fields[4].parse::<i32>(), fields[5].parse::<i32>(), base64::decode_config(fields[6], base64::URL_SAFE_NO_PAD)) {
+            (Ok(index), Ok(threshold), Ok(prime), Ok(data)) => Ok(TextShare { set_id: String::from(fields[2]), index, threshold, prime, data }),
+            _ => Err(String::from("Malformed text share")),
+        }
+    }
+
+    fn to_bytes(self: &TextShare) -> Result<Vec<u8>, String> {
+        let set_id = match parse_set_id(&self.set_id) {
+            None => return Err(format!("Malformed text share set ID: {}", self.set_id)),
+            Some(set_id) => set_id,
+        };
+        let mut result: Vec<u8> = Vec::with_capacity(BINARY_HEADER_LENGTH + self.data.len() + CHECKSUM_LENGTH);
+        result.push(TEXT_SHARE_VERSION as u8);
+        result.extend_from_slice(&set_id.to_be_bytes());
+        for value in &[self.index, self.threshold, self.prime] {
+            match u16::try_from(*value) {
+                Err(_) => return Err(format!("Text share value {} is too large for the binary form", value)),
+                Ok(value) => result.extend_from_slice(&value.to_be_bytes()),
+            }
+        }
+        result.extend_from_slice(&self.data);
+        let checksum = Sha256::digest(&result);
+        result.extend_from_slice(&checksum[0..CHECKSUM_LENGTH]);
+        return Ok(result);
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<TextShare, String> {
+        if bytes.len() < BINARY_HEADER_LENGTH + CHECKSUM_LENGTH {
+            return Err(String::from("Text share is too short; check it for missing words or characters"));
+        }
+        let (body, expected) = bytes.split_at(bytes.len() - CHECKSUM_LENGTH);
+        // Every word or group passed its own check, so the mistake is a valid word or group in place of another
+        if Sha256::digest(body)[0..CHECKSUM_LENGTH] != *expected {
+            return Err(String::from("Text share failed its checksum; check it for typing mistakes"));
+        }
+        if body[0] as i32 != TEXT_SHARE_VERSION {
+            return Err(format!("Unsupported text share version: {}", body[0]));
+        }
+
+        let value = |offset: usize| u16::from_be_bytes(body[offset..(offset + 2)].try_into().unwrap()) as i32;
+        return Ok(TextShare {
+            set_id: format!("{:016x}", u64::from_be_bytes(body[1..9].try_into().unwrap())),
+            index: value(9),
+            threshold: value(11),
+            prime: value(13),
+            data: body[BINARY_HEADER_LENGTH..].to_vec(),
+        });
+    }
+}
+
+// Set IDs are the 16 hex digits of a u64, so that the binary form can carry them
+fn parse_set_id(set_id: &str) -> Option<u64> {
+    if set_id.len() != 16 || !set_id.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    return u64::from_str_radix(set_id, 16).ok();
+}
+
+fn checksum(body: &str) -> String {
+    return Sha256::digest(body.as_bytes())[0..CHECKSUM_LENGTH].iter().map(|byte| format!("{:02x}", byte)).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_text_share() {
+        let share = TextShare { set_id: String::from("0123456789abcdef"), index: 3, threshold: 2, prime: 5717, data: vec![1, 2, 255, 254] };
+        let text = share.encode();
+        assert!(text.starts_with("russs:1:0123456789abcdef:3:2:5717:AQL__g:"));
+        assert_eq!(TextShare::parse(&text).unwrap(), share);
+        assert_eq!(TextShare::parse(&format!(" {}\n", text.replace("5717", "57\n17"))).unwrap(), share);
+
+        // A typing mistake anywhere is caught
+        let mistyped = text.replace(":3:", ":4:");
+        assert!(TextShare::parse(&mistyped).unwrap_err().contains("checksum"));
+        assert!(TextShare::parse(&text.replace("russs:1:", "russs:2:")).is_err());
+        assert!(TextShare::parse("AQL__g").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_encodings() {
+        let share = TextShare { set_id: String::from("0123456789abcdef"), index: 3, threshold: 2, prime: 5717, data: vec![1, 2, 255, 254] };
+        for encoding in &[Encoding::Base64, Encoding::Words, Encoding::Base32] {
+            assert_eq!(TextShare::parse(&share.encode_as(*encoding).unwrap()).unwrap(), share);
+        }
+        let malformed = TextShare { set_id: String::from("nothex"), ..share.clone() };
+        assert!(malformed.encode_as(Encoding::Words).is_err());
+        assert!(TextShare::parse(&malformed.encode()).unwrap_err().starts_with("Malformed text share set ID"));
+        assert!(TextShare { prime: 70001, ..share.clone() }.encode_as(Encoding::Base32).is_err());
+
+        let words = share.encode_as(Encoding::Words).unwrap();
+        let mut misspelt: Vec<&str> = words.split(' ').collect();
+        misspelt[2] = "xyzzy";
+        assert_eq!(TextShare::parse(&misspelt.join(" ")).unwrap_err(), "Word 3 (\"xyzzy\") isn't in the word list");
+        let swapped = words.replacen("actor", "acorn", 1);
+        assert!(TextShare::parse(&swapped).unwrap_err().contains("checksum"));
+
+        let base32 = share.encode_as(Encoding::Base32).unwrap();
+        let mut mistyped: Vec<char> = base32.chars().collect();
+        mistyped[7] = if mistyped[7] == 'X' { 'Y' } else { 'X' };
+        let mistyped: String = mistyped.into_iter().collect();
+        assert!(TextShare::parse(&mistyped).unwrap_err().starts_with("Group 2 "));
+    }
+}
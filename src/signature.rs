@@ -0,0 +1,306 @@
+extern crate ed25519_dalek;
+extern crate sha2;
+extern crate zeroize;
+
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::prelude::*;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
+
+use crate::header::Header;
+use crate::output::OutputFile;
+
+//# Pieces signed by their dealer with Ed25519, so that holders can tell a piece is the one the dealer made
+//# A signed piece records the dealer's public key in its header under DEALER_KEY, and ends with the dealer's
+//# signature of the SHA-256 of everything before it, the header and the body, inside any armor or encryption.
+//# The key in the header only says who claims to have made the piece; holders check it against the public key
+//# they got from the dealer themselves.
+//# Dealer keys are kept in a file holding the 32-byte Ed25519 secret key in hex, and public keys are given in hex.
+
+pub const DEALER_KEY: &str = "dealer";
+pub const SIGNATURE_LENGTH: usize = 64;
+const KEY_LENGTH: usize = 32;
+
+//# Generate a new dealer key, writing it to path, and return its public key
+pub fn generate_key<P>(path: P, overwrite: bool) -> Result<String, String>
+    where P: AsRef<Path> {
+    let mut secret = [0 as u8; KEY_LENGTH];
+    thread_rng().fill(&mut secret);
+    let key = SigningKey::from_bytes(&secret);
+    secret.zeroize();
+
+    let mut file = OutputFile::create(path.as_ref(), overwrite)?;
+    let mut text = format!("{}\n", to_hex(key.as_bytes()));
+    let result = file.write_all(text.as_bytes());
+    text.zeroize();
+    if let Err(error) = result {
+        return Err(format!("Error writing {}: {}", path.as_ref().display(), error));
+    }
+    file.commit()?;
+
+    return Ok(public_key(&key));
+}
+
+//# Read the dealer key in the file at path
+pub fn read_key<P>(path: P) -> Result<SigningKey, String>
+    where P: AsRef<Path> {
+    let my_path = path.as_ref();
+    let mut text = match std::fs::read_to_string(my_path) {
+        Err(error) => return Err(format!("Error reading dealer key {}: {}", my_path.display(), error)),
+        Ok(text) => text,
+    };
+    let result = from_hex(text.trim());
+    text.zeroize();
+    let mut secret = match result {
+        Some(secret) if secret.len() == KEY_LENGTH => secret,
+        _ => return Err(format!("Malformed dealer key {}", my_path.display())),
+    };
+    let key = SigningKey::from_bytes(secret.as_slice().try_into().unwrap());
+    secret.zeroize();
+    return Ok(key);
+}
+
+//# The public key of a dealer key, in hex
+pub fn public_key(key: &SigningKey) -> String {
+    return to_hex(key.verifying_key().as_bytes());
+}
+
+//# Parse a dealer's public key, as given by public_key
+pub fn parse_public_key(text: &str) -> Result<VerifyingKey, String> {
+    let result = from_hex(text.trim()).and_then(|bytes| bytes.as_slice().try_into().ok()).and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok());
+    return result.ok_or_else(|| format!("Invalid dealer public key {}", text.trim()));
+}
+
+//# Record in header that pieces made from it are signed with key
+pub fn add_to_header(key: &SigningKey, header: &mut Header) {
+    header.add_extension(DEALER_KEY, public_key(key));
+}
+
+//# Check that the piece read from source, header and all, is signed by its dealer
+//# If trusted is given, the piece must have been signed with that key, and unsigned pieces are refused;
+//# otherwise unsigned pieces pass, and signed ones are only checked against the key they name.
+pub fn verify_piece<R>(mut source: R, trusted: Option<&VerifyingKey>) -> Result<(), String>
+    where R: Read {
+    let header = Header::read(&mut source)?;
+    let named = match Trailer::new(&header)? {
+        None if trusted.is_some() => return Err(String::from("Piece isn't signed by its dealer")),
+        None => return Ok(()),
+        Some(trailer) => *trailer.key(),
+    };
+    if trusted.map_or(false, |trusted| *trusted != named) {
+        return Err(String::from("Piece is signed by a different dealer"));
+    }
+
+    let mut reader = SignedReader::new(source, &header)?;
+    if let Err(error) = std::io::copy(&mut reader, &mut std::io::sink()) {
+        return Err(format!("Error reading piece: {}", error));
+    }
+    return reader.verify();
+}
+
+//# Writes a piece into sink, signing it with key when given
+//# finish must be called, or a signed piece is left without its signature.
+pub struct SignWriter<W> {
+    sink: W,
+    key: Option<SigningKey>,
+    digest: Sha256,
+}
+
+impl<W> SignWriter<W>
+    where W: Write {
+    pub fn new(sink: W, key: Option<&SigningKey>) -> SignWriter<W> {
+        return SignWriter { sink, key: key.cloned(), digest: Sha256::new() };
+    }
+
+    // Write the signature, returning sink
+    pub fn finish(mut self: SignWriter<W>) -> Result<W, String> {
+        if let Some(ref key) = self.key {
+            let signature = key.sign(&self.digest.finalize_reset());
+            if let Err(error) = self.sink.write_all(&signature.to_bytes()) {
+                return Err(format!("Error signing piece: {}", error));
+            }
+        }
+        return Ok(self.sink);
+    }
+}
+
+impl<W> Write for SignWriter<W>
+    where W: Write {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let length = self.sink.write(data)?;
+        self.digest.update(&data[0..length]);
+        return Ok(length);
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return self.sink.flush();
+    }
+}
+
+//# Separates the body of a signed piece from its signature as the body is supplied in pieces
+//# The last SIGNATURE_LENGTH bytes are always held back, as they may turn out to be the signature, and the rest
+//# is hashed and handed back as the body. This is shared by readers and Combiner, which is given bodies in pieces.
+pub struct Trailer {
+    key: VerifyingKey,
+    digest: Sha256,
+    // Supplied but not yet handed back; once the piece has all been supplied, the signature
+    pending: Vec<u8>,
+}
+
+impl Trailer {
+    //# Begin reading the body of the piece with header, or None if it isn't signed
+    pub fn new(header: &Header) -> Result<Option<Trailer>, String> {
+        let key = match header.extension(DEALER_KEY) {
+            None => return Ok(None),
+            Some(named) => parse_public_key(named)?,
+        };
+        let mut digest = Sha256::new();
+        digest.update(&header.to_bytes());
+        return Ok(Some(Trailer { key, digest, pending: Vec::new() }));
+    }
+
+    //# The key the piece's header says it was signed with
+    pub fn key(self: &Trailer) -> &VerifyingKey {
+        return &self.key;
+    }
+
+    //# Supply more of the piece, returning what is now known to be body
+    pub fn update(self: &mut Trailer, data: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(data);
+        let length = self.pending.len().saturating_sub(SIGNATURE_LENGTH);
+        let body: Vec<u8> = self.pending.drain(0..length).collect();
+        self.digest.update(&body);
+        return body;
+    }
+
+    //# The signature at the end of the piece, once the piece has all been supplied
+    pub fn signature(self: &Trailer) -> &[u8] {
+        return &self.pending;
+    }
+
+    //# Check the signature against the key in the header, once the piece has all been supplied
+    pub fn verify(self: &Trailer) -> Result<(), String> {
+        let signature = match self.pending.as_slice().try_into() {
+            Err(_) => return Err(String::from("Piece's signature is missing")),
+            Ok(bytes) => Signature::from_bytes(bytes),
+        };
+        return match self.key.verify(&self.digest.clone().finalize(), &signature) {
+            Err(_) => Err(String::from("Piece's signature doesn't match; it isn't as its dealer made it")),
+            Ok(()) => Ok(()),
+        }
+    }
+}
+
+//# Reads the body of a piece whose header has already been read from source, leaving off its signature if it has one
+pub struct SignedReader<R> {
+    source: R,
+    trailer: Option<Trailer>,
+    // Body handed back by trailer that didn't fit in the caller's buffer
+    ready: Vec<u8>,
+    finished: bool,
+}
+
+impl<R> SignedReader<R>
+    where R: Read {
+    pub fn new(source: R, header: &Header) -> Result<SignedReader<R>, String> {
+        return Ok(SignedReader { source, trailer: Trailer::new(header)?, ready: Vec::new(), finished: false });
+    }
+
+    // The signature at the end of the piece, once the body has all been read
+    pub fn trailer(self: &SignedReader<R>) -> &[u8] {
+        return match self.trailer {
+            Some(ref trailer) if self.finished => trailer.signature(),
+            _ => &[],
+        }
+    }
+
+    // Check the signature against the key in the header, once the body has all been read
+    pub fn verify(self: &SignedReader<R>) -> Result<(), String> {
+        return match self.trailer {
+            None => Err(String::from("Piece isn't signed by its dealer")),
+            Some(_) if !self.finished => Err(String::from("Piece's signature is missing")),
+            Some(ref trailer) => trailer.verify(),
+        }
+    }
+}
+
+impl<R> Read for SignedReader<R>
+    where R: Read {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let trailer = match self.trailer {
+            None => return self.source.read(buffer),
+            Some(ref mut trailer) => trailer,
+        };
+
+        while !self.finished && self.ready.is_empty() && !buffer.is_empty() {
+            let mut data = vec![0 as u8; buffer.len().max(SIGNATURE_LENGTH)];
+            let length = self.source.read(&mut data)?;
+            self.finished = length == 0;
+            self.ready = trailer.update(&data[0..length]);
+        }
+
+        let length = buffer.len().min(self.ready.len());
+        buffer[0..length].copy_from_slice(&self.ready[0..length]);
+        self.ready.drain(0..length);
+        return Ok(length);
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 || !text.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    return (0..text.len()).step_by(2).map(|index| u8::from_str_radix(&text[index..(index + 2)], 16).ok()).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify_piece() {
+        let key = SigningKey::from_bytes(&[7; KEY_LENGTH]);
+        let mut header = Header::new(1, 5717, "secret");
+        add_to_header(&key, &mut header);
+        let mut writer = SignWriter::new(Vec::new(), Some(&key));
+        writer.write_all(&header.to_bytes()).unwrap();
+        writer.write_all(&[1; 300]).unwrap();
+        let piece = writer.finish().unwrap();
+        assert_eq!(piece.len(), header.to_bytes().len() + 300 + SIGNATURE_LENGTH);
+
+        let trusted = parse_public_key(&public_key(&key)).unwrap();
+        assert!(verify_piece(piece.as_slice(), None).is_ok());
+        assert!(verify_piece(piece.as_slice(), Some(&trusted)).is_ok());
+        let other = SigningKey::from_bytes(&[8; KEY_LENGTH]).verifying_key();
+        assert!(verify_piece(piece.as_slice(), Some(&other)).is_err());
+
+        // The body is read without the signature
+        let mut source = piece.as_slice();
+        let read = Header::read(&mut source).unwrap();
+        let mut reader = SignedReader::new(source, &read).unwrap();
+        let mut body: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut body).unwrap();
+        assert_eq!(body, vec![1; 300]);
+        assert_eq!(reader.trailer().len(), SIGNATURE_LENGTH);
+        assert!(reader.verify().is_ok());
+
+        let mut forged = piece.clone();
+        forged[header.to_bytes().len() + 5] ^= 1;
+        assert!(verify_piece(forged.as_slice(), Some(&trusted)).is_err());
+        assert!(verify_piece(&piece[0..(piece.len() - 1)], None).is_err());
+
+        let mut unsigned = Header::new(1, 5717, "secret").to_bytes();
+        unsigned.extend_from_slice(&[1; 300]);
+        assert!(verify_piece(unsigned.as_slice(), None).is_ok());
+        assert!(verify_piece(unsigned.as_slice(), Some(&trusted)).is_err());
+        assert!(parse_public_key("not a key").is_err());
+    }
+}
@@ -0,0 +1,531 @@
+extern crate hmac;
+extern crate pbkdf2;
+extern crate sha2;
+extern crate zeroize;
+
+use std::collections::BTreeMap;
+
+use hmac::{Hmac, Mac};
+use rand::prelude::*;
+use sha2::Sha256;
+use zeroize::Zeroize;
+
+use crate::gf256;
+use crate::secret::SecretBuffer;
+
+//# SLIP-0039 mnemonic shares, as used by hardware wallets; see https://github.com/satoshilabs/slips/blob/master/slip-0039.md
+//# The master secret is encrypted with a passphrase, split among groups, and each group's share split again among its
+//# members. Unlike the rest of russs, sharing is over GF(256), so each share is the length of the secret.
+//# A mnemonic is a series of 10 bit words:
+//# identifier (15 bits), extendable (1 bit), iterationExponent (4 bits)
+//# groupIndex, groupThreshold - 1, groupCount - 1, memberIndex, memberThreshold - 1 (4 bits each)
+//# value     the share, padded with zero bits at the start to a whole number of words
+//# checksum  3 words of RS1024 over everything before it
+
+pub const WORDS: [&str; 1024] = [
+    "academic", "acid", "acne", "acquire", "acrobat", "activity", "actress", "adapt", "adequate", "adjust", "admit",
+    "adorn", "adult", "advance", "advocate", "afraid", "again", "agency", "agree", "aide", "aircraft", "airline",
+    "airport", "ajar", "alarm", "album", "alcohol", "alien", "alive", "alpha", "already", "alto", "aluminum", "always",
+    "amazing", "ambition", "amount", "amuse", "analysis", "anatomy", "ancestor", "ancient", "angel", "angry", "animal",
+    "answer", "antenna", "anxiety", "apart", "aquatic", "arcade", "arena", "argue", "armed", "artist", "artwork",
+    "aspect", "auction", "august", "aunt", "average", "aviation", "avoid", "award", "away", "axis", "axle", "beam",
+    "beard", "beaver", "become", "bedroom", "behavior", "being", "believe", "belong", "benefit", "best", "beyond",
+    "bike", "biology", "birthday", "bishop", "black", "blanket", "blessing", "blimp", "blind", "blue", "body", "bolt",
+    "boring", "born", "both", "boundary", "bracelet", "branch", "brave", "breathe", "briefing", "broken", "brother",
+    "browser", "bucket", "budget", "building", "bulb", "bulge", "bumpy", "bundle", "burden", "burning", "busy", "buyer",
+    "cage", "calcium", "camera", "campus", "canyon", "capacity", "capital", "capture", "carbon", "cards", "careful",
+    "cargo", "carpet", "carve", "category", "cause", "ceiling", "center", "ceramic", "champion", "change", "charity",
+    "check", "chemical", "chest", "chew", "chubby", "cinema", "civil", "class", "clay", "cleanup", "client", "climate",
+    "clinic", "clock", "clogs", "closet", "clothes", "club", "cluster", "coal", "coastal", "coding", "column",
+    "company", "corner", "costume", "counter", "course", "cover", "cowboy", "cradle", "craft", "crazy", "credit",
+    "cricket", "criminal", "crisis", "critical", "crowd", "crucial", "crunch", "crush", "crystal", "cubic", "cultural",
+    "curious", "curly", "custody", "cylinder", "daisy", "damage", "dance", "darkness", "database", "daughter",
+    "deadline", "deal", "debris", "debut", "decent", "decision", "declare", "decorate", "decrease", "deliver", "demand",
+    "density", "deny", "depart", "depend", "depict", "deploy", "describe", "desert", "desire", "desktop", "destroy",
+    "detailed", "detect", "device", "devote", "diagnose", "dictate", "diet", "dilemma", "diminish", "dining", "diploma",
+    "disaster", "discuss", "disease", "dish", "dismiss", "display", "distance", "dive", "divorce", "document", "domain",
+    "domestic", "dominant", "dough", "downtown", "dragon", "dramatic", "dream", "dress", "drift", "drink", "drove",
+    "drug", "dryer", "duckling", "duke", "duration", "dwarf", "dynamic", "early", "earth", "easel", "easy", "echo",
+    "eclipse", "ecology", "edge", "editor", "educate", "either", "elbow", "elder", "election", "elegant", "element",
+    "elephant", "elevator", "elite", "else", "email", "emerald", "emission", "emperor", "emphasis", "employer", "empty",
+    "ending", "endless", "endorse", "enemy", "energy", "enforce", "engage", "enjoy", "enlarge", "entrance", "envelope",
+    "envy", "epidemic", "episode", "equation", "equip", "eraser", "erode", "escape", "estate", "estimate", "evaluate",
+    "evening", "evidence", "evil", "evoke", "exact", "example", "exceed", "exchange", "exclude", "excuse", "execute",
+    "exercise", "exhaust", "exotic", "expand", "expect", "explain", "express", "extend", "extra", "eyebrow", "facility",
+    "fact", "failure", "faint", "fake", "false", "family", "famous", "fancy", "fangs", "fantasy", "fatal", "fatigue",
+    "favorite", "fawn", "fiber", "fiction", "filter", "finance", "findings", "finger", "firefly", "firm", "fiscal",
+    "fishing", "fitness", "flame", "flash", "flavor", "flea", "flexible", "flip", "float", "floral", "fluff", "focus",
+    "forbid", "force", "forecast", "forget", "formal", "fortune", "forward", "founder", "fraction", "fragment",
+    "frequent", "freshman", "friar", "fridge", "friendly", "frost", "froth", "frozen", "fumes", "funding", "furl",
+    "fused", "galaxy", "game", "garbage", "garden", "garlic", "gasoline", "gather", "general", "genius", "genre",
+    "genuine", "geology", "gesture", "glad", "glance", "glasses", "glen", "glimpse", "goat", "golden", "graduate",
+    "grant", "grasp", "gravity", "gray", "greatest", "grief", "grill", "grin", "grocery", "gross", "group", "grownup",
+    "grumpy", "guard", "guest", "guilt", "guitar", "gums", "hairy", "hamster", "hand", "hanger", "harvest", "have",
+    "havoc", "hawk", "hazard", "headset", "health", "hearing", "heat", "helpful", "herald", "herd", "hesitate", "hobo",
+    "holiday", "holy", "home", "hormone", "hospital", "hour", "huge", "human", "humidity", "hunting", "husband", "hush",
+    "husky", "hybrid", "idea", "identify", "idle", "image", "impact", "imply", "improve", "impulse", "include",
+    "income", "increase", "index", "indicate", "industry", "infant", "inform", "inherit", "injury", "inmate", "insect",
+    "inside", "install", "intend", "intimate", "invasion", "involve", "iris", "island", "isolate", "item", "ivory",
+    "jacket", "jerky", "jewelry", "join", "judicial", "juice", "jump", "junction", "junior", "junk", "jury", "justice",
+    "kernel", "keyboard", "kidney", "kind", "kitchen", "knife", "knit", "laden", "ladle", "ladybug", "lair", "lamp",
+    "language", "large", "laser", "laundry", "lawsuit", "leader", "leaf", "learn", "leaves", "lecture", "legal",
+    "legend", "legs", "lend", "length", "level", "liberty", "library", "license", "lift", "likely", "lilac", "lily",
+    "lips", "liquid", "listen", "literary", "living", "lizard", "loan", "lobe", "location", "losing", "loud", "loyalty",
+    "luck", "lunar", "lunch", "lungs", "luxury", "lying", "lyrics", "machine", "magazine", "maiden", "mailman", "main",
+    "makeup", "making", "mama", "manager", "mandate", "mansion", "manual", "marathon", "march", "market", "marvel",
+    "mason", "material", "math", "maximum", "mayor", "meaning", "medal", "medical", "member", "memory", "mental",
+    "merchant", "merit", "method", "metric", "midst", "mild", "military", "mineral", "minister", "miracle", "mixed",
+    "mixture", "mobile", "modern", "modify", "moisture", "moment", "morning", "mortgage", "mother", "mountain", "mouse",
+    "move", "much", "mule", "multiple", "muscle", "museum", "music", "mustang", "nail", "national", "necklace",
+    "negative", "nervous", "network", "news", "nuclear", "numb", "numerous", "nylon", "oasis", "obesity", "object",
+    "observe", "obtain", "ocean", "often", "olympic", "omit", "oral", "orange", "orbit", "order", "ordinary",
+    "organize", "ounce", "oven", "overall", "owner", "paces", "pacific", "package", "paid", "painting", "pajamas",
+    "pancake", "pants", "papa", "paper", "parcel", "parking", "party", "patent", "patrol", "payment", "payroll",
+    "peaceful", "peanut", "peasant", "pecan", "penalty", "pencil", "percent", "perfect", "permit", "petition",
+    "phantom", "pharmacy", "photo", "phrase", "physics", "pickup", "picture", "piece", "pile", "pink", "pipeline",
+    "pistol", "pitch", "plains", "plan", "plastic", "platform", "playoff", "pleasure", "plot", "plunge", "practice",
+    "prayer", "preach", "predator", "pregnant", "premium", "prepare", "presence", "prevent", "priest", "primary",
+    "priority", "prisoner", "privacy", "prize", "problem", "process", "profile", "program", "promise", "prospect",
+    "provide", "prune", "public", "pulse", "pumps", "punish", "puny", "pupal", "purchase", "purple", "python",
+    "quantity", "quarter", "quick", "quiet", "race", "racism", "radar", "railroad", "rainbow", "raisin", "random",
+    "ranked", "rapids", "raspy", "reaction", "realize", "rebound", "rebuild", "recall", "receiver", "recover", "regret",
+    "regular", "reject", "relate", "remember", "remind", "remove", "render", "repair", "repeat", "replace", "require",
+    "rescue", "research", "resident", "response", "result", "retailer", "retreat", "reunion", "revenue", "review",
+    "reward", "rhyme", "rhythm", "rich", "rival", "river", "robin", "rocky", "romantic", "romp", "roster", "round",
+    "royal", "ruin", "ruler", "rumor", "sack", "safari", "salary", "salon", "salt", "satisfy", "satoshi", "saver",
+    "says", "scandal", "scared", "scatter", "scene", "scholar", "science", "scout", "scramble", "screw", "script",
+    "scroll", "seafood", "season", "secret", "security", "segment", "senior", "shadow", "shaft", "shame", "shaped",
+    "sharp", "shelter", "sheriff", "short", "should", "shrimp", "sidewalk", "silent", "silver", "similar", "simple",
+    "single", "sister", "skin", "skunk", "slap", "slavery", "sled", "slice", "slim", "slow", "slush", "smart", "smear",
+    "smell", "smirk", "smith", "smoking", "smug", "snake", "snapshot", "sniff", "society", "software", "soldier",
+    "solution", "soul", "source", "space", "spark", "speak", "species", "spelling", "spend", "spew", "spider", "spill",
+    "spine", "spirit", "spit", "spray", "sprinkle", "square", "squeeze", "stadium", "staff", "standard", "starting",
+    "station", "stay", "steady", "step", "stick", "stilt", "story", "strategy", "strike", "style", "subject", "submit",
+    "sugar", "suitable", "sunlight", "superior", "surface", "surprise", "survive", "sweater", "swimming", "swing",
+    "switch", "symbolic", "sympathy", "syndrome", "system", "tackle", "tactics", "tadpole", "talent", "task", "taste",
+    "taught", "taxi", "teacher", "teammate", "teaspoon", "temple", "tenant", "tendency", "tension", "terminal",
+    "testify", "texture", "thank", "that", "theater", "theory", "therapy", "thorn", "threaten", "thumb", "thunder",
+    "ticket", "tidy", "timber", "timely", "ting", "tofu", "together", "tolerate", "total", "toxic", "tracks", "traffic",
+    "training", "transfer", "trash", "traveler", "treat", "trend", "trial", "tricycle", "trip", "triumph", "trouble",
+    "true", "trust", "twice", "twin", "type", "typical", "ugly", "ultimate", "umbrella", "uncover", "undergo", "unfair",
+    "unfold", "unhappy", "union", "universe", "unkind", "unknown", "unusual", "unwrap", "upgrade", "upstairs",
+    "username", "usher", "usual", "valid", "valuable", "vampire", "vanish", "various", "vegan", "velvet", "venture",
+    "verdict", "verify", "very", "veteran", "vexed", "victim", "video", "view", "vintage", "violence", "viral",
+    "visitor", "visual", "vitamins", "vocal", "voice", "volume", "voter", "voting", "walnut", "warmth", "warn", "watch",
+    "wavy", "wealthy", "weapon", "webcam", "welcome", "welfare", "western", "width", "wildlife", "window", "wine",
+    "wireless", "wisdom", "withdraw", "wits", "wolf", "woman", "work", "worthy", "wrap", "wrist", "writing", "wrote",
+    "year", "yelp", "yield", "yoga", "zero",
+];
+
+const RADIX_BITS: usize = 10;
+const CHECKSUM_WORDS: usize = 3;
+// Identifier, flags and group fields, and the checksum
+const METADATA_WORDS: usize = 4 + CHECKSUM_WORDS;
+pub const MIN_SECRET_LENGTH: usize = 16;
+const MIN_MNEMONIC_WORDS: usize = METADATA_WORDS + (MIN_SECRET_LENGTH * 8 + RADIX_BITS - 1) / RADIX_BITS;
+pub const MAX_SHARE_COUNT: usize = 16;
+const CUSTOMIZATION: &[u8] = b"shamir";
+const EXTENDABLE_CUSTOMIZATION: &[u8] = b"shamir_extendable";
+const BASE_ITERATION_COUNT: u32 = 10000;
+const ROUND_COUNT: u8 = 4;
+const DIGEST_LENGTH: usize = 4;
+// x coordinates at which the shared secret and its digest are kept
+const SECRET_INDEX: u8 = 255;
+const DIGEST_INDEX: u8 = 254;
+const GENERATOR: [u32; 10] = [0xe0e040, 0x1c1c080, 0x3838100, 0x7070200, 0xe0e0009, 0x1c0c2412, 0x38086c24, 0x3090fc48, 0x21b1f890, 0x3f3f120];
+
+//# One decoded mnemonic
+#[derive(Clone, Debug, PartialEq)]
+pub struct MnemonicShare {
+    pub identifier: u16,
+    // Whether the identifier was left out of the encryption, so that more shares can be made for the same secret later
+    pub extendable: bool,
+    pub iteration_exponent: u8,
+    pub group_index: u8,
+    pub group_threshold: u8,
+    pub group_count: u8,
+    pub member_index: u8,
+    pub member_threshold: u8,
+    pub value: Vec<u8>,
+}
+
+impl MnemonicShare {
+    pub fn encode(self: &MnemonicShare) -> String {
+        let mut words: Vec<u32> = Vec::new();
+        let id_fields = (self.identifier as u32) << 5 | (self.extendable as u32) << 4 | self.iteration_exponent as u32;
+        let group_fields = (self.group_index as u32) << 16 | (self.group_threshold as u32 - 1) << 12 | (self.group_count as u32 - 1) << 8
+            | (self.member_index as u32) << 4 | (self.member_threshold as u32 - 1);
+        for fields in &[id_fields, group_fields] {
+            words.push(fields >> RADIX_BITS);
+            words.push(fields & 0x3ff);
+        }
+
+        let value_words = (self.value.len() * 8 + RADIX_BITS - 1) / RADIX_BITS;
+        let mut bits = value_words * RADIX_BITS - self.value.len() * 8;
+        let mut buffer: u32 = 0;
+        for byte in &self.value {
+            buffer = (buffer << 8) | *byte as u32;
+            bits += 8;
+            while bits >= RADIX_BITS {
+                bits -= RADIX_BITS;
+                words.push((buffer >> bits) & 0x3ff);
+            }
+            buffer &= (1 << bits) - 1;
+        }
+
+        let checksum = polymod(customization(self.extendable), words.iter().copied().chain(vec![0; CHECKSUM_WORDS])) ^ 1;
+        words.extend((0..CHECKSUM_WORDS).rev().map(|index| (checksum >> (index * RADIX_BITS)) & 0x3ff));
+        return words.iter().map(|word| WORDS[*word as usize]).collect::<Vec<&str>>().join(" ");
+    }
+
+    pub fn parse(mnemonic: &str) -> Result<MnemonicShare, String> {
+        let mut words: Vec<u32> = Vec::new();
+        for (position, word) in mnemonic.split_whitespace().enumerate() {
+            match WORDS.binary_search(&word.to_lowercase().as_str()) {
+                Err(_) => return Err(format!("Word {} (\"{}\") isn't in the SLIP-0039 word list", position + 1, word)),
+                Ok(index) => words.push(index as u32),
+            }
+        }
+        if words.len() < MIN_MNEMONIC_WORDS {
+            return Err(format!("Mnemonic is too short; it should be at least {} words", MIN_MNEMONIC_WORDS));
+        }
+        let value_bits = (words.len() - METADATA_WORDS) * RADIX_BITS;
+        let padding = value_bits % 16;
+        if padding > 8 {
+            return Err(String::from("Mnemonic has the wrong number of words"));
+        }
+
+        let extendable = words[1] >> 4 & 1 == 1;
+        if polymod(customization(extendable), words.iter().copied()) != 1 {
+            return Err(String::from("Mnemonic failed its checksum; check it for typing mistakes"));
+        }
+
+        let id_fields = words[0] << RADIX_BITS | words[1];
+        let group_fields = words[2] << RADIX_BITS | words[3];
+        let group_threshold = (group_fields >> 12 & 0xf) as u8 + 1;
+        let group_count = (group_fields >> 8 & 0xf) as u8 + 1;
+        if group_threshold > group_count {
+            return Err(String::from("Mnemonic has a group threshold greater than its group count"));
+        }
+
+        let mut value: Vec<u8> = Vec::new();
+        let mut buffer: u32 = 0;
+        let mut bits = 0;
+        for (index, word) in words[4..(words.len() - CHECKSUM_WORDS)].iter().enumerate() {
+            buffer = (buffer << RADIX_BITS) | word;
+            bits += RADIX_BITS;
+            if index == 0 {
+                if buffer >> (RADIX_BITS - padding) != 0 {
+                    return Err(String::from("Mnemonic has non-zero padding"));
+                }
+                bits -= padding;
+                buffer &= (1 << bits) - 1;
+            }
+            while bits >= 8 {
+                bits -= 8;
+                value.push((buffer >> bits) as u8);
+                buffer &= (1 << bits) - 1;
+            }
+        }
+
+        return Ok(MnemonicShare {
+            identifier: (id_fields >> 5) as u16,
+            extendable,
+            iteration_exponent: (id_fields & 0xf) as u8,
+            group_index: (group_fields >> 16) as u8,
+            group_threshold,
+            group_count,
+            member_index: (group_fields >> 4 & 0xf) as u8,
+            member_threshold: (group_fields & 0xf) as u8 + 1,
+            value,
+        });
+    }
+}
+
+//# Encrypt master_secret with passphrase and split it among groups, each a (member threshold, member count) pair,
+//# group_threshold of which are needed to recover it; returns the mnemonics of each group
+pub fn split(master_secret: &[u8], passphrase: &[u8], group_threshold: u8, groups: &[(u8, u8)], iteration_exponent: u8, extendable: bool) -> Result<Vec<Vec<String>>, String> {
+    if master_secret.len() < MIN_SECRET_LENGTH || master_secret.len() % 2 != 0 {
+        return Err(format!("The master secret must be an even number of bytes, and at least {}", MIN_SECRET_LENGTH));
+    }
+    if passphrase.iter().any(|byte| !(32..=126).contains(byte)) {
+        return Err(String::from("The passphrase must contain only printable ASCII characters"));
+    }
+    if groups.is_empty() || groups.len() > MAX_SHARE_COUNT || group_threshold < 1 || group_threshold as usize > groups.len() {
+        return Err(format!("The group threshold must be between 1 and the number of groups, which may be at most {}", MAX_SHARE_COUNT));
+    }
+    for (member_threshold, member_count) in groups {
+        if *member_count as usize > MAX_SHARE_COUNT || *member_threshold < 1 || member_threshold > member_count {
+            return Err(format!("Member thresholds must be between 1 and the number of members, which may be at most {}", MAX_SHARE_COUNT));
+        }
+        if *member_threshold == 1 && *member_count > 1 {
+            return Err(String::from("A group with a member threshold of 1 must have only one member"));
+        }
+    }
+    if iteration_exponent > 0xf {
+        return Err(String::from("The iteration exponent must be at most 15"));
+    }
+
+    let identifier = thread_rng().gen::<u16>() & 0x7fff;
+    let encrypted = feistel(master_secret, passphrase, iteration_exponent, identifier, extendable, false);
+    let group_shares = split_secret(group_threshold, groups.len() as u8, &encrypted);
+
+    return Ok(groups.iter().zip(group_shares).map(|((member_threshold, member_count), (group_index, group_secret))| {
+        split_secret(*member_threshold, *member_count, &group_secret).into_iter().map(|(member_index, value)| {
+            let mut share = MnemonicShare {
+                identifier,
+                extendable,
+                iteration_exponent,
+                group_index,
+                group_threshold,
+                group_count: groups.len() as u8,
+                member_index,
+                member_threshold: *member_threshold,
+                value: value.to_vec(),
+            };
+            let mnemonic = share.encode();
+            share.value.zeroize();
+            mnemonic
+        }).collect()
+    }).collect());
+}
+
+//# Recover the master secret from enough mnemonics made by split, or any other SLIP-0039 implementation
+pub fn combine<TMnemonics, TMnemonic>(mnemonics: &TMnemonics, passphrase: &[u8]) -> Result<SecretBuffer, String>
+    where TMnemonics: AsRef<[TMnemonic]> + ?Sized,
+        TMnemonic: AsRef<str> {
+    let mut shares: Vec<MnemonicShare> = Vec::new();
+    for (index, mnemonic) in mnemonics.as_ref().iter().enumerate() {
+        match MnemonicShare::parse(mnemonic.as_ref()) {
+            Err(message) => return Err(format!("Mnemonic {}: {}", index + 1, message)),
+            Ok(share) => shares.push(share),
+        }
+    }
+    if shares.is_empty() {
+        return Err(String::from("Insufficient number of inputs (0)"));
+    }
+
+    let first = &shares[0];
+    if shares.iter().any(|share| share.identifier != first.identifier || share.extendable != first.extendable || share.iteration_exponent != first.iteration_exponent) {
+        return Err(String::from("Input mnemonics come from different splits"));
+    }
+    if shares.iter().any(|share| share.group_threshold != first.group_threshold || share.group_count != first.group_count) {
+        return Err(String::from("Differing group thresholds or counts in input mnemonics"));
+    }
+    if shares.iter().any(|share| share.value.len() != first.value.len()) {
+        return Err(String::from("Input mnemonics are of differing lengths"));
+    }
+
+    let mut groups: BTreeMap<u8, Vec<&MnemonicShare>> = BTreeMap::new();
+    for share in &shares {
+        let group = groups.entry(share.group_index).or_insert_with(Vec::new);
+        if group.iter().any(|other| other.member_threshold != share.member_threshold) {
+            return Err(format!("Differing member thresholds in input mnemonics for group {}", share.group_index + 1));
+        }
+        match group.iter().find(|other| other.member_index == share.member_index) {
+            Some(other) if other.value != share.value => return Err(format!("Input mnemonics for group {} have conflicting members", share.group_index + 1)),
+            Some(_) => {},
+            None => group.push(share),
+        }
+    }
+    if groups.len() != first.group_threshold as usize {
+        return Err(format!("Wrong number of groups ({} of {} required)", groups.len(), first.group_threshold));
+    }
+
+    let mut group_shares: Vec<(u8, SecretBuffer)> = Vec::new();
+    for (group_index, members) in groups {
+        if members.len() != members[0].member_threshold as usize {
+            return Err(format!("Wrong number of mnemonics for group {} ({} of {} required)", group_index + 1, members.len(), members[0].member_threshold));
+        }
+        let member_shares: Vec<(u8, SecretBuffer)> = members.iter().map(|share| (share.member_index, SecretBuffer::from_slice(&share.value))).collect();
+        group_shares.push((group_index, recover_secret(members[0].member_threshold, &member_shares)?));
+    }
+    let encrypted = recover_secret(first.group_threshold, &group_shares)?;
+    return Ok(feistel(&encrypted, passphrase, first.iteration_exponent, first.identifier, first.extendable, true));
+}
+
+fn customization(extendable: bool) -> &'static [u8] {
+    return if extendable { EXTENDABLE_CUSTOMIZATION } else { CUSTOMIZATION };
+}
+
+// RS1024, the BCH code of Bech32 widened to 10 bit words
+fn polymod<T>(customization: &[u8], words: T) -> u32
+    where T: Iterator<Item = u32> {
+    let mut checksum: u32 = 1;
+    for value in customization.iter().map(|byte| *byte as u32).chain(words) {
+        let top = checksum >> 20;
+        checksum = (checksum & 0xfffff) << RADIX_BITS ^ value;
+        for (index, generator) in GENERATOR.iter().enumerate() {
+            if top >> index & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    return checksum;
+}
+
+fn digest(random: &[u8], secret: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(random).expect("HMAC accepts all key sizes");
+    mac.update(secret);
+    return mac.finalize().into_bytes()[..DIGEST_LENGTH].to_vec();
+}
+
+// Shares of secret at x = 0, 1, ...; with a threshold above 1, the polynomial also passes through a digest of the secret,
+// so that recovering it from the wrong shares can be noticed
+fn split_secret(threshold: u8, count: u8, secret: &[u8]) -> Vec<(u8, SecretBuffer)> {
+    if threshold == 1 {
+        return (0..count).map(|index| (index, SecretBuffer::from_slice(secret))).collect();
+    }
+
+    let random_share = |length: usize| {
+        let mut share = SecretBuffer::zeroed(length);
+        thread_rng().fill(share.as_mut());
+        share
+    };
+    let mut shares: Vec<(u8, SecretBuffer)> = (0..(threshold - 2)).map(|index| (index, random_share(secret.len()))).collect();
+    let random = random_share(secret.len() - DIGEST_LENGTH);
+    let mut digest_share = SecretBuffer::from_slice(&digest(&random, secret));
+    digest_share.extend_from_slice(&random);
+
+    let mut base = shares.clone();
+    base.push((DIGEST_INDEX, digest_share));
+    base.push((SECRET_INDEX, SecretBuffer::from_slice(secret)));
+    for index in (threshold - 2)..count {
+        shares.push((index, gf256::interpolate(&base, index)));
+    }
+    return shares;
+}
+
+fn recover_secret(threshold: u8, shares: &[(u8, SecretBuffer)]) -> Result<SecretBuffer, String> {
+    if threshold == 1 {
+        return Ok(shares[0].1.clone());
+    }
+
+    let secret = gf256::interpolate(shares, SECRET_INDEX);
+    let digest_share = gf256::interpolate(shares, DIGEST_INDEX);
+    if digest(&digest_share[DIGEST_LENGTH..], &secret) != digest_share[..DIGEST_LENGTH] {
+        return Err(String::from("Recovered secret failed verification; the mnemonics don't belong together"));
+    }
+    return Ok(secret);
+}
+
+// The four round Feistel cipher over the master secret, with PBKDF2 as its round function
+// Every intermediate value is wiped, as each half is as good as the secret once the passphrase is known
+fn feistel(data: &[u8], passphrase: &[u8], iteration_exponent: u8, identifier: u16, extendable: bool, decrypt: bool) -> SecretBuffer {
+    let mut salt: Vec<u8> = Vec::new();
+    if !extendable {
+        salt.extend_from_slice(CUSTOMIZATION);
+        salt.extend_from_slice(&identifier.to_be_bytes());
+    }
+    let iterations = (BASE_ITERATION_COUNT << iteration_exponent) / ROUND_COUNT as u32;
+
+    let (mut left, mut right) = (data[..(data.len() / 2)].to_vec(), data[(data.len() / 2)..].to_vec());
+    let rounds: Vec<u8> = if decrypt { (0..ROUND_COUNT).rev().collect() } else { (0..ROUND_COUNT).collect() };
+    for round in rounds {
+        let mut password = vec![round];
+        password.extend_from_slice(passphrase);
+        let mut round_salt = salt.clone();
+        round_salt.extend_from_slice(&right);
+        let mut key = vec![0; right.len()];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(&password, &round_salt, iterations, &mut key).expect("HMAC accepts all key sizes");
+
+        let mixed: Vec<u8> = left.iter().zip(key.iter()).map(|(value, key)| value ^ key).collect();
+        for temporary in [&mut password, &mut round_salt, &mut key, &mut left].iter_mut() {
+            temporary.zeroize();
+        }
+        left = right;
+        right = mixed;
+    }
+
+    let mut result = SecretBuffer::with_capacity(data.len());
+    result.extend_from_slice(&right);
+    result.extend_from_slice(&left);
+    left.zeroize();
+    right.zeroize();
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn from_hex(text: &str) -> Vec<u8> {
+        return (0..(text.len() / 2)).map(|index| u8::from_str_radix(&text[(index * 2)..(index * 2 + 2)], 16).unwrap()).collect();
+    }
+
+    // Vectors in the format of the SLIP-0039 reference vectors.json, each a description, mnemonics and the secret
+    // they give with the passphrase TREZOR, or an empty secret if they must be refused; any further fields are ignored
+    #[test]
+    fn test_vectors() {
+        let path = Path::new(file!()).parent().unwrap().parent().unwrap().join("tests").join("data").join("slip39-vectors.json");
+        let vectors: Vec<Vec<serde_json::Value>> = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        for vector in &vectors {
+            let description = vector[0].as_str().unwrap();
+            let mnemonics: Vec<&str> = vector[1].as_array().unwrap().iter().map(|mnemonic| mnemonic.as_str().unwrap()).collect();
+            let secret = vector[2].as_str().unwrap();
+            if secret.is_empty() {
+                assert!(combine(&mnemonics, b"TREZOR").is_err(), "{}", description);
+                continue;
+            }
+            assert_eq!(combine(&mnemonics, b"TREZOR").unwrap().as_slice(), from_hex(secret).as_slice(), "{}", description);
+            for mnemonic in &mnemonics {
+                assert_eq!(MnemonicShare::parse(mnemonic).unwrap().encode(), *mnemonic, "{}", description);
+            }
+        }
+
+        let invalid_checksum = vectors[1][1][0].as_str().unwrap();
+        assert!(combine(&[invalid_checksum], b"TREZOR").unwrap_err().contains("checksum"));
+        let shares = vectors[3][1][0].as_str().unwrap();
+        assert_eq!(combine(&[shares], b"TREZOR").unwrap_err(), "Wrong number of mnemonics for group 1 (1 of 2 required)");
+    }
+
+    // Change the words of mnemonic and put its checksum right, so that only the change can be refused
+    fn with_change<F>(mnemonic: &str, change: F) -> String
+        where F: Fn(&mut Vec<u32>) {
+        let mut words: Vec<u32> = mnemonic.split_whitespace().map(|word| WORDS.binary_search(&word).unwrap() as u32).collect();
+        words.truncate(words.len() - CHECKSUM_WORDS);
+        change(&mut words);
+        let extendable = words[1] >> 4 & 1 == 1;
+        let checksum = polymod(customization(extendable), words.iter().copied().chain(vec![0; CHECKSUM_WORDS])) ^ 1;
+        words.extend((0..CHECKSUM_WORDS).rev().map(|index| (checksum >> (index * RADIX_BITS)) & 0x3ff));
+        return words.iter().map(|word| WORDS[*word as usize]).collect::<Vec<&str>>().join(" ");
+    }
+
+    // Kinds of invalid mnemonic in the reference vectors that tests/data/slip39-vectors.json doesn't hold yet,
+    // made from one of its valid mnemonics
+    #[test]
+    fn test_invalid_fields() {
+        let path = Path::new(file!()).parent().unwrap().parent().unwrap().join("tests").join("data").join("slip39-vectors.json");
+        let vectors: Vec<Vec<serde_json::Value>> = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let valid = vectors[0][1][0].as_str().unwrap();
+        assert_eq!(with_change(valid, |_| ()), valid);
+
+        // A 128-bit secret takes 130 bits of words, and the two left over must be zero
+        let padded = with_change(valid, |words| words[4] |= 0x200);
+        assert_eq!(combine(&[&padded], b"TREZOR").unwrap_err(), "Mnemonic 1: Mnemonic has non-zero padding");
+        let groups = with_change(valid, |words| words[2] |= 0xf << 2);
+        assert_eq!(combine(&[&groups], b"TREZOR").unwrap_err(), "Mnemonic 1: Mnemonic has a group threshold greater than its group count");
+        let short = with_change(valid, |words| { words.pop(); });
+        assert!(combine(&[&short], b"TREZOR").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_groups() {
+        let secret = from_hex("0102030405060708090a0b0c0d0e0f101112131415161718");
+        for extendable in &[false, true] {
+            let groups = split(&secret, b"passphrase", 2, &[(1, 1), (2, 3), (3, 5)], 0, *extendable).unwrap();
+            assert_eq!(groups.iter().map(|group| group.len()).collect::<Vec<usize>>(), vec![1, 3, 5]);
+
+            let mnemonics = vec![&groups[1][2], &groups[2][0], &groups[1][0], &groups[2][4], &groups[2][3]];
+            assert_eq!(combine(&mnemonics, b"passphrase").unwrap().as_slice(), secret.as_slice());
+            assert!(combine(&mnemonics, b"wrong").unwrap().as_slice() != secret.as_slice());
+            assert_eq!(combine(&[&groups[0][0], &groups[1][1], &groups[1][2]], b"passphrase").unwrap().as_slice(), secret.as_slice());
+            assert!(combine(&mnemonics[..4], b"passphrase").is_err());
+        }
+
+        assert!(split(&secret[..14], b"", 1, &[(1, 1)], 0, false).is_err());
+        assert!(split(&secret, b"", 1, &[(1, 2)], 0, false).is_err());
+        assert!(split(&secret, b"", 2, &[(1, 1)], 0, false).is_err());
+    }
+}
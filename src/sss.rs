@@ -1,22 +1,60 @@
 extern crate rand;
-extern crate modulo;
-extern crate num_bigint;
-extern crate num_traits;
+extern crate zeroize;
 
+use std::ffi::{OsStr, OsString};
 use std::fs::File;
-use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
 use std::convert::TryInto;
 
+use age::Identity;
+use ed25519_dalek::SigningKey;
 use rand::prelude::*;
-use modulo::Mod;
-use num_bigint::{BigInt, ToBigInt};
-use num_traits::ToPrimitive;
-use num_traits::identities::{Zero, One};
-
-pub const VERSION: i32 = 1;
-const BUFFER_SIZE: usize = 8192;
-const MAX_SECRET_FILENAME_LENGTH: usize = BUFFER_SIZE - 50;
+use zeroize::Zeroize;
+
+use crate::archive;
+use crate::armor;
+use crate::armor::{ArmorWriter, PieceReader, PieceWriter};
+use crate::field;
+use crate::field::Field;
+use crate::gfshare;
+use crate::hiss;
+use crate::recipient;
+use crate::recipient::{PieceSink, PieceSource};
+
+use crate::header::Header;
+use crate::metadata::Metadata;
+use crate::output;
+use crate::output::OutputFile;
+use crate::parity::{Layout, ParityDecoder, ParityEncoder};
+use crate::passphrase;
+use crate::secret;
+use crate::secret::{SecretBuffer, SecretString};
+use crate::progress;
+use crate::progress::{Control, Phase, Progress};
+use crate::share::{Encoding, TextShare};
+use crate::signature;
+use crate::signature::{SignWriter, SignedReader};
+use crate::slip39;
+use crate::ssss;
+use crate::stream;
+use crate::vault;
+
+pub const VERSION: i32 = 5;
+pub const BUFFER_SIZE: usize = 8192;
+pub const MAX_SECRET_FILENAME_LENGTH: usize = BUFFER_SIZE - 50;
+// Header extension identifying the pieces generated together, so pieces from different splits aren't mixed
+pub const SET_ID_KEY: &str = "set";
+// Header extension recording how many pieces are required, so combining knows how many damaged pieces it can do without
+pub const REQUIRED_KEY: &str = "required";
+//# Placeholders for GenerateOptions::name_template:
+//# {stem}  the secret's file name, without its extension
+//# {index} the piece's index, starting at 1
+//# {total} the number of pieces
+//# {set}   the identifier shared by all the pieces
+//# {label} the piece's entry in GenerateOptions::labels
+pub const DEFAULT_NAME_TEMPLATE: &str = "{stem}-{index}.shard";
+pub const DEFAULT_ARMORED_NAME_TEMPLATE: &str = "{stem}-{index}.shard.txt";
 
 #[allow(unused_mut)]
 pub fn generate_string<TCollection, TProgress>(secret: &TCollection, pieces_count: i32, required_pieces_count: i32, prime: i32, mut progress_callback: TProgress) -> Vec<(i32, Vec<u8>)>
@@ -27,16 +65,81 @@ pub fn generate_string<TCollection, TProgress>(secret: &TCollection, pieces_coun
     }).collect();
 }
 
-fn open_file<P: AsRef<Path>>(path: P) -> Result<File, String> {
-    return match File::open(&path) {
-        Err(error) => Err(format!("Error opening {}: {}", path.as_ref().to_str().unwrap(), error)),
-        Ok(file) => Ok(file),
+//# Split secret into self-describing text shares, written out in encoding; see share::TextShare
+pub fn generate_text_shares<TCollection, TProgress>(secret: &TCollection, pieces_count: i32, required_pieces_count: i32, prime: i32, encoding: Encoding, progress_callback: TProgress) -> Result<Vec<String>, String>
+    where TCollection: AsRef<[u8]> + ?Sized,
+        TProgress: FnMut(f64) {
+    let set_id = generate_set_id();
+    return generate_string(secret, pieces_count, required_pieces_count, prime, progress_callback).into_iter().map(|piece| {
+        TextShare { set_id: set_id.clone(), index: piece.0, threshold: required_pieces_count, prime, data: piece.1 }.encode_as(encoding)
+    }).collect::<Result<Vec<String>, String>>();
+}
+
+//# Options for generate_mnemonics_with_options
+#[derive(Clone, Debug, Default)]
+pub struct MnemonicOptions {
+    // Each step doubles the work of turning the passphrase into a key, from 10000 iterations of PBKDF2; at most 15
+    pub iteration_exponent: u8,
+    // Leave the split's identifier out of the encryption, so that further splits of the same secret are possible
+    pub extendable: bool,
+}
+
+//# Split secret into SLIP-0039 mnemonics, encrypted with passphrase, for hardware wallets; see slip39
+//# groups holds the required and total number of mnemonics of each group, of which group_threshold are needed.
+//# Returns the mnemonics of each group.
+pub fn generate_mnemonics<TCollection>(secret: &TCollection, group_threshold: i32, groups: &[(i32, i32)], passphrase: &str) -> Result<Vec<Vec<String>>, String>
+    where TCollection: AsRef<[u8]> + ?Sized {
+    return generate_mnemonics_with_options(secret, group_threshold, groups, passphrase, &MnemonicOptions::default());
+}
+
+pub fn generate_mnemonics_with_options<TCollection>(secret: &TCollection, group_threshold: i32, groups: &[(i32, i32)], passphrase: &str, options: &MnemonicOptions) -> Result<Vec<Vec<String>>, String>
+    where TCollection: AsRef<[u8]> + ?Sized {
+    let count = |value: i32| -> Result<u8, String> {
+        return if value >= 1 && value <= slip39::MAX_SHARE_COUNT as i32 {
+            Ok(value as u8)
+        } else {
+            Err(format!("Groups and their members number between 1 and {}", slip39::MAX_SHARE_COUNT))
+        }
+    };
+    let mut my_groups: Vec<(u8, u8)> = Vec::new();
+    for (required, total) in groups {
+        my_groups.push((count(*required)?, count(*total)?));
     }
+    return slip39::split(secret.as_ref(), passphrase.as_bytes(), count(group_threshold)?, &my_groups, options.iteration_exponent, options.extendable);
+}
+
+//# Options for generate_ssss_shares_with_options and interpolate_ssss_shares_with_options
+#[derive(Clone, Debug, Default)]
+pub struct SsssOptions {
+    // Prefix for each share, as ssss-split -w
+    pub token: Option<String>,
+    // Size of the field in bits, a multiple of 8, as ssss-split -s; the length of the secret if None
+    pub security_level: Option<usize>,
+    // Leave out the diffusion layer, as ssss-split -D and ssss-combine -D
+    pub skip_diffusion: bool,
+}
+
+//# Split secret into shares for ssss-combine, the Linux ssss tool; see ssss
+pub fn generate_ssss_shares<TCollection>(secret: &TCollection, pieces_count: i32, required_pieces_count: i32) -> Result<Vec<String>, String>
+    where TCollection: AsRef<[u8]> + ?Sized {
+    return generate_ssss_shares_with_options(secret, pieces_count, required_pieces_count, &SsssOptions::default());
+}
+
+pub fn generate_ssss_shares_with_options<TCollection>(secret: &TCollection, pieces_count: i32, required_pieces_count: i32, options: &SsssOptions) -> Result<Vec<String>, String>
+    where TCollection: AsRef<[u8]> + ?Sized {
+    return ssss::split(secret.as_ref(), pieces_count.max(0) as u32, required_pieces_count.max(0) as u32, options.token.as_deref(), options.security_level, !options.skip_diffusion);
 }
 
-fn create_file<P: AsRef<Path>>(path: P) -> Result<File, String> {
-    return match File::create(&path) {
-        Err(error) => Err(format!("Error opening {}: {}", path.as_ref().to_str().unwrap(), error)),
+//# Split secret into Vault unseal key shares, in base64 as Vault shows them; see vault
+pub fn generate_vault_shares<TCollection>(secret: &TCollection, pieces_count: i32, required_pieces_count: i32) -> Result<Vec<String>, String>
+    where TCollection: AsRef<[u8]> + ?Sized {
+    let shares = vault::split(secret.as_ref(), pieces_count.max(0) as usize, required_pieces_count.max(0) as usize)?;
+    return Ok(shares.iter().map(|share| vault::encode(share)).collect());
+}
+
+fn open_file<P: AsRef<Path>>(path: P) -> Result<File, String> {
+    return match File::open(&path) {
+        Err(error) => Err(format!("Error opening {}: {}", path.as_ref().display(), error)),
         Ok(file) => Ok(file),
     }
 }
@@ -44,93 +147,360 @@ fn create_file<P: AsRef<Path>>(path: P) -> Result<File, String> {
 fn get_file_size<P: AsRef<Path>>(path: P) -> Result<f64, String> {
     let my_path = path.as_ref();
     return match my_path.metadata() {
-        Err(error) => Err(format!("Error getting file size for {}: {}", my_path.to_str().unwrap(), error)),
+        Err(error) => Err(format!("Error getting file size for {}: {}", my_path.display(), error)),
         Ok(metadata) => Ok(metadata.len() as f64),
     }
 }
 
+//# Options for generate_file_with_options
+#[derive(Clone, Debug, Default)]
+pub struct GenerateOptions {
+    // Replace existing pieces with the same names instead of failing
+    pub overwrite: bool,
+    // Record the user and group owning the secret file
+    pub record_owner: bool,
+    // Record the secret file's extended attributes
+    pub record_xattrs: bool,
+    // Where to write the pieces: next to the secret if empty, all in one directory if it has a
+    // single entry, and otherwise one entry per piece, e.g. one for each of several mounted drives
+    pub output_directories: Vec<PathBuf>,
+    // File name for each piece; DEFAULT_NAME_TEMPLATE, or DEFAULT_ARMORED_NAME_TEMPLATE, if None
+    pub name_template: Option<String>,
+    // Labels for the holders of each piece, for the {label} placeholder; empty, or one per piece
+    pub labels: Vec<String>,
+    // Reed–Solomon parity to add to each piece, as a percentage of its size, so that it can be
+    // repaired on its own; see parity::Layout
+    pub parity_overhead: u32,
+    // Write the pieces as text that can be emailed or pasted; see armor
+    pub armor: bool,
+    // Write the pieces for gfcombine from libgfshare instead, which records no metadata, and names
+    // them after the secret with the extensions gfcombine needs; see gfshare
+    pub gfshare: bool,
+    // Write the pieces in the format of Hiss instead, which records no metadata; see hiss
+    pub hiss: bool,
+    // age recipients to encrypt each piece to, so only its holder can read it; empty, or one per piece; see recipient
+    pub recipients: Vec<String>,
+    // Passphrases to lock each piece with instead, if any; empty, or one per piece; see passphrase
+    pub passphrases: Vec<Option<SecretString>>,
+    // Dealer key file to sign each piece with, so holders can tell it came from the dealer; see signature
+    pub dealer_key: Option<PathBuf>,
+    // Identifier shared by all the pieces, telling them apart from those of other splits; random if None
+    pub set_id: Option<String>,
+}
+
 //# Process a secret file and generate an output file per piece
-//# Format:
-//# version\n          (text)
-//# pieceIndex\n       (text)
-//# prime\n            (text)
-//# originalFilename\n (text)
-//# raw binary data
-pub fn generate_file<T>(secret_file_name: &str, pieces_count: i32, required_pieces_count: i32, prime: i32, mut progress_callback: T) -> Result<Vec<String>, String>
-    where T: FnMut(f64) {
-    let parse_error = format!("Error parsing file name: {}", secret_file_name);
-    let secret_path = Path::new(secret_file_name);
-    let secret_file = open_file(secret_file_name)?;
-    let mut progress: f64 = 0.0;
-
-
-    let basename: String;
-    match secret_path.file_name() {
-        None => return Err(parse_error),
-        Some(path) => basename = String::from(path.to_str().unwrap()),
-    }
-    let total_progress = get_file_size(secret_file_name)?;
-
-    let piece_names: Vec<PathBuf> = (0..pieces_count).map(|index| {
-        secret_path.with_file_name(format!("{}-{}.shard", secret_path.file_stem().unwrap().to_str().unwrap(), index + 1).as_str())
-    }).collect();
+//# See header::Header for the format of the text header preceding the raw binary data, and
+//# metadata::Metadata for the file metadata recorded in it
+pub fn generate_file<P, TProgress, TControl>(secret_file_name: P, pieces_count: i32, required_pieces_count: i32, prime: i32, progress_callback: TProgress) -> Result<Vec<PathBuf>, String>
+    where P: AsRef<Path>,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    return generate_file_with_options(secret_file_name, pieces_count, required_pieces_count, prime, &GenerateOptions::default(), progress_callback);
+}
+
+//# Pieces only appear once they are all complete, and none are left behind if the split fails
+//# or progress_callback cancels it
+pub fn generate_file_with_options<P, TProgress, TControl>(secret_file_name: P, pieces_count: i32, required_pieces_count: i32, prime: i32, options: &GenerateOptions, mut progress_callback: TProgress) -> Result<Vec<PathBuf>, String>
+    where P: AsRef<Path>,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    let secret_path = secret_file_name.as_ref();
+    let mut secret_file = open_file(secret_path)?;
+
+    let (basename, stem) = match (secret_path.file_name(), secret_path.file_stem()) {
+        (Some(basename), Some(stem)) => (basename, stem),
+        _ => return Err(format!("Error parsing file name: {}", secret_path.display())),
+    };
+    let total_progress = get_file_size(secret_path)? as u64;
+    if options.gfshare {
+        return generate_gfshare_file(&mut secret_file, secret_path, basename, pieces_count, required_pieces_count, options, |progress: &Progress| {
+            progress_callback(&Progress { total_bytes: Some(total_progress), ..progress.clone() })
+        });
+    }
+
+    let set_id = options.set_id.clone().unwrap_or_else(generate_set_id);
+    if options.hiss {
+        if options.armor || options.parity_overhead > 0 || !options.recipients.is_empty() || !options.passphrases.is_empty() || options.dealer_key.is_some() {
+            return Err(String::from("Hiss pieces can't be armored, have parity, be encrypted, or be signed"));
+        }
+        let mut piece_files = create_piece_files(secret_path, stem, pieces_count, &set_id, options)?;
+        hiss::split(&mut secret_file, &mut piece_files, required_pieces_count, prime, basename, |progress: &Progress| {
+            progress_callback(&Progress { total_bytes: Some(total_progress), ..progress.clone() })
+        })?;
+        return commit_files(piece_files);
+    }
+
+    let mut header = Header::new(0, prime, basename);
+    header.add_extension(SET_ID_KEY, set_id.clone());
+    Metadata::read(secret_path, options.record_owner, options.record_xattrs)?.add_to_header(&mut header)?;
+    if let Some(layout) = Layout::for_overhead(options.parity_overhead)? {
+        layout.add_to_header(&mut header);
+    }
+    let dealer_key = options.dealer_key.as_ref().map(signature::read_key).transpose()?;
+    if let Some(ref key) = dealer_key {
+        signature::add_to_header(key, &mut header);
+    }
+
+    let piece_files = create_piece_files(secret_path, stem, pieces_count, &set_id, options)?;
+    let mut piece_writers = piece_writers(piece_files, &header, required_pieces_count, options, dealer_key.as_ref())?;
+
+    stream::split_with_header(&mut secret_file, &mut piece_writers, required_pieces_count, &header, |progress: &Progress| {
+        progress_callback(&Progress { total_bytes: Some(total_progress), ..progress.clone() })
+    })?;
 
-    let mut piece_files: Vec<File> = Vec::new();
+    return commit_files(finish_piece_writers(piece_writers)?);
+}
+
+fn generate_gfshare_file<TProgress, TControl>(secret_file: &mut File, secret_path: &Path, basename: &OsStr, pieces_count: i32, required_pieces_count: i32, options: &GenerateOptions, progress_callback: TProgress) -> Result<Vec<PathBuf>, String>
+    where TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    if options.armor || options.parity_overhead > 0 || options.name_template.is_some() || !options.recipients.is_empty() || !options.passphrases.is_empty() || options.dealer_key.is_some() {
+        return Err(String::from("gfshare pieces can't be armored, have parity, be named by a template, be encrypted, or be signed"));
+    }
+    let share_numbers = gfshare::generate_share_numbers(std::cmp::max(pieces_count, 0) as usize)?;
+    let count = share_numbers.len();
+    let directories = &options.output_directories;
+    if directories.len() > 1 && directories.len() != count {
+        return Err(format!("Expected 1 or {} output directories, got {}", count, directories.len()));
+    }
+
+    let mut piece_files: Vec<OutputFile> = Vec::new();
+    for (index, share_number) in share_numbers.iter().enumerate() {
+        let name = gfshare::piece_name(basename, *share_number);
+        let path = match directories.len() {
+            0 => secret_path.with_file_name(name),
+            1 => directories[0].join(name),
+            _ => directories[index].join(name),
+        };
+        piece_files.push(OutputFile::create(&path, options.overwrite)?);
+    }
+
+    gfshare::split(secret_file, &mut piece_files, &share_numbers, std::cmp::max(required_pieces_count, 0) as usize, progress_callback)?;
+    return commit_files(piece_files);
+}
+
+//# Process a set of files and directories as a single secret, generating an output file per piece
+//# The secret is a tar archive of secret_paths, each stored under its own name, and is recorded in the
+//# pieces under the name of archive_path, next to which the pieces are written.
+//# Reconstructing the pieces extracts the archive; see archive::extract_archive.
+pub fn generate_archive<T, P, Q, TProgress, TControl>(secret_paths: &T, archive_path: Q, pieces_count: i32, required_pieces_count: i32, prime: i32, progress_callback: TProgress) -> Result<Vec<PathBuf>, String>
+    where T: AsRef<[P]> + ?Sized,
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    return generate_archive_with_options(secret_paths, archive_path, pieces_count, required_pieces_count, prime, &GenerateOptions::default(), progress_callback);
+}
+
+//# Metadata for each file is kept in the archive itself, so options don't record any
+pub fn generate_archive_with_options<T, P, Q, TProgress, TControl>(secret_paths: &T, archive_path: Q, pieces_count: i32, required_pieces_count: i32, prime: i32, options: &GenerateOptions, mut progress_callback: TProgress) -> Result<Vec<PathBuf>, String>
+    where T: AsRef<[P]> + ?Sized,
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    if options.gfshare || options.hiss {
+        return Err(String::from("gfshare and Hiss pieces can't hold an archive, as they can't record that they do"));
+    }
+    let my_archive_path = archive_path.as_ref();
+    let (basename, stem) = match (my_archive_path.file_name(), my_archive_path.file_stem()) {
+        (Some(basename), Some(stem)) => (basename, stem),
+        _ => return Err(format!("Error parsing file name: {}", my_archive_path.display())),
+    };
+    // Archive headers and padding make the secret slightly larger than this
+    let total_progress = archive::content_size(secret_paths);
+
+    let set_id = options.set_id.clone().unwrap_or_else(generate_set_id);
+    let mut header = Header::new(0, prime, basename);
+    header.add_extension(SET_ID_KEY, set_id.clone());
+    header.add_extension(archive::ARCHIVE_KEY, String::from(archive::TAR));
+    if let Some(layout) = Layout::for_overhead(options.parity_overhead)? {
+        layout.add_to_header(&mut header);
+    }
+    let dealer_key = options.dealer_key.as_ref().map(signature::read_key).transpose()?;
+    if let Some(ref key) = dealer_key {
+        signature::add_to_header(key, &mut header);
+    }
+
+    let piece_files = create_piece_files(my_archive_path, stem, pieces_count, &set_id, options)?;
+    let mut piece_writers = piece_writers(piece_files, &header, required_pieces_count, options, dealer_key.as_ref())?;
+
+    let mut writer = stream::SplitWriter::new(&mut piece_writers, required_pieces_count, &header, |progress: &Progress| {
+        progress_callback(&Progress { total_bytes: Some(total_progress), ..progress.clone() }).into()
+    })?;
+    let result = archive::write_archive(secret_paths, &mut writer).map(|_| ());
+    // Report why the pieces couldn't be written, rather than how that looked to the archive
+    if let Some(message) = writer.error() {
+        return Err(String::from(message));
+    }
+    result?;
+    writer.finish()?;
+
+    return commit_files(finish_piece_writers(piece_writers)?);
+}
+
+//# A random identifier for a set of pieces, as recorded in their headers and text shares
+pub fn generate_set_id() -> String {
+    return format!("{:016x}", thread_rng().gen::<u64>());
+}
+
+// Create an output file for each piece of the secret at path, named and placed according to options
+fn create_piece_files(path: &Path, stem: &OsStr, pieces_count: i32, set_id: &str, options: &GenerateOptions) -> Result<Vec<OutputFile>, String> {
+    let piece_names = piece_paths(path, stem, pieces_count, set_id, options)?;
+
+    // Fail before doing any work if we would have to replace a piece
     for path in &piece_names {
-        piece_files.push(create_file(path)?);
+        output::check_destination(path, options.overwrite)?;
     }
 
-    // Actual writing begins here
-    for index in 0..piece_files.len() {
-        // Write header
-        let file = &piece_files[index];
-        write_file(file, &format!("{}\n", VERSION))?;
-        write_file(file, &format!("{}\n", index + 1))?;
-        write_file(file, &format!("{}\n", prime))?;
-        write_file(file, &format!("{}\n", basename))?;
+    let mut piece_files: Vec<OutputFile> = Vec::new();
+    for path in &piece_names {
+        piece_files.push(OutputFile::create(path, options.overwrite)?);
     }
 
-    let mut buffer = [0 as u8; BUFFER_SIZE];
-    let mut length: usize;
+    return Ok(piece_files);
+}
 
-    loop {
-        length = read_file(&secret_file, &mut buffer[..])?;
-        if length == 0 {
-            break;
+fn piece_paths(path: &Path, stem: &OsStr, pieces_count: i32, set_id: &str, options: &GenerateOptions) -> Result<Vec<PathBuf>, String> {
+    let count = std::cmp::max(pieces_count, 0) as usize;
+    let directories = &options.output_directories;
+    if directories.len() > 1 && directories.len() != count {
+        return Err(format!("Expected 1 or {} output directories, got {}", count, directories.len()));
+    }
+    if !options.labels.is_empty() && options.labels.len() != count {
+        return Err(format!("Expected {} labels, got {}", count, options.labels.len()));
+    }
+    let default_template = if options.armor { DEFAULT_ARMORED_NAME_TEMPLATE } else { DEFAULT_NAME_TEMPLATE };
+    let template = options.name_template.as_ref().map(|template| template.as_str()).unwrap_or(default_template);
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for index in 0..count {
+        let label = options.labels.get(index).map(|label| label.as_str()).unwrap_or("");
+        let name = expand_name_template(template, stem, index + 1, count, set_id, label)?;
+        let piece_path = match directories.len() {
+            0 => path.with_file_name(name),
+            1 => directories[0].join(name),
+            _ => directories[index].join(name),
+        };
+        if paths.contains(&piece_path) {
+            return Err(format!("Name template {} gives more than one piece the name {}", template, piece_path.display()));
         }
+        paths.push(piece_path);
+    }
+
+    return Ok(paths);
+}
 
-        let result = generate_string(&buffer[0..length], pieces_count, required_pieces_count, prime, |_|{});
-        for index in 0..result.len() {
-            // Write bodies
-            write_file(&piece_files[index], &result[index].1)?;
+fn expand_name_template(template: &str, stem: &OsStr, index: usize, total: usize, set_id: &str, label: &str) -> Result<OsString, String> {
+    let mut name = OsString::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        name.push(&rest[..start]);
+        let end = match rest[start..].find('}') {
+            None => return Err(format!("Unterminated placeholder in name template {}", template)),
+            Some(end) => start + end,
+        };
+        match &rest[(start + 1)..end] {
+            "stem" => name.push(stem),
+            "index" => name.push(index.to_string()),
+            "total" => name.push(total.to_string()),
+            "set" => name.push(set_id),
+            "label" => name.push(label),
+            placeholder => return Err(format!("Unknown placeholder {{{}}} in name template {}", placeholder, template)),
         }
-        progress += length as f64;
-        progress_callback(progress / total_progress);
+        rest = &rest[(end + 1)..];
     }
+    name.push(rest);
 
-    return Result::Ok(piece_names.iter().map(|path| String::from(path.to_str().unwrap())).collect());
+    // Each name has to be a single file name, so pieces can't end up outside their directory
+    let mut components = Path::new(&name).components();
+    return match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(name),
+        _ => Err(format!("Name template {} gives the invalid file name {}", template, name.to_string_lossy())),
+    }
 }
 
-fn read_file<T>(mut file: &File, data: &mut T) -> Result<usize, String>
-    where T: AsMut<[u8]> + ?Sized {
-    return match file.read(data.as_mut()) {
-        Err(error) => Err(format!("Error reading file: {}", error)),
-        Ok(read) => Ok(read),
+// Encrypt each piece file to its holder, or wrap it in armor, if options ask for it, and sign it with dealer_key if given
+fn piece_writers(files: Vec<OutputFile>, template: &Header, required_pieces_count: i32, options: &GenerateOptions, dealer_key: Option<&SigningKey>) -> Result<Vec<SignWriter<PieceWriter<PieceSink<OutputFile>>>>, String> {
+    let recipients = options.recipients.iter().map(|recipient| recipient::parse_recipient(recipient)).collect::<Result<Vec<_>, String>>()?;
+    if !recipients.is_empty() && recipients.len() != files.len() {
+        return Err(format!("Expected {} recipients, got {}", files.len(), recipients.len()));
+    }
+    if !options.passphrases.is_empty() && options.passphrases.len() != files.len() {
+        return Err(format!("Expected {} passphrases, got {}", files.len(), options.passphrases.len()));
     }
+    // Armor would be lost inside the encryption
+    if options.armor && (!recipients.is_empty() || options.passphrases.iter().any(|passphrase| passphrase.is_some())) {
+        return Err(String::from("Encrypted pieces can't be armored"));
+    }
+
+    return files.into_iter().enumerate().map(|(index, file)| {
+        let sink = PieceSink::new(file, recipients.get(index), options.passphrases.get(index).and_then(|passphrase| passphrase.as_ref()))?;
+        if !options.armor {
+            return Ok(SignWriter::new(PieceWriter::Binary(sink), dealer_key));
+        }
+        let fields = armor::header_fields(&stream::piece_header(template, index as i32 + 1, required_pieces_count));
+        return Ok(SignWriter::new(PieceWriter::Armored(ArmorWriter::new(sink, &fields)?), dealer_key));
+    }).collect();
 }
 
-fn write_file<T>(mut file: &File, data: &T) -> Result<usize, String>
-    where T: AsRef<[u8]> + ?Sized {
-    return match file.write(data.as_ref()) {
-        Err(error) => Err(format!("Error writing file: {}", error)),
-        Ok(written) => Ok(written),
+fn finish_piece_writers(writers: Vec<SignWriter<PieceWriter<PieceSink<OutputFile>>>>) -> Result<Vec<OutputFile>, String> {
+    return writers.into_iter().map(|writer| writer.finish()?.finish()?.finish()).collect();
+}
+
+// Move a set of output files into place, removing those already in place if one of them fails
+// Files being replaced are moved aside first, and put back if one fails, so that a failure never loses pieces
+// that were there before
+fn commit_files(files: Vec<OutputFile>) -> Result<Vec<PathBuf>, String> {
+    let mut committed: Vec<PathBuf> = Vec::new();
+    let mut replaced: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut result: Result<(), String> = Ok(());
+    for file in files {
+        let path = file.path().to_path_buf();
+        if file.overwrite() && path.symlink_metadata().is_ok() {
+            let aside = match output::hidden_path(&path, "orig") {
+                Err(message) => {
+                    result = Err(message);
+                    break;
+                },
+                Ok(aside) => aside,
+            };
+            if let Err(error) = std::fs::rename(&path, &aside) {
+                result = Err(format!("Error moving {} aside: {}", path.display(), error));
+                break;
+            }
+            replaced.push((path, aside));
+        }
+        match file.commit() {
+            Err(message) => {
+                result = Err(message);
+                break;
+            },
+            Ok(path) => committed.push(path),
+        }
     }
+
+    if let Err(message) = result {
+        for path in &committed {
+            let _ = std::fs::remove_file(path);
+        }
+        for (path, aside) in &replaced {
+            let _ = std::fs::rename(aside, path);
+        }
+        return Err(message);
+    }
+    for (_, aside) in &replaced {
+        let _ = std::fs::remove_file(aside);
+    }
+    return Ok(committed);
 }
 
-fn validate_piece_files<T>(piece_files: &T) -> Result<(), String>
-    where T: AsRef<[String]> {
+fn validate_piece_files<T, P>(piece_files: &T) -> Result<(), String>
+    where T: AsRef<[P]> + ?Sized,
+        P: AsRef<Path> {
     let files = piece_files.as_ref();
-    let length= get_file_size(&files[0])?;
+    let length = get_file_size(&files[0])?;
     for file in &files[1..] {
         if get_file_size(file)? != length {
             return Err(format!("Mismatching file lengths: {} vs. {}", length, get_file_size(file)?));
@@ -140,95 +510,66 @@ fn validate_piece_files<T>(piece_files: &T) -> Result<(), String>
     return Ok(());
 }
 
-fn validate_header<TInts, TStrings>(versions: &TInts, indices: &TInts, primes: &TInts, buffers: &Vec<[u8; BUFFER_SIZE]>, filenames: &TStrings) -> Result<(), String>
-    where TInts: AsRef<[i32]> + ?Sized,
-        TStrings: AsRef<[String]> + ?Sized,
-        {
+pub fn validate_header<T>(headers: &T) -> Result<(), String>
+    where T: AsRef<[Header]> + ?Sized {
     // TODO: More detailed error messages
+    let my_headers = headers.as_ref();
 
-    if versions.as_ref().iter().any(|version| *version != VERSION) {
+    // Pieces written by earlier versions can still be read, as long as they all agree
+    if my_headers.iter().any(|header| header.version < 1 || header.version > VERSION || header.version != my_headers[0].version) {
         return Err(String::from("Invalid versions for input files"));
     }
 
-    let my_indices = indices.as_ref();
+    let my_indices: Vec<i32> = my_headers.iter().map(|header| header.index).collect();
     if (1..my_indices.len()).any(|i| my_indices[i..].iter().any(|value| *value == my_indices[i - 1])) {
         return Err(String::from("Duplicate indices in input files"));
     }
 
-    let my_primes = primes.as_ref();
-    let prime = my_primes[0];
-    if my_primes.iter().any(|value| *value != prime) {
+    let prime = my_headers[0].prime;
+    if my_headers.iter().any(|header| header.prime != prime) {
         return Err(String::from("Differing primes in input files"));
     }
 
-    let my_filenames = filenames.as_ref();
-    let filename = &my_filenames[0];
+    let filename = &my_headers[0].filename;
     if filename.len() > MAX_SECRET_FILENAME_LENGTH {
         return Err(format!("Original filenames are too long: {}", filename.len()));
     }
-    if my_filenames.iter().any(|value| value != filename) {
+    if my_headers.iter().any(|header| &header.filename != filename) {
         return Err(String::from("Differing filenames in input files"));
     }
 
-    if buffers.iter().any(|buffer| buffer.as_ref().len() % 2 != 0) {
-        return Err(String::from("Input buffer has invalid (odd) length"));
+    let set_id = my_headers[0].extension(SET_ID_KEY);
+    if my_headers.iter().any(|header| header.extension(SET_ID_KEY) != set_id) {
+        return Err(String::from("Input files come from different splits"));
     }
 
-    if buffers.len() != my_indices.len() {
-        return Err(String::from("Internal error reading header"));
+    if my_headers.iter().any(|header| header.extensions != my_headers[0].extensions) {
+        return Err(String::from("Differing metadata in input files"));
     }
 
     return Ok(());
 }
 
-pub fn read_headers<T>(pieces: &T) -> Result<(i32, String, Vec<i32>, Vec<[u8; BUFFER_SIZE]>, usize), String>
-    where T: AsRef<[File]> {
-
-    let mut indices: Vec<i32> = Vec::new();
-    let mut filenames: Vec<String> = Vec::new();
-    let mut primes: Vec<i32> = Vec::new();
-    let mut versions: Vec<i32> = Vec::new();
-    let mut buffers: Vec<[u8; BUFFER_SIZE]> = Vec::new();
-    let mut buffer_length = 0 as usize;
-
-    for piece in pieces.as_ref() {
-        let mut header = [0 as u8; BUFFER_SIZE];
-        read_file(&piece, &mut header[..])?;
+//# Read and validate the header of each piece, leaving each positioned at the start of its body
+pub fn read_headers<R, TProgress, TControl>(pieces: &mut [R], mut progress_callback: TProgress) -> Result<Vec<Header>, String>
+    where R: Read,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    if pieces.is_empty() {
+        return Err(String::from("Insufficient number of inputs (0)"));
+    }
 
-        let headers: Vec<&[u8]> = header.splitn(5, |byte| *byte == '\n' as u8).collect();
-        if headers.len() < 5 {
-            return Err(String::from("Malformed header in input file"));
-        }
-        match String::from_utf8_lossy(headers[0]).into_owned().parse::<i32>() {
-            Err(error) => return Err(format!("Error parsing header version: {}", error)),
-            Ok(version) => versions.push(version),
-        }
-        match String::from_utf8_lossy(headers[1]).into_owned().parse::<i32>() {
-            Err(error) => return Err(format!("Error parsing header version: {}", error)),
-            Ok(index) => indices.push(index),
-        }
-        match String::from_utf8_lossy(headers[2]).into_owned().parse::<i32>() {
-            Err(error) => return Err(format!("Error parsing header prime: {}", error)),
-            Ok(prime) => primes.push(prime),
-        }
-        filenames.push(String::from_utf8_lossy(headers[3]).into_owned());
-
-        let mut buffer = [0 as u8; BUFFER_SIZE];
-        buffer[0..headers[4].len()].copy_from_slice(headers[4]);
-        let read = read_file(&piece, &mut buffer[headers[4].len()..])?;
-        if buffer_length == 0 {
-            buffer_length = headers[4].len() + read;
-        } else if headers[4].len() + read != buffer_length {
-            return Err(format!("Mismatched buffer sizes in input files"));
-        }
-        buffers.push(buffer);
+    let mut headers: Vec<Header> = Vec::new();
+    for (index, piece) in pieces.iter_mut().enumerate() {
+        progress::report(&mut progress_callback, Progress::new(Phase::ReadingHeaders, 0, None, Some(index)))?;
+        headers.push(Header::read(piece)?);
     }
-    validate_header(&versions, &indices, &primes, &buffers, &filenames)?;
+    validate_header(&headers)?;
 
-    return Ok((primes[0], filenames[0].clone(), indices, buffers, buffer_length));
+    return Ok(headers);
 }
 
-fn binary_buffer_to_points<T>(buffer: &T) -> Vec<i16>
+pub fn binary_buffer_to_points<T>(buffer: &T) -> Vec<i16>
     where T: AsRef<[u8]> + ?Sized {
     let my_buffer = buffer.as_ref();
     return (0..(my_buffer.len() / 2)).map(|input_index| {
@@ -238,7 +579,7 @@ fn binary_buffer_to_points<T>(buffer: &T) -> Vec<i16>
 }
 
 #[allow(unused_mut)]
-pub fn interpolate_string<TPiecesCollection, TBytesCollection, TCallback>(pieces: &TPiecesCollection, prime: i32, mut progress_callback: TCallback) -> Result<String, String>
+pub fn interpolate_string<TPiecesCollection, TBytesCollection, TCallback>(pieces: &TPiecesCollection, prime: i32, mut progress_callback: TCallback) -> Result<SecretString, String>
     where TCallback: FnMut(f64),
         TPiecesCollection: AsRef<[(i32, TBytesCollection)]> + ?Sized,
         TBytesCollection: AsRef<[u8]> {
@@ -246,157 +587,435 @@ pub fn interpolate_string<TPiecesCollection, TBytesCollection, TCallback>(pieces
         (piece.0, binary_buffer_to_points(&piece.1))
     }).collect();
     let result = interpolate_buffer(&point_buffers, prime, progress_callback)?;
-    return Ok(String::from_utf8(result).unwrap());
+    return SecretString::from_buffer(result);
 }
 
-//# Solve for each value encoded in a set of files and write a file built from the solution
-//# See generate_file for format
-pub fn interpolate_file<T, TProgress>(pieces: &T, destination: &str, mut progress_callback: TProgress) -> Result<String, String>
-    where T: AsRef<[String]> + ?Sized,
-        TProgress: FnMut(f64) {
-    let mut files: Vec<File> = Vec::new();
-    let my_pieces = pieces.as_ref();
-    validate_piece_files(&my_pieces)?;
-    for piece in my_pieces {
-        files.push(open_file(piece)?);
+//# Lock each share with its passphrase, if it has one, so that its holder can keep it somewhere it might be lost
+//# Any kind of text share can be locked; see passphrase.
+pub fn lock_text_shares<TShares, TShare>(shares: &TShares, passphrases: &[Option<SecretString>]) -> Result<Vec<String>, String>
+    where TShares: AsRef<[TShare]> + ?Sized,
+        TShare: AsRef<str> {
+    let my_shares = shares.as_ref();
+    if passphrases.len() != my_shares.len() {
+        return Err(format!("Expected {} passphrases, got {}", my_shares.len(), passphrases.len()));
     }
-    let total_progress = get_file_size(&my_pieces[0])?;
-    let mut progress = 0.0;
-
-    let (prime, output_filename, indices, mut buffers, buffer_length) = read_headers(&files)?;
-
-    let destination_path = Path::new(destination).join(&output_filename);
-    let output_file = create_file(&destination_path)?;
-    let mut end_of_file = false;
-    let mut read = buffer_length;
-
-    while !end_of_file {
-        let point_buffers: Vec<(i32, Vec<i16>)> = indices.iter().map(|x| *x).zip(buffers.iter().map(|buffer| {
-            binary_buffer_to_points(&buffer[0..read])
-        })).collect();
-        let result = interpolate_buffer(&point_buffers, prime, |_|{})?;
-        write_file(&output_file, &result)?;
-        progress += read as f64;
-        progress_callback(progress / total_progress);
-
-        for index in 0..files.len() {
-            read = read_file(&files[index], &mut buffers[index][..])?;
-            if read == 0 {
-                end_of_file = true;
-            }
+    return my_shares.iter().zip(passphrases).map(|(share, passphrase)| match passphrase {
+        None => Ok(String::from(share.as_ref())),
+        Some(passphrase) => passphrase::lock_text(share.as_ref(), passphrase),
+    }).collect();
+}
+
+//# Unlock each locked share with its passphrase, leaving the rest as they are
+pub fn unlock_text_shares<TShares, TShare>(shares: &TShares, passphrases: &[Option<SecretString>]) -> Result<Vec<SecretString>, String>
+    where TShares: AsRef<[TShare]> + ?Sized,
+        TShare: AsRef<str> {
+    let mut unlocked: Vec<SecretString> = Vec::new();
+    for (index, share) in shares.as_ref().iter().enumerate() {
+        if !passphrase::is_locked_text(share.as_ref()) {
+            unlocked.push(SecretString::from(share.as_ref()));
+            continue;
+        }
+        let result = match passphrases.get(index).and_then(|passphrase| passphrase.as_ref()) {
+            None => Err(String::from("Share is locked; give the passphrase of its holder")),
+            Some(passphrase) => passphrase::unlock_text(share.as_ref(), passphrase),
+        };
+        match result {
+            Err(message) => return Err(format!("Share {}: {}", index + 1, message)),
+            Ok(share) => unlocked.push(share),
+        }
+    }
+    return Ok(unlocked);
+}
+
+//# As interpolate_string, for shares made by generate_text_shares, which carry their own index and prime
+//# The shares may be in any mix of encodings.
+pub fn interpolate_text_shares<TShares, TShare, TProgress>(shares: &TShares, progress_callback: TProgress) -> Result<SecretString, String>
+    where TShares: AsRef<[TShare]> + ?Sized,
+        TShare: AsRef<str>,
+        TProgress: FnMut(f64) {
+    let mut parsed: Vec<TextShare> = Vec::new();
+    for (index, share) in shares.as_ref().iter().enumerate() {
+        match TextShare::parse(share.as_ref()) {
+            Err(message) => return Err(format!("Share {}: {}", index + 1, message)),
+            Ok(share) => parsed.push(share),
         }
     }
+    if parsed.is_empty() {
+        return Err(String::from("Insufficient number of inputs (0)"));
+    }
+
+    if parsed.iter().any(|share| share.set_id != parsed[0].set_id) {
+        return Err(String::from("Input shares come from different splits"));
+    }
+    if parsed.iter().any(|share| share.prime != parsed[0].prime || share.threshold != parsed[0].threshold) {
+        return Err(String::from("Differing primes or thresholds in input shares"));
+    }
+    if (parsed.len() as i32) < parsed[0].threshold {
+        return Err(format!("Insufficient number of inputs ({} of {} required)", parsed.len(), parsed[0].threshold));
+    }
 
-    return Ok(String::from(destination_path.as_os_str().to_str().unwrap()));
+    let prime = parsed[0].prime;
+    let pieces: Vec<(i32, Vec<u8>)> = parsed.into_iter().map(|share| (share.index, share.data)).collect();
+    return interpolate_string(&pieces, prime, progress_callback);
 }
 
-//    Generate (requiredPiecesCount - 1) polynomial coefficients less than prime
-fn  generate_coefficients(required_pieces_count: i32, prime: i32) -> Vec<i32> {
-    return (1..required_pieces_count).map(|_|
-        rand::thread_rng().gen_range(0, prime)
-    ).collect();
+//# Recover a secret from SLIP-0039 mnemonics, made by generate_mnemonics or a hardware wallet
+pub fn interpolate_mnemonics<TMnemonics, TMnemonic>(mnemonics: &TMnemonics, passphrase: &str) -> Result<SecretBuffer, String>
+    where TMnemonics: AsRef<[TMnemonic]> + ?Sized,
+        TMnemonic: AsRef<str> {
+    return slip39::combine(mnemonics, passphrase.as_bytes());
 }
 
-// Generate the first pieces_count points on the polynomial described by coefficients
-fn  generate_points<T>(secret: i32, pieces_count: i32, coefficients: &T, prime: i32) -> Vec<(i32, i32)>
-    where T: AsRef<[i32]> + ?Sized {
-    let my_coefficients: &[i32] = coefficients.as_ref();
-    let mut pieces : Vec<(i32, i32)> = (0..(pieces_count + 1)).map(|x| {
-        let mut sum = secret;
-        for index in 0..my_coefficients.len() {
-            sum += my_coefficients[index] * (x.pow(index as u32 + 1));
-        }
-        (x, Mod::modulo(sum, prime))
-    }).collect();
-    pieces.remove(0);
-    return pieces;
+//# Recover a secret from required_pieces_count shares made by ssss-split, or generate_ssss_shares
+//# As with ssss-combine, the threshold must be given, and only that many shares are used. The secret comes back
+//# as long as the security level, so a shorter one split with ssss-split -s has leading zero bytes.
+pub fn interpolate_ssss_shares<TShares, TShare>(shares: &TShares, required_pieces_count: i32) -> Result<SecretBuffer, String>
+    where TShares: AsRef<[TShare]> + ?Sized,
+        TShare: AsRef<str> {
+    return interpolate_ssss_shares_with_options(shares, required_pieces_count, &SsssOptions::default());
 }
 
-macro_rules! multiply_all {
-    ($( $x:expr ),+ ) => {
-        $( $x.fold(BigInt::one(), |total, value| total * value) )?
+pub fn interpolate_ssss_shares_with_options<TShares, TShare>(shares: &TShares, required_pieces_count: i32, options: &SsssOptions) -> Result<SecretBuffer, String>
+    where TShares: AsRef<[TShare]> + ?Sized,
+        TShare: AsRef<str> {
+    return ssss::combine(shares, required_pieces_count.max(0) as u32, !options.skip_diffusion);
+}
+
+//# Recover a key from Vault unseal key shares, in base64 or hex
+pub fn interpolate_vault_shares<TShares, TShare>(shares: &TShares) -> Result<SecretBuffer, String>
+    where TShares: AsRef<[TShare]> + ?Sized,
+        TShare: AsRef<str> {
+    let mut decoded: Vec<Vec<u8>> = Vec::new();
+    for (index, share) in shares.as_ref().iter().enumerate() {
+        match vault::decode(share.as_ref()) {
+            Err(message) => return Err(format!("Share {}: {}", index + 1, message)),
+            Ok(share) => decoded.push(share),
+        }
     }
+    return vault::combine(&decoded);
 }
 
-// Solve for the 0th-order term of the lagrange polynomial partially described by points
-// in the prime finite field for prime
-fn  interpolate_secret<T>(points: &T, prime: i32) -> Result<i32, String>
-    where T: AsRef<[(i32, i32)]> + ?Sized {
-    let my_points: &[(i32, i32)] = points.as_ref();
-    validate_points(&my_points, prime)?;
+//# Options for interpolate_file_with_options
+#[derive(Clone, Debug, Default)]
+pub struct InterpolateOptions {
+    // Replace an existing file with the original name instead of failing
+    pub overwrite: bool,
+    // Leave the permissions of the reconstructed file at their private default
+    pub skip_mode: bool,
+    // Leave the reconstructed file's modification time as the time it was written
+    pub skip_modified: bool,
+    // Restore the recorded owner, which usually takes root, rather than leaving the file owned by the current user
+    // The setuid and setgid bits are only restored along with the owner
+    pub restore_owner: bool,
+    // Restore recorded extended attributes, which may need privileges for namespaces other than user
+    pub restore_xattrs: bool,
+    // Write the reconstructed file here rather than under its original name in the destination
+    // directory; for pieces made by generate_archive, the directory to extract into
+    pub output_path: Option<PathBuf>,
+    // age identity files to decrypt pieces encrypted to their holders; see recipient
+    pub identities: Vec<PathBuf>,
+    // Passphrases to unlock pieces locked by their holders; empty, or one per piece
+    pub passphrases: Vec<Option<SecretString>>,
+    // Public key of the dealer the pieces must be signed by; unsigned pieces are refused if given; see signature
+    pub dealer: Option<String>,
+}
 
-    let x_values : Vec<i32> = my_points.iter().map(|point| point.0).collect();
-    let y_values : Vec<i32> = my_points.iter().map(|point| point.1).collect();
-    let prime_long = prime as i64;
-    let prime_big = prime.to_bigint().unwrap();
+//# Solve for each value encoded in a set of files and write a file built from the solution
+//# The mode and modification time recorded in the pieces are restored unless options say to skip them, and the
+//# owner and extended attributes only if options ask for them
+//# Pieces made by generate_archive are extracted into destination instead, returning the path of the
+//# single file or directory they contained, or destination itself if they contained several
+//# See generate_file for format
+pub fn interpolate_file<T, P, D, TProgress, TControl>(pieces: &T, destination: D, progress_callback: TProgress) -> Result<PathBuf, String>
+    where T: AsRef<[P]> + ?Sized,
+        P: AsRef<Path>,
+        D: AsRef<Path>,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    return interpolate_file_with_options(pieces, destination, &InterpolateOptions::default(), progress_callback);
+}
 
-    let mut numerators: Vec<BigInt> = Vec::new();
-    let mut denominators: Vec<BigInt> = Vec::new();
+//# The output file only appears once it is complete, and nothing is left behind if the
+//# reconstruction fails or progress_callback cancels it
+pub fn interpolate_file_with_options<T, P, D, TProgress, TControl>(pieces: &T, destination: D, options: &InterpolateOptions, mut progress_callback: TProgress) -> Result<PathBuf, String>
+    where T: AsRef<[P]> + ?Sized,
+        P: AsRef<Path>,
+        D: AsRef<Path>,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    let my_pieces = pieces.as_ref();
+    if my_pieces.is_empty() {
+        return Err(String::from("Insufficient number of inputs (0)"));
+    }
+    // Pieces named like gfsplit's are its, unless they turn out to have a header
+    let share_numbers: Option<Vec<u8>> = my_pieces.iter().map(gfshare::share_number).collect();
+    if let Some(share_numbers) = share_numbers {
+        if PieceReader::detect(open_file(&my_pieces[0])?).and_then(|mut file| Header::read(&mut file)).is_err() {
+            // gfshare pieces have nowhere to carry a signature
+            if options.dealer.is_some() {
+                return Err(String::from("gfshare pieces can't be signed, so can't be checked against the dealer"));
+            }
+            return interpolate_gfshare(my_pieces, &share_numbers, destination.as_ref(), options, progress_callback);
+        }
+    }
+
+    let identities = recipient::read_identities(&options.identities)?;
+    if !options.passphrases.is_empty() && options.passphrases.len() != my_pieces.len() {
+        return Err(format!("Expected {} passphrases, got {}", my_pieces.len(), options.passphrases.len()));
+    }
+    let trusted = options.dealer.as_ref().map(|dealer| signature::parse_public_key(dealer)).transpose()?;
+    let mut files: Vec<PieceReader<PieceSource<File>>> = Vec::new();
+    let mut piece_sizes: Vec<u64> = Vec::new();
+    let mut last_byte: Option<u8> = None;
+    for (index, piece) in my_pieces.iter().enumerate() {
+        let passphrase = options.passphrases.get(index).and_then(|passphrase| passphrase.as_ref());
+        let mut source = open_piece(piece.as_ref(), &identities, passphrase)?;
+        piece_sizes.push(source.length()?);
+        if index == 0 {
+            last_byte = read_last_byte(&mut source)?;
+        }
+        files.push(PieceReader::detect(source)?);
+    }
+    // Armored pieces can be mixed in, but only binary ones can be expected to be the same length
+    let binary_sizes: Vec<u64> = piece_sizes.iter().zip(files.iter()).filter(|(_, file)| !file.is_armored()).map(|(size, _)| *size).collect();
+    if binary_sizes.iter().any(|size| *size != binary_sizes[0]) {
+        return Err(format!("Mismatching file lengths: {} vs. {}", binary_sizes[0], binary_sizes.iter().find(|size| **size != binary_sizes[0]).unwrap()));
+    }
 
-    for index in 0..x_values.len() {
-        let mut other_x_values = x_values.clone();
-        let this_x = other_x_values.remove(index);
+    let headers = read_headers(&mut files, &mut progress_callback)?;
+    // Signatures cover the whole piece, so each is read through once to check it before anything is written
+    if trusted.is_some() || headers[0].extension(signature::DEALER_KEY).is_some() {
+        for (index, piece) in my_pieces.iter().enumerate() {
+            let passphrase = options.passphrases.get(index).and_then(|passphrase| passphrase.as_ref());
+            let source = PieceReader::detect(open_piece(piece.as_ref(), &identities, passphrase)?)?;
+            if let Err(message) = signature::verify_piece(source, trusted.as_ref()) {
+                return Err(format!("{}: {}", piece.as_ref().display(), message));
+            }
+        }
+    }
+    let armored = files[0].is_armored();
+
+    // Each byte of the secret is stored as a two-byte point in every piece, give or take the chunk headers, parity
+    // and signature, and armor stores every three bytes as four characters
+    let piece_size = piece_sizes[0];
+    let binary_size = if armored { piece_size * 3 / 4 } else { piece_size };
+    let total_progress = binary_size.saturating_sub(headers[0].to_bytes().len() as u64) / 2;
+
+    if headers[0].extension(archive::ARCHIVE_KEY).is_some() {
+        let directory = options.output_path.as_ref().map(|path| path.as_path()).unwrap_or(destination.as_ref());
+        return interpolate_archive(&mut files, &headers, directory, options, |progress: &Progress| {
+            progress_callback(&Progress { total_bytes: Some(total_progress), ..progress.clone() }).into()
+        });
+    }
 
-        numerators.push(multiply_all!(other_x_values.iter().map(|x| 0 - *x)));
-        denominators.push(multiply_all!(other_x_values.iter().map(|x| this_x - *x)));
+    let metadata = Metadata::from_header(&headers[0])?;
+    let destination_path = match options.output_path {
+        None => destination.as_ref().join(original_file_name(&headers[0])?),
+        Some(ref path) => path.clone(),
+    };
+    let mut output_file = OutputFile::create(&destination_path, options.overwrite)?;
+
+    let body_length = piece_size.saturating_sub(headers[0].to_bytes().len() as u64);
+    if !armored && hiss::is_hiss_piece(&headers[0], body_length, last_byte) {
+        hiss::combine(&mut files, &headers, &mut output_file, |progress: &Progress| {
+            progress_callback(&Progress { total_bytes: Some(total_progress), ..progress.clone() })
+        })?;
+    } else {
+        stream::combine_bodies(&mut files, &headers, &mut output_file, |progress: &Progress| {
+            progress_callback(&Progress { total_bytes: Some(total_progress), ..progress.clone() })
+        })?;
     }
 
-    let denominator = multiply_all!(denominators.iter());
-    let numerator = (0..x_values.len()).fold(BigInt::zero(), |total, index| {
-        total + divide_and_apply_modulus(&Mod::modulo(&numerators[index] * &denominator * &y_values[index].to_bigint().unwrap(), &prime_big), &denominators[index], &prime_big)
+    metadata_to_restore(metadata, options).apply(output_file.file())?;
+    return output_file.commit();
+}
+
+// The last byte of source, if it has any, leaving source at its start
+fn read_last_byte<R>(source: &mut R) -> Result<Option<u8>, String>
+    where R: Read + Seek {
+    let mut last = [0 as u8; 1];
+    let result = source.seek(SeekFrom::End(0)).and_then(|length| match length {
+        0 => Ok(None),
+        _ => source.seek(SeekFrom::End(-1)).and_then(|_| source.read_exact(&mut last)).map(|_| Some(last[0])),
     });
+    return match result.and_then(|last| source.seek(SeekFrom::Start(0)).map(|_| last)) {
+        Err(error) => Err(format!("Error reading piece: {}", error)),
+        Ok(last) => Ok(last),
+    }
+}
 
-    let result = Mod::modulo(divide_and_apply_modulus(&numerator, &denominator, &prime_big) + prime_long, prime_long);
-    return match result.to_i32() {
-        None => Err(format!("Error interpolating secret: integer overflow for {}", result)),
-        Some(value) => Ok(value),
+// Open a piece, decrypting or unlocking it if need be
+fn open_piece(piece: &Path, identities: &[Box<dyn Identity>], passphrase: Option<&SecretString>) -> Result<PieceSource<File>, String> {
+    return match PieceSource::open(open_file(piece)?, identities, passphrase) {
+        Err(message) => Err(format!("{}: {}", piece.display(), message)),
+        Ok(source) => Ok(source),
     }
 }
 
-fn  divide_and_apply_modulus(numerator: &BigInt, denominator: &BigInt, prime: &BigInt) -> i64 {
-    return (numerator * modular_multiplicative_inverse(denominator, prime).0).to_i64().unwrap();
+// gfshare pieces are combined into a file named after them, without their extension
+fn interpolate_gfshare<P, TProgress, TControl>(pieces: &[P], share_numbers: &[u8], destination: &Path, options: &InterpolateOptions, mut progress_callback: TProgress) -> Result<PathBuf, String>
+    where P: AsRef<Path>,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    validate_piece_files(pieces)?;
+    let mut files: Vec<File> = Vec::new();
+    for piece in pieces {
+        files.push(open_file(piece)?);
+    }
+
+    let destination_path = match options.output_path {
+        None => match pieces[0].as_ref().file_stem() {
+            None => return Err(format!("Error parsing file name: {}", pieces[0].as_ref().display())),
+            Some(stem) => destination.join(stem),
+        },
+        Some(ref path) => path.clone(),
+    };
+    let total_progress = get_file_size(&pieces[0])? as u64;
+    let mut output_file = OutputFile::create(&destination_path, options.overwrite)?;
+
+    gfshare::combine(&mut files, share_numbers, &mut output_file, |progress: &Progress| {
+        progress_callback(&Progress { total_bytes: Some(total_progress), ..progress.clone() })
+    })?;
+    return output_file.commit();
 }
 
-// https://en.wikipedia.org/wiki/Extended_Euclidean_algorithm
-fn  modular_multiplicative_inverse<T>(a: &T, z: &T) -> (i64, i64)
-    where T: Into<BigInt> + Clone {
-    let mut x: i64 = 0;
-    let mut last_x: i64 = 1;
-    let mut y: i64 = 1;
-    let mut last_y: i64 = 0;
-    let mut a: BigInt = a.clone().into();
-    let mut z: BigInt = z.clone().into();
+fn interpolate_archive<R, TProgress>(files: &mut [R], headers: &[Header], destination: &Path, options: &InterpolateOptions, progress_callback: TProgress) -> Result<PathBuf, String>
+    where R: Read,
+        TProgress: FnMut(&Progress) -> Control {
+    let format = headers[0].extension(archive::ARCHIVE_KEY).unwrap();
+    if format != archive::TAR {
+        return Err(format!("Unsupported archive format: {}", format));
+    }
 
-    while z != BigInt::zero() {
-        let integer_quotient: i64 = (&a / &z).to_i64().unwrap();
-        let new_a = z;
-        z = Mod::modulo(&a , &new_a);
-        a = new_a;
+    let mut reader = stream::CombineReader::new(files, headers, progress_callback)?;
+    let result = archive::extract_archive(&mut reader, destination, options.overwrite);
+    // Report why the pieces couldn't be read, rather than how that looked to the archive
+    if let Some(message) = reader.error() {
+        return Err(String::from(message));
+    }
 
-        let new_x = last_x - (integer_quotient * x);
-        last_x = x;
-        x = new_x;
+    let restored = result?;
+    return Ok(if restored.len() == 1 { restored[0].clone() } else { destination.to_path_buf() });
+}
+
+//# Repair a piece in place from its own parity, returning the number of damaged blocks rebuilt
+//# The piece is only rewritten if it was damaged, and only if all of the damage could be repaired.
+//# Its header and any signature aren't covered by the parity, so damage there can't be.
+pub fn repair_file<P, TProgress, TControl>(piece: P, mut progress_callback: TProgress) -> Result<u64, String>
+    where P: AsRef<Path>,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    let my_piece = piece.as_ref();
+    let total_progress = get_file_size(my_piece)? as u64;
+    let mut file = PieceReader::detect(open_file(my_piece)?)?;
+    let header = Header::read(&mut file)?;
+    let layout = match Layout::from_header(&header)? {
+        None => return Err(format!("{} has no parity to repair it from", my_piece.display())),
+        Some(layout) => layout,
+    };
+
+    // Rewritten as it's read, in the same form, and only committed if there was anything to repair
+    let armored = file.is_armored();
+    let mut file = SignedReader::new(file, &header)?;
+    let mut output_file = match armored {
+        false => PieceWriter::Binary(OutputFile::create(my_piece, true)?),
+        true => PieceWriter::Armored(ArmorWriter::new(OutputFile::create(my_piece, true)?, &armor::header_fields(&header))?),
+    };
+    let mut decoder = ParityDecoder::new(layout);
+    let mut encoder = ParityEncoder::new(layout);
+    let mut buffer = vec![0 as u8; BUFFER_SIZE];
+    let mut progress = header.to_bytes().len() as u64;
+    write_file(&mut output_file, my_piece, &header.to_bytes())?;
 
-        let new_y = last_y - (integer_quotient * y);
-        last_y = y;
-        y = new_y;
+    loop {
+        let length = match file.read(&mut buffer) {
+            Err(ref error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(format!("Error reading {}: {}", my_piece.display(), error)),
+            Ok(length) => length,
+        };
+        if length == 0 {
+            break;
+        }
+        write_file(&mut output_file, my_piece, &encoder.update(&decoder.update(&buffer[0..length])))?;
+        progress += length as u64;
+        progress::report(&mut progress_callback, Progress::new(Phase::Repairing, progress, Some(total_progress), None))?;
+    }
+    write_file(&mut output_file, my_piece, &encoder.update(&decoder.finish()))?;
+    write_file(&mut output_file, my_piece, &encoder.finish())?;
+    // The signature isn't covered by the parity, but still holds for the repaired piece
+    write_file(&mut output_file, my_piece, file.trailer())?;
+
+    if decoder.unrepairable_stripes() > 0 {
+        return Err(format!("{} is too badly damaged to repair ({} stripes)", my_piece.display(), decoder.unrepairable_stripes()));
+    }
+    if decoder.repaired_blocks() > 0 {
+        output_file.finish()?.commit()?;
     }
 
-    return (last_x.into(), last_y.into());
+    return Ok(decoder.repaired_blocks());
+}
+
+fn write_file<W>(output_file: &mut W, path: &Path, data: &[u8]) -> Result<(), String>
+    where W: Write {
+    return match output_file.write_all(data) {
+        Err(error) => Err(format!("Error writing {}: {}", path.display(), error)),
+        Ok(()) => Ok(()),
+    }
+}
+
+// The name the pieces give for the file they were made from, as long as it is a single file name, so that pieces
+// can't be made to write outside the destination directory
+fn original_file_name(header: &Header) -> Result<OsString, String> {
+    let name = header.filename_os();
+    let mut components = Path::new(&name).components();
+    return match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(name),
+        _ => Err(format!("Pieces give the invalid file name {}", name.to_string_lossy())),
+    }
+}
+
+fn metadata_to_restore(metadata: Metadata, options: &InterpolateOptions) -> Metadata {
+    return Metadata {
+        mode: if options.skip_mode { None } else { metadata.mode },
+        modified: if options.skip_modified { None } else { metadata.modified },
+        owner: if options.restore_owner { metadata.owner } else { None },
+        xattrs: if options.restore_xattrs { metadata.xattrs } else { Vec::new() },
+    };
+}
+
+//    Generate (requiredPiecesCount - 1) polynomial coefficients less than prime
+fn  generate_coefficients(required_pieces_count: i32, prime: i32) -> Vec<u32> {
+    return (1..required_pieces_count).map(|_|
+        rand::thread_rng().gen_range(0, prime as u32)
+    ).collect();
+}
+
+// Generate the first pieces_count points on the polynomial described by coefficients
+fn  generate_points<T>(secret: i32, pieces_count: i32, coefficients: &T, prime: i32) -> Vec<(i32, i32)>
+    where T: AsRef<[u32]> + ?Sized {
+    let field = Field::new(prime as u32);
+    let constant = field.reduce(secret as u32);
+    return (1..(pieces_count + 1)).map(|x| {
+        (x, field.evaluate(constant, coefficients, x as u32) as i32)
+    }).collect();
 }
 
 //# Generate the first piecesCount values for the polynomial for each byte in secret
 fn generate_buffer<TSecret, TProgress>(secret: &TSecret, total_pieces: i32, required_pieces: i32, prime: i32, mut progress_callback: TProgress) -> Vec<(i32, Vec<i16>)>
     where TSecret: AsRef<[u8]> + ?Sized,
         TProgress: FnMut(f64) {
+    secret::protect_process();
     let mut result: Vec<(i32, Vec<i16>)> = (0..total_pieces).map(|index| (index + 1, Vec::new())).collect();
     let my_secret = secret.as_ref();
     let total_progress = my_secret.len() as f64;
 
     for i in 0..my_secret.len() {
-        for point in generate_points(my_secret[i] as i32, total_pieces, &generate_coefficients(required_pieces, prime), prime) {
+        // The coefficients and the pieces together would reveal the secret
+        let mut coefficients = generate_coefficients(required_pieces, prime);
+        for point in generate_points(my_secret[i] as i32, total_pieces, &coefficients, prime) {
             result[point.0 as usize - 1].1.push(point.1 as i16)
         }
+        coefficients.zeroize();
         progress_callback(i as f64 / total_progress);
     }
 
@@ -410,7 +1029,9 @@ fn  validate_points<T>(points: &T, prime: i32) -> Result<(), String>
     if my_points.len() < 2 {
         return Err(format!("Insufficient number of inputs ({})", my_points.len()));
     }
-    if my_points.iter().any(|point| point.1 >= prime) {
+    field::validate_prime(prime)?;
+    // Check every value without exiting early, so the time taken doesn't depend on them
+    if my_points.iter().fold(false, |invalid, point| invalid | (point.1 < 0) | (point.1 >= prime)) {
         return Err(format!("Prime {} must be greater than all values {:?}", prime, my_points));
     }
 
@@ -431,24 +1052,39 @@ fn validate_buffers<TContainer, TByteBuffer>(buffers: &TContainer) -> Result<(),
 }
 
 //# Solve for each set of points in points and return an ordered array of solutions
-fn interpolate_buffer<TContainer, TPointBuffer, TProgress>(points: &TContainer, prime: i32, mut progress_callback: TProgress) -> Result<Vec<u8>, String>
+pub fn interpolate_buffer<TContainer, TPointBuffer, TProgress>(points: &TContainer, prime: i32, mut progress_callback: TProgress) -> Result<SecretBuffer, String>
     where TContainer: AsRef<[(i32, TPointBuffer)]> + ?Sized,
         TPointBuffer: AsRef<[i16]>,
         TProgress: FnMut(f64) {
     let my_points = points.as_ref();
     validate_buffers(&my_points)?;
+    validate_points(&my_points.iter().map(|point| (point.0, 0)).collect::<Vec<(i32, i32)>>(), prime)?;
+
+    // The x-values are shared by every set of points, so the basis only needs solving once
+    let field = Field::new(prime as u32);
+    let x_values: Vec<u32> = my_points.iter().map(|point| field.from_public(point.0 as i64)).collect();
+    let basis = field.lagrange_basis_at_zero(&x_values)?;
 
     let point_count = my_points[0].1.as_ref().len();
-    let mut result: Vec<u8> = Vec::new();
+    let mut result = SecretBuffer::with_capacity(point_count);
+    let mut y_values: Vec<u32> = vec![0; my_points.len()];
+    let mut invalid = false;
 
     for i in 0..point_count {
-        match interpolate_secret(&my_points.iter().map(|point| (point.0, point.1.as_ref()[i] as i32)).collect::<Vec<(i32, i32)>>(), prime) {
-            Err(message) => return Err(message),
-            Ok(value) => result.push(value as u8),
+        for (y, point) in y_values.iter_mut().zip(my_points.iter()) {
+            let value = point.1.as_ref()[i];
+            invalid |= (value < 0) | (value as i32 >= prime);
+            *y = value as u16 as u32;
         }
+        result.push(field.combine(&y_values, &basis) as u8);
         progress_callback(i as f64 / point_count as f64);
     }
 
+    // Only report invalid values once everything has been processed, so the time taken doesn't depend on them
+    if invalid {
+        return Err(format!("Prime {} must be greater than all values", prime));
+    }
+
     return Ok(result);
 }
 
@@ -467,7 +1103,7 @@ mod  tests {
 
         assert_eq!(coefficients.len() as i32, required_pieces - 1);
         for coefficient in coefficients {
-            assert!(coefficient < prime);
+            assert!(coefficient < prime as u32);
         }
     }
 
@@ -498,41 +1134,7 @@ mod  tests {
         assert!(validate_points(&[(1, 50001), (2, 20000), (3, 30000)], prime).is_err()); // Prime too small for y-values
     }
 
-    //        it "creates a product of inputs" do
-    #[test]
-    fn  test_multiply_all() {
-        let empty_values: [i32; 0] = [];
-        assert_eq!(multiply_all!(empty_values.iter()), BigInt::one());
-
-        let test_data = [
-            ([1, 2, 3], 6),
-            ([2, -1, 2], -4),
-            ([0, -43, 112], 0),
-        ];
-
-        for test_datum in &test_data {
-            assert_eq!(multiply_all!(test_datum.0.iter()), test_datum.1.to_bigint().unwrap());
-        }
-    }
-
-    //    it "calculates modular multiplicative inverse given known inputs" do
-    #[test]
-    fn test_modular_multiplicative_inverse() {
-        let test_data = [
-            ((-4, 3617), 904),
-            ((-4, 7211), -1803),
-        ];
-
-        for test_datum in &test_data {
-            let inverse = modular_multiplicative_inverse(&(test_datum.0).0.to_bigint().unwrap(), &(test_datum.0).1.to_bigint().unwrap()).0;
-            assert_eq!(inverse, test_datum.1 as i64);
-            assert_eq!(Mod::modulo((test_datum.0).0 * inverse, (test_datum.0).1), 1);
-        }
-    }
-
-    // TODO: Move to integration tests
-    // It's not straightforward to do integration tests with an executable crate,
-    // need to reorganize into a lib + an executable before that will be feasible
+    // TODO: Move to integration tests now that the crate is split into a lib + an executable
 
     fn choose_n_from<T>(source: &Vec<T>, n: usize) -> Vec<T>
         where T: Clone {
@@ -555,7 +1157,10 @@ mod  tests {
             assert!(point.1 < prime);
         }
 
-        assert_eq!(interpolate_secret(&choose_n_from(&points, required_pieces as usize), prime)?, secret);
+        let field = Field::new(prime as u32);
+        let chosen_points = choose_n_from(&points, required_pieces as usize);
+        let basis = field.lagrange_basis_at_zero(&chosen_points.iter().map(|point| point.0 as u32).collect::<Vec<u32>>())?;
+        assert_eq!(field.combine(&chosen_points.iter().map(|point| point.1 as u32).collect::<Vec<u32>>(), &basis) as i32, secret);
         Ok(())
     }
 
@@ -586,7 +1191,7 @@ mod  tests {
         assert!(interpolate_buffer(&buffers, prime, |_|{}).is_err());
     }
 
-    fn roundtrip_buffer<TSecret, TProgress>(secret: &TSecret, mut progress_callback: TProgress) -> Result<Vec<u8>, String>
+    fn roundtrip_buffer<TSecret, TProgress>(secret: &TSecret, mut progress_callback: TProgress) -> Result<SecretBuffer, String>
         where TSecret: AsRef<[u8]> + ?Sized,
             TProgress: FnMut(f64) {
         let total_pieces = 8;
@@ -622,10 +1227,10 @@ mod  tests {
     fn test_roundtrip_buffer() {
         let secret: Vec<u8> = (0..32).map(|_| random::<u8>()).collect();
         let calculated_secret = roundtrip_buffer(&secret, |_|{}).unwrap();
-        assert_eq!(secret, calculated_secret);
+        assert_eq!(secret, calculated_secret.as_slice());
     }
 
-    fn roundtrip_string<T>(secret: &str, mut progress_callback: T) -> Result<String, String>
+    fn roundtrip_string<T>(secret: &str, mut progress_callback: T) -> Result<SecretString, String>
         where T: FnMut(f64) {
         let total_pieces = 8;
         let required_pieces = 5;
@@ -651,7 +1256,69 @@ mod  tests {
     fn test_roundtrip_string() {
         let secret: String = String::from("1234567890123456789012");
         let calculated_secret = roundtrip_string(secret.as_str(), |_|{}).unwrap();
-        assert_eq!(secret, calculated_secret);
+        assert_eq!(secret, calculated_secret.as_str());
+    }
+
+    #[test]
+    fn test_roundtrip_text_shares() {
+        let secret = "secret text";
+        let shares = generate_text_shares(secret, 5, 3, 5717, Encoding::Base64, |_|{}).unwrap();
+        assert_eq!(interpolate_text_shares(&shares[1..4], |_|{}).unwrap().as_str(), secret);
+        assert_eq!(interpolate_text_shares(&[&shares[4], &shares[0], &shares[2]], |_|{}).unwrap().as_str(), secret);
+
+        assert_eq!(interpolate_text_shares(&shares[0..2], |_|{}), Err(String::from("Insufficient number of inputs (2 of 3 required)")));
+        let other_shares = generate_text_shares(secret, 5, 3, 5717, Encoding::Base64, |_|{}).unwrap();
+        assert_eq!(interpolate_text_shares(&[&shares[0], &shares[1], &other_shares[2]], |_|{}), Err(String::from("Input shares come from different splits")));
+        assert!(interpolate_text_shares(&[&shares[0], &shares[1], &shares[2][1..]], |_|{}).unwrap_err().starts_with("Share 3: "));
+
+        let words = generate_text_shares(secret, 5, 3, 5717, Encoding::Words, |_|{}).unwrap();
+        let base32 = generate_text_shares(secret, 5, 3, 5717, Encoding::Base32, |_|{}).unwrap();
+        assert_eq!(interpolate_text_shares(&words[2..5], |_|{}).unwrap().as_str(), secret);
+        assert_eq!(interpolate_text_shares(&base32[0..3], |_|{}).unwrap().as_str(), secret);
+        let mistyped = format!("{}{}", if base32[1].starts_with('0') { '1' } else { '0' }, &base32[1][1..]);
+        assert!(interpolate_text_shares(&[&base32[0], &mistyped, &base32[2]], |_|{}).unwrap_err().starts_with("Share 2: Group 1 "));
+    }
+
+    #[test]
+    fn test_roundtrip_locked_text_shares() {
+        let shares = generate_text_shares("secret", 3, 2, 5717, Encoding::Words, |_|{}).unwrap();
+        let passphrases = vec![Some(SecretString::from("first")), None, Some(SecretString::from("third"))];
+        let locked = lock_text_shares(&shares, &passphrases).unwrap();
+        assert!(passphrase::is_locked_text(&locked[0]) && locked[1] == shares[1]);
+
+        assert!(unlock_text_shares(&locked, &[]).unwrap_err().starts_with("Share 1: "));
+        let unlocked = unlock_text_shares(&locked[1..], &passphrases[1..]).unwrap();
+        assert_eq!(interpolate_text_shares(&unlocked.iter().map(|share| share.as_str()).collect::<Vec<&str>>(), |_|{}).unwrap().as_str(), "secret");
+    }
+
+    #[test]
+    fn test_roundtrip_mnemonics() {
+        let secret = "sixteen byte key";
+        let groups = generate_mnemonics(secret, 1, &[(2, 3)], "").unwrap();
+        assert_eq!(interpolate_mnemonics(&groups[0][1..3], "").unwrap().as_slice(), secret.as_bytes());
+        assert!(generate_mnemonics(secret, 1, &[(2, 300)], "").is_err());
+        assert!(generate_mnemonics("too short", 1, &[(1, 1)], "").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_ssss_shares() {
+        let secret = "secret text";
+        let shares = generate_ssss_shares(secret, 5, 3).unwrap();
+        assert_eq!(interpolate_ssss_shares(&shares[2..5], 3).unwrap().as_slice(), secret.as_bytes());
+
+        let options = SsssOptions { token: Some(String::from("backup")), security_level: Some(128), skip_diffusion: true };
+        let shares = generate_ssss_shares_with_options(secret, 5, 3, &options).unwrap();
+        assert!(shares[4].starts_with("backup-5-"));
+        assert!(interpolate_ssss_shares_with_options(&shares[1..4], 3, &options).unwrap().as_slice().ends_with(secret.as_bytes()));
+        assert!(generate_ssss_shares(secret, 5, 1).is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_vault_shares() {
+        let key = [7u8; 32];
+        let shares = generate_vault_shares(&key, 5, 3).unwrap();
+        assert_eq!(interpolate_vault_shares(&shares[1..4]).unwrap().as_slice(), &key);
+        assert!(interpolate_vault_shares(&[&shares[0], "not a share!"]).unwrap_err().starts_with("Share 2: "));
     }
 
     //    it "reports progress for strings" do
@@ -672,21 +1339,492 @@ mod  tests {
         let prime = 5717;
         let input = destination.join("testInput");
 
-        let pieces = generate_file(input.to_str().unwrap(), total_pieces, required_pieces, prime, |_|{}).unwrap();
+        let options = GenerateOptions { overwrite: true, ..GenerateOptions::default() };
+        let pieces = generate_file_with_options(&input, total_pieces, required_pieces, prime, &options, |_|{}).unwrap();
 
         let test_data = [
-            String::from(input.with_file_name("testInput-differingPrime.shard").to_str().unwrap()),
-            String::from(input.with_file_name("testInput-differingVersion.shard").to_str().unwrap()),
-            String::from(input.with_file_name("testInput-differingFilename.shard").to_str().unwrap()),
-            String::from(input.with_file_name("testInput-invalidFilename.shard").to_str().unwrap()),
+            input.with_file_name("testInput-differingPrime.shard"),
+            input.with_file_name("testInput-differingVersion.shard"),
+            input.with_file_name("testInput-differingFilename.shard"),
+            input.with_file_name("testInput-invalidFilename.shard"),
             pieces[0].clone(),
         ];
 
         for test_datum in &test_data {
             let mut test_pieces = pieces.clone();
             test_pieces.push(test_datum.clone());
-            assert!(interpolate_file(&test_pieces, destination.to_str().unwrap(), |_|{}).is_err());
+            assert!(interpolate_file(&test_pieces, &destination, |_|{}).is_err());
+        }
+    }
+
+    // Copy the test input into a fresh directory, so that tests don't trip over each other's pieces
+    fn copy_input_to_temporary_directory() -> PathBuf {
+        let source = Path::new(file!()).parent().unwrap().parent().unwrap().join("tests").join("data").join("testInput");
+        let directory = std::env::temp_dir().join(format!("russs-test-{:016x}", random::<u64>()));
+        std::fs::create_dir(&directory).unwrap();
+        std::fs::copy(&source, directory.join("testInput")).unwrap();
+        return directory.join("testInput");
+    }
+
+    #[test]
+    fn test_cancel_file() {
+        let input = copy_input_to_temporary_directory();
+        let mut events = 0;
+
+        // Cancel partway through the body, after every piece has been created
+        let result = generate_file(&input, 4, 3, 5717, |progress: &Progress| {
+            assert_eq!(progress.total_bytes, Some(8664));
+            events += 1;
+            if progress.phase == Phase::Splitting { Control::Cancel } else { Control::Continue }
+        });
+
+        assert_eq!(result, Err(String::from(progress::CANCELLED)));
+        assert_eq!(events, 5);
+        // Only the input should remain
+        assert_eq!(std::fs::read_dir(input.parent().unwrap()).unwrap().count(), 1);
+        std::fs::remove_dir_all(input.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_cancel_archive() {
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap();
+
+        let result = generate_archive(&[&input], directory.join("archive"), 3, 2, 5717, |progress: &Progress| {
+            if progress.phase == Phase::Splitting { Control::Cancel } else { Control::Continue }
+        });
+        assert_eq!(result, Err(String::from(progress::CANCELLED)));
+        assert_eq!(std::fs::read_dir(directory).unwrap().count(), 1);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_no_clobber_file() {
+        let input = copy_input_to_temporary_directory();
+        let destination = input.parent().unwrap();
+
+        let pieces = generate_file(&input, 3, 2, 5717, |_|{}).unwrap();
+        assert!(generate_file(&input, 3, 2, 5717, |_|{}).unwrap_err().starts_with(output::ALREADY_EXISTS));
+
+        // The original file is still there
+        assert!(interpolate_file(&pieces, destination, |_|{}).unwrap_err().starts_with(output::ALREADY_EXISTS));
+        let options = InterpolateOptions { overwrite: true, ..InterpolateOptions::default() };
+        interpolate_file_with_options(&pieces, destination, &options, |_|{}).unwrap();
+
+        std::fs::remove_dir_all(destination).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_original_name() {
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap().join("nested");
+        std::fs::create_dir(&directory).unwrap();
+
+        for name in ["../escaped", "/tmp/absolute", ".."].iter() {
+            let mut sinks: Vec<Vec<u8>> = (0..2).map(|_| Vec::new()).collect();
+            stream::split(&mut "secret".as_bytes(), &mut sinks, 2, 5717, name, |_|{}).unwrap();
+            let pieces: Vec<PathBuf> = sinks.iter().enumerate().map(|(index, sink)| {
+                let path = directory.join(format!("piece-{}", index));
+                std::fs::write(&path, sink).unwrap();
+                path
+            }).collect();
+            let options = InterpolateOptions { overwrite: true, ..InterpolateOptions::default() };
+            assert!(interpolate_file_with_options(&pieces, &directory, &options, |_|{}).unwrap_err().contains("invalid file name"));
+        }
+        assert!(!input.parent().unwrap().join("escaped").exists());
+
+        std::fs::remove_dir_all(input.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_commit_files_rollback() {
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap();
+        let replaced = directory.join("replaced");
+        std::fs::write(&replaced, b"original").unwrap();
+
+        // The second file fails because its destination appears after it was created
+        let mut first = OutputFile::create(&replaced, true).unwrap();
+        first.write_all(b"replacement").unwrap();
+        let mut second = OutputFile::create(directory.join("new"), false).unwrap();
+        second.write_all(b"new").unwrap();
+        std::fs::write(directory.join("new"), b"in the way").unwrap();
+        assert!(commit_files(vec![first, second]).unwrap_err().starts_with(output::ALREADY_EXISTS));
+
+        // The replaced file is put back, and nothing else is left behind
+        assert_eq!(std::fs::read(&replaced).unwrap(), b"original");
+        assert_eq!(std::fs::read_dir(directory).unwrap().count(), 3);
+
+        let mut first = OutputFile::create(&replaced, true).unwrap();
+        first.write_all(b"replacement").unwrap();
+        assert_eq!(commit_files(vec![first]).unwrap(), vec![replaced.clone()]);
+        assert_eq!(std::fs::read(&replaced).unwrap(), b"replacement");
+        assert_eq!(std::fs::read_dir(directory).unwrap().count(), 3);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_roundtrip_non_utf8_file() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap().to_path_buf();
+        // "café" in Latin-1
+        let secret_path = directory.join(OsStr::from_bytes(b"caf\xe9"));
+        std::fs::rename(&input, &secret_path).unwrap();
+
+        let pieces = generate_file(&secret_path, 3, 2, 5717, |_|{}).unwrap();
+        assert_eq!(pieces[0], directory.join(OsStr::from_bytes(b"caf\xe9-1.shard")));
+
+        let result = interpolate_file(&pieces[1..], &directory, |_|{}).unwrap_err();
+        assert!(result.starts_with(output::ALREADY_EXISTS));
+        std::fs::remove_file(&secret_path).unwrap();
+        assert_eq!(interpolate_file(&pieces[1..], &directory, |_|{}).unwrap(), secret_path);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_metadata() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap().to_path_buf();
+        std::fs::set_permissions(&input, std::fs::Permissions::from_mode(0o640)).unwrap();
+        let modified = filetime::FileTime::from_unix_time(1_000_000_000, 123_456_789);
+        filetime::set_file_mtime(&input, modified).unwrap();
+
+        let options_for_owner = GenerateOptions { record_owner: true, ..GenerateOptions::default() };
+        let pieces = generate_file_with_options(&input, 3, 2, 5717, &options_for_owner, |_|{}).unwrap();
+        std::fs::remove_file(&input).unwrap();
+
+        interpolate_file(&pieces[..2], &directory, |_|{}).unwrap();
+        let restored = input.metadata().unwrap();
+        assert_eq!(restored.permissions().mode() & 0o7777, 0o640);
+        assert_eq!(filetime::FileTime::from_last_modification_time(&restored), modified);
+        std::fs::remove_file(&input).unwrap();
+
+        let options = InterpolateOptions { skip_mode: true, skip_modified: true, ..InterpolateOptions::default() };
+        interpolate_file_with_options(&pieces[1..], &directory, &options, |_|{}).unwrap();
+        let restored = input.metadata().unwrap();
+        assert_eq!(restored.permissions().mode() & 0o7777, 0o600);
+        assert!(filetime::FileTime::from_last_modification_time(&restored) != modified);
+
+        // The setgid bit only comes back along with the owner it was for
+        std::fs::set_permissions(&input, std::fs::Permissions::from_mode(0o2750)).unwrap();
+        for piece in &pieces {
+            std::fs::remove_file(piece).unwrap();
+        }
+        let pieces = generate_file_with_options(&input, 3, 2, 5717, &options_for_owner, |_|{}).unwrap();
+        std::fs::remove_file(&input).unwrap();
+        interpolate_file(&pieces[..2], &directory, |_|{}).unwrap();
+        assert_eq!(input.metadata().unwrap().permissions().mode() & 0o7777, 0o750);
+        std::fs::remove_file(&input).unwrap();
+        let options = InterpolateOptions { restore_owner: true, ..InterpolateOptions::default() };
+        interpolate_file_with_options(&pieces[..2], &directory, &options, |_|{}).unwrap();
+        assert_eq!(input.metadata().unwrap().permissions().mode() & 0o7777, 0o2750);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_roundtrip_archive() {
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap().to_path_buf();
+        let tree = directory.join("tree");
+        std::fs::create_dir_all(tree.join("nested")).unwrap();
+        std::fs::copy(&input, tree.join("nested").join("secret")).unwrap();
+        std::fs::write(tree.join("empty"), b"").unwrap();
+
+        let pieces = generate_archive(&[&tree], &tree, 4, 3, 5717, |_|{}).unwrap();
+        assert_eq!(pieces[0], directory.join("tree-1.shard"));
+
+        let destination = directory.join("restored");
+        std::fs::create_dir(&destination).unwrap();
+        let mut events = 0;
+        let result = interpolate_file(&pieces[1..], &destination, |progress: &Progress| {
+            events += 1;
+            if progress.phase == Phase::Combining { Control::Cancel } else { Control::Continue }
+        });
+        assert_eq!(result, Err(String::from(progress::CANCELLED)));
+        assert_eq!(events, 4);
+        assert_eq!(std::fs::read_dir(&destination).unwrap().count(), 0);
+
+        assert_eq!(interpolate_file(&pieces[1..], &destination, |_|{}).unwrap(), destination.join("tree"));
+        assert_eq!(std::fs::read(destination.join("tree").join("nested").join("secret")).unwrap(), std::fs::read(&input).unwrap());
+        assert_eq!(std::fs::read(destination.join("tree").join("empty")).unwrap(), b"");
+
+        // Several inputs are restored side by side
+        let pieces = generate_archive(&[&input, &tree.join("empty")], directory.join("bundle"), 2, 2, 5717, |_|{}).unwrap();
+        let destination = directory.join("bundle");
+        std::fs::create_dir(&destination).unwrap();
+        assert_eq!(interpolate_file(&pieces, &destination, |_|{}).unwrap(), destination);
+        assert!(destination.join("testInput").exists() && destination.join("empty").exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_expand_name_template() {
+        let stem = OsStr::new("secret");
+        assert_eq!(expand_name_template(DEFAULT_NAME_TEMPLATE, stem, 2, 5, "0123", "").unwrap(), "secret-2.shard");
+        assert_eq!(expand_name_template("{label}_{stem}.{index}of{total}.{set}", stem, 2, 5, "0123", "alice").unwrap(), "alice_secret.2of5.0123");
+        assert!(expand_name_template("{stem}-{unknown}", stem, 1, 2, "0123", "").is_err());
+        assert!(expand_name_template("{stem}-{index", stem, 1, 2, "0123", "").is_err());
+        assert!(expand_name_template("../{stem}-{index}", stem, 1, 2, "0123", "").is_err());
+        assert!(expand_name_template("{label}", stem, 1, 2, "0123", "").is_err());
+    }
+
+    #[test]
+    fn test_output_directories() {
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap().to_path_buf();
+        let drives: Vec<PathBuf> = (0..3).map(|index| directory.join(format!("drive{}", index))).collect();
+        for drive in &drives {
+            std::fs::create_dir(drive).unwrap();
         }
+
+        let options = GenerateOptions {
+            output_directories: drives.clone(),
+            name_template: Some(String::from("{label}-{stem}-{index}of{total}.shard")),
+            labels: vec![String::from("alice"), String::from("bob"), String::from("carol")],
+            ..GenerateOptions::default()
+        };
+        let pieces = generate_file_with_options(&input, 3, 2, 5717, &options, |_|{}).unwrap();
+        assert_eq!(pieces, vec![
+            drives[0].join("alice-testInput-1of3.shard"),
+            drives[1].join("bob-testInput-2of3.shard"),
+            drives[2].join("carol-testInput-3of3.shard"),
+        ]);
+
+        // Pieces from another split of the same file can't be mixed in
+        let options = GenerateOptions { output_directories: vec![directory.clone()], ..GenerateOptions::default() };
+        let other_pieces = generate_file_with_options(&input, 3, 2, 5717, &options, |_|{}).unwrap();
+        assert_eq!(other_pieces[0], directory.join("testInput-1.shard"));
+        let result = interpolate_file(&[&pieces[0], &other_pieces[1]], &directory, |_|{});
+        assert_eq!(result, Err(String::from("Input files come from different splits")));
+
+        let options = GenerateOptions { output_directories: drives[..2].to_vec(), ..GenerateOptions::default() };
+        assert!(generate_file_with_options(&input, 3, 2, 5717, &options, |_|{}).is_err());
+
+        let output = directory.join("restored");
+        let options = InterpolateOptions { output_path: Some(output.clone()), ..InterpolateOptions::default() };
+        assert_eq!(interpolate_file_with_options(&pieces[1..], &drives[0], &options, |_|{}).unwrap(), output);
+        assert_eq!(std::fs::read(&output).unwrap(), std::fs::read(&input).unwrap());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_repair_file() {
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap().to_path_buf();
+        let options = GenerateOptions { parity_overhead: 10, ..GenerateOptions::default() };
+        let pieces = generate_file_with_options(&input, 3, 2, 5717, &options, |_|{}).unwrap();
+        let originals: Vec<Vec<u8>> = pieces.iter().map(|piece| std::fs::read(piece).unwrap()).collect();
+
+        // Bad sectors in both of the pieces needed are corrected as they're read
+        for (piece, original) in pieces.iter().zip(originals.iter()) {
+            let mut damaged = original.clone();
+            for byte in &mut damaged[2000..3024] {
+                *byte = 0;
+            }
+            std::fs::write(piece, damaged).unwrap();
+        }
+        let output = directory.join("restored");
+        let interpolate_options = InterpolateOptions { output_path: Some(output.clone()), ..InterpolateOptions::default() };
+        interpolate_file_with_options(&pieces[0..2], &directory, &interpolate_options, |_|{}).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), std::fs::read(&input).unwrap());
+
+        // The damage isn't aligned with the blocks, since it's counted from the start of the header
+        let mut events = 0;
+        let repaired = repair_file(&pieces[0], |progress: &Progress| {
+            assert_eq!(progress.phase, Phase::Repairing);
+            events += 1;
+        }).unwrap();
+        assert!(repaired == 2 || repaired == 3);
+        assert!(events > 0);
+        assert_eq!(std::fs::read(&pieces[0]).unwrap(), originals[0]);
+        assert_eq!(repair_file(&pieces[0], |_|{}).unwrap(), 0);
+
+        // More damage than there is parity leaves the piece as it was
+        let mut damaged = originals[1].clone();
+        for byte in &mut damaged[1000..(originals[1].len() / 2)] {
+            *byte ^= 1;
+        }
+        std::fs::write(&pieces[1], &damaged).unwrap();
+        assert!(repair_file(&pieces[1], |_|{}).is_err());
+        assert_eq!(std::fs::read(&pieces[1]).unwrap(), damaged);
+
+        let plain_pieces = generate_file_with_options(&input, 3, 2, 5717, &GenerateOptions { overwrite: true, ..GenerateOptions::default() }, |_|{}).unwrap();
+        assert!(repair_file(&plain_pieces[0], |_|{}).is_err());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_roundtrip_armored_file() {
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap().to_path_buf();
+        let options = GenerateOptions { armor: true, ..GenerateOptions::default() };
+        let pieces = generate_file_with_options(&input, 3, 2, 5717, &options, |_|{}).unwrap();
+        assert_eq!(pieces[0], directory.join("testInput-1.shard.txt"));
+        let text = std::fs::read(&pieces[0]).unwrap();
+        assert!(text.is_ascii());
+        assert!(armor::is_armored(&text));
+
+        // Binary and armored pieces can be combined together
+        let binary_piece = directory.join("binary.shard");
+        let mut binary: Vec<u8> = Vec::new();
+        armor::ArmorReader::new(File::open(&pieces[1]).unwrap()).read_to_end(&mut binary).unwrap();
+        std::fs::write(&binary_piece, &binary).unwrap();
+
+        let output = directory.join("restored");
+        let interpolate_options = InterpolateOptions { output_path: Some(output.clone()), ..InterpolateOptions::default() };
+        interpolate_file_with_options(&[&pieces[0], &binary_piece], &directory, &interpolate_options, |_|{}).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), std::fs::read(&input).unwrap());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_roundtrip_encrypted_file() {
+        use age::secrecy::ExposeSecret;
+
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap().to_path_buf();
+        let identities: Vec<age::x25519::Identity> = (0..3).map(|_| age::x25519::Identity::generate()).collect();
+        let identity_files: Vec<PathBuf> = identities.iter().enumerate().map(|(index, identity)| {
+            let path = directory.join(format!("holder{}.key", index));
+            std::fs::write(&path, format!("{}\n", identity.to_string().expose_secret())).unwrap();
+            path
+        }).collect();
+
+        let options = GenerateOptions { recipients: identities.iter().map(|identity| identity.to_public().to_string()).collect(), ..GenerateOptions::default() };
+        let pieces = generate_file_with_options(&input, 3, 2, 5717, &options, |_|{}).unwrap();
+        assert!(Header::read(&mut open_file(&pieces[0]).unwrap()).is_err());
+        let armored = GenerateOptions { armor: true, overwrite: true, ..options.clone() };
+        assert!(generate_file_with_options(&input, 3, 2, 5717, &armored, |_|{}).is_err());
+
+        std::fs::remove_file(&input).unwrap();
+        assert!(interpolate_file(&[&pieces[2], &pieces[0]], &directory, |_|{}).is_err());
+        let wrong = InterpolateOptions { identities: vec![identity_files[1].clone()], ..InterpolateOptions::default() };
+        assert!(interpolate_file_with_options(&[&pieces[2], &pieces[0]], &directory, &wrong, |_|{}).is_err());
+        let holders = InterpolateOptions { identities: vec![identity_files[0].clone(), identity_files[2].clone()], ..InterpolateOptions::default() };
+        let output = interpolate_file_with_options(&[&pieces[2], &pieces[0]], &directory, &holders, |_|{}).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), std::fs::read(Path::new(file!()).parent().unwrap().parent().unwrap().join("tests/data/testInput")).unwrap());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_roundtrip_locked_file() {
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap().to_path_buf();
+        let passphrases = vec![Some(SecretString::from("first")), None, Some(SecretString::from("third"))];
+        let options = GenerateOptions { passphrases: passphrases.clone(), ..GenerateOptions::default() };
+        let pieces = generate_file_with_options(&input, 3, 2, 5717, &options, |_|{}).unwrap();
+        assert!(recipient::is_locked(&mut open_file(&pieces[0]).unwrap()).unwrap());
+        assert!(!recipient::is_locked(&mut open_file(&pieces[1]).unwrap()).unwrap());
+
+        std::fs::remove_file(&input).unwrap();
+        assert!(interpolate_file(&[&pieces[2], &pieces[1]], &directory, |_|{}).is_err());
+        let wrong = InterpolateOptions { passphrases: vec![Some(SecretString::from("first")), None], ..InterpolateOptions::default() };
+        assert!(interpolate_file_with_options(&[&pieces[2], &pieces[1]], &directory, &wrong, |_|{}).is_err());
+        let holders = InterpolateOptions { passphrases: vec![passphrases[2].clone(), None], ..InterpolateOptions::default() };
+        let output = interpolate_file_with_options(&[&pieces[2], &pieces[1]], &directory, &holders, |_|{}).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), std::fs::read(Path::new(file!()).parent().unwrap().parent().unwrap().join("tests/data/testInput")).unwrap());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_roundtrip_signed_file() {
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap().to_path_buf();
+        let key_file = directory.join("dealer.key");
+        let dealer = signature::generate_key(&key_file, false).unwrap();
+        let options = GenerateOptions { dealer_key: Some(key_file.clone()), parity_overhead: 10, ..GenerateOptions::default() };
+        let pieces = generate_file_with_options(&input, 3, 2, 5717, &options, |_|{}).unwrap();
+        let header = Header::read(&mut open_file(&pieces[0]).unwrap()).unwrap();
+        assert_eq!(header.extension(signature::DEALER_KEY), Some(dealer.as_str()));
+        let armored = GenerateOptions { armor: true, name_template: Some(String::from("{stem}-{index}.asc")), ..options.clone() };
+        let armored_pieces = generate_file_with_options(&input, 3, 2, 5717, &armored, |_|{}).unwrap();
+        let hiss = GenerateOptions { hiss: true, overwrite: true, ..options.clone() };
+        assert!(generate_file_with_options(&input, 3, 2, 5717, &hiss, |_|{}).is_err());
+
+        let expected = std::fs::read(&input).unwrap();
+        std::fs::remove_file(&input).unwrap();
+        let trusted = InterpolateOptions { dealer: Some(dealer.clone()), overwrite: true, ..InterpolateOptions::default() };
+        let output = interpolate_file_with_options(&pieces[1..3], &directory, &trusted, |_|{}).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), expected);
+        let output = interpolate_file_with_options(&[&armored_pieces[0], &armored_pieces[2]], &directory, &trusted, |_|{}).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), expected);
+
+        // Pieces from another dealer, or with no dealer at all, are refused
+        let other = InterpolateOptions { dealer: Some(signature::generate_key(directory.join("other.key"), false).unwrap()), overwrite: true, ..InterpolateOptions::default() };
+        assert!(interpolate_file_with_options(&pieces[1..3], &directory, &other, |_|{}).is_err());
+        std::fs::create_dir(directory.join("unsigned")).unwrap();
+        let unsigned = generate_file_with_options(&output, 3, 2, 5717, &GenerateOptions { output_directories: vec![directory.join("unsigned")], ..GenerateOptions::default() }, |_|{}).unwrap();
+        assert!(interpolate_file_with_options(&unsigned[0..2], &directory, &trusted, |_|{}).is_err());
+
+        // Parity repairs the piece without spoiling its signature, but a forged piece is refused even without a trusted dealer
+        let original = std::fs::read(&pieces[0]).unwrap();
+        let mut damaged = original.clone();
+        damaged[2000] ^= 1;
+        std::fs::write(&pieces[0], &damaged).unwrap();
+        assert!(interpolate_file_with_options(&pieces[0..2], &directory, &InterpolateOptions { overwrite: true, ..InterpolateOptions::default() }, |_|{}).is_err());
+        assert_eq!(repair_file(&pieces[0], |_|{}).unwrap(), 1);
+        assert_eq!(std::fs::read(&pieces[0]).unwrap(), original);
+        assert!(interpolate_file_with_options(&pieces[0..2], &directory, &trusted, |_|{}).is_ok());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_roundtrip_hiss_file() {
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap().to_path_buf();
+        let options = GenerateOptions { hiss: true, ..GenerateOptions::default() };
+        let pieces = generate_file_with_options(&input, 3, 2, 5717, &options, |_|{}).unwrap();
+        let header = Header::read(&mut open_file(&pieces[0]).unwrap()).unwrap();
+        assert_eq!((header.version, header.extensions.len()), (hiss::VERSION, 0));
+        let armored = GenerateOptions { hiss: true, armor: true, overwrite: true, ..GenerateOptions::default() };
+        assert!(generate_file_with_options(&input, 3, 2, 5717, &armored, |_|{}).is_err());
+
+        std::fs::remove_file(&input).unwrap();
+        let output = interpolate_file(&[&pieces[2], &pieces[0]], &directory, |_|{}).unwrap();
+        assert_eq!(output, input);
+        assert_eq!(std::fs::read(&output).unwrap(), std::fs::read(Path::new(file!()).parent().unwrap().parent().unwrap().join("tests/data/testInput")).unwrap());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_roundtrip_gfshare_file() {
+        let input = copy_input_to_temporary_directory();
+        let directory = input.parent().unwrap().to_path_buf();
+        let options = GenerateOptions { gfshare: true, ..GenerateOptions::default() };
+        let pieces = generate_file_with_options(&input, 3, 2, 5717, &options, |_|{}).unwrap();
+        assert!(pieces.iter().all(|piece| gfshare::share_number(piece).is_some() && piece.file_stem() == input.file_name()));
+        assert_eq!(std::fs::metadata(&pieces[0]).unwrap().len(), std::fs::metadata(&input).unwrap().len());
+        let armored = GenerateOptions { gfshare: true, armor: true, overwrite: true, ..GenerateOptions::default() };
+        assert!(generate_file_with_options(&input, 3, 2, 5717, &armored, |_|{}).is_err());
+
+        std::fs::remove_file(&input).unwrap();
+        // They can't be signed, so can't pass for a dealer's either
+        let trusted = InterpolateOptions { dealer: Some(signature::public_key(&SigningKey::from_bytes(&[7; 32]))), ..InterpolateOptions::default() };
+        assert!(interpolate_file_with_options(&[&pieces[2], &pieces[0]], &directory, &trusted, |_|{}).is_err());
+        let output = interpolate_file(&[&pieces[2], &pieces[0]], &directory, |_|{}).unwrap();
+        assert_eq!(output, input);
+        assert_eq!(std::fs::read(&output).unwrap(), std::fs::read(Path::new(file!()).parent().unwrap().parent().unwrap().join("tests/data/testInput")).unwrap());
+
+
+        std::fs::remove_dir_all(&directory).unwrap();
     }
 
     //    it "successfully roundtrips a file" do
@@ -703,9 +1841,10 @@ mod  tests {
         // Because the shards preserve the original file path, we copy the input file to the expected output path
         std::fs::copy(&input, &output).unwrap();
 
-        let pieces = generate_file(output.to_str().unwrap(), total_pieces, required_pieces, prime, |_| progress_callbacks += 1).unwrap();
+        let options = GenerateOptions { overwrite: true, ..GenerateOptions::default() };
+        let pieces = generate_file_with_options(&output, total_pieces, required_pieces, prime, &options, |_| progress_callbacks += 1).unwrap();
         for piece in &pieces {
-            assert!(Path::new(&piece).exists());
+            assert!(piece.exists());
         }
 
         std::fs::remove_file(&output).unwrap();
@@ -713,8 +1852,8 @@ mod  tests {
         assert!(progress_callbacks > 0);
         progress_callbacks = 0;
 
-        let result = interpolate_file(&choose_n_from(&pieces, required_pieces as usize), destination.to_str().unwrap(), |_| progress_callbacks += 1).unwrap();
-        assert_eq!(result.as_str(), output.to_str().unwrap());
+        let result = interpolate_file(&choose_n_from(&pieces, required_pieces as usize), &destination, |_| progress_callbacks += 1).unwrap();
+        assert_eq!(result, output);
 
         let mut input_data: Vec<u8> = Vec::new();
         let mut output_data: Vec<u8> = Vec::new();
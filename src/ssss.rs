@@ -0,0 +1,335 @@
+extern crate zeroize;
+
+use rand::prelude::*;
+use zeroize::Zeroize;
+
+use crate::secret;
+use crate::secret::SecretBuffer;
+
+//# Shares in the format of ssss-split and ssss-combine (http://point-at-infinity.org/ssss/), one per line:
+//# [token-]index-value
+//# index  from 1, zero-padded to the width of the number of shares
+//# value  in hex, a point on a polynomial over GF(2^n), for n the security level in bits
+//# The polynomial is monic, of degree the threshold, with the secret as its constant term. Secrets of 64 bits or more
+//# first go through a diffusion layer of zero-keyed XTEA rounds over the whole secret.
+
+pub const MAX_DEGREE: usize = 1024;
+pub const MAX_TOKEN_LENGTH: usize = 128;
+const MIN_DIFFUSION_DEGREE: usize = 64;
+// Rounds of the diffusion layer for each byte of the secret
+const DIFFUSION_ROUNDS: usize = 40;
+const XTEA_DELTA: u32 = 0x9e3779b9;
+const XTEA_CYCLES: u32 = 32;
+// The fields are modulo x^n + x^a + x^b + x^c + 1, with [a, b, c] for each n a multiple of 8 from 8 to MAX_DEGREE
+const IRREDUCIBLE: [[usize; 3]; MAX_DEGREE / 8] = [
+    [4, 3, 1], [5, 3, 1], [4, 3, 1], [7, 3, 2], [5, 4, 3], [5, 3, 2], [7, 4, 2], [4, 3, 1], [10, 9, 3], [9, 4, 2],
+    [7, 6, 2], [10, 9, 6], [4, 3, 1], [5, 4, 3], [4, 3, 1], [7, 2, 1], [5, 3, 2], [7, 4, 2], [6, 3, 2], [5, 3, 2],
+    [15, 3, 2], [11, 3, 2], [9, 8, 7], [7, 2, 1], [5, 3, 2], [9, 3, 1], [7, 3, 1], [9, 8, 3], [9, 4, 2], [8, 5, 3],
+    [15, 14, 10], [10, 5, 2], [9, 6, 2], [9, 3, 2], [9, 5, 2], [11, 10, 1], [7, 3, 2], [11, 2, 1], [9, 7, 4], [4, 3, 1],
+    [8, 3, 1], [7, 4, 1], [7, 2, 1], [13, 11, 6], [5, 3, 2], [7, 3, 2], [8, 7, 5], [12, 3, 2], [13, 10, 6], [5, 3, 2],
+    [5, 3, 2], [9, 5, 2], [9, 7, 2], [13, 4, 3], [4, 3, 1], [11, 6, 4], [18, 9, 6], [19, 18, 13], [11, 3, 2],
+    [15, 9, 6], [4, 3, 1], [16, 5, 2], [15, 14, 6], [8, 5, 2], [15, 11, 2], [11, 6, 2], [7, 5, 3], [8, 3, 1],
+    [19, 16, 9], [11, 9, 6], [15, 7, 6], [13, 4, 3], [14, 13, 3], [13, 6, 3], [9, 5, 2], [19, 13, 6], [19, 10, 3],
+    [11, 6, 5], [9, 2, 1], [14, 3, 2], [13, 3, 1], [7, 5, 4], [11, 9, 8], [11, 6, 5], [23, 16, 9], [19, 14, 6],
+    [23, 10, 2], [8, 3, 2], [5, 4, 3], [9, 6, 4], [4, 3, 2], [13, 8, 6], [13, 11, 1], [13, 10, 3], [11, 6, 5],
+    [19, 17, 4], [15, 14, 7], [13, 9, 6], [9, 7, 3], [9, 7, 1], [14, 3, 2], [11, 8, 2], [11, 6, 4], [13, 5, 2],
+    [11, 5, 1], [11, 4, 1], [19, 10, 3], [21, 10, 6], [13, 3, 1], [15, 7, 5], [19, 18, 10], [7, 5, 3], [12, 7, 2],
+    [7, 5, 1], [14, 9, 6], [10, 3, 2], [15, 13, 12], [12, 11, 9], [16, 9, 7], [12, 9, 3], [9, 5, 2], [17, 10, 6],
+    [24, 9, 3], [17, 15, 13], [5, 4, 3], [19, 17, 8], [15, 6, 3], [19, 6, 1],
+];
+
+//# One parsed share
+#[derive(Clone, Debug, PartialEq)]
+pub struct SsssShare {
+    pub token: Option<String>,
+    pub index: u32,
+    pub value: Vec<u8>,
+}
+
+impl SsssShare {
+    pub fn parse(text: &str) -> Result<SsssShare, String> {
+        let fields: Vec<&str> = text.trim().rsplitn(3, '-').collect();
+        if fields.len() < 2 || fields[0].len() % 2 != 0 || fields[0].len() * 4 > MAX_DEGREE || fields.get(2).map(|token| token.len() > MAX_TOKEN_LENGTH) == Some(true) {
+            return Err(String::from("Malformed ssss share"));
+        }
+
+        let value: Result<Vec<u8>, _> = (0..(fields[0].len() / 2)).map(|index| u8::from_str_radix(&fields[0][(index * 2)..(index * 2 + 2)], 16)).collect();
+        return match (fields[1].parse::<u32>(), value) {
+            (Ok(index), Ok(value)) if index > 0 && !value.is_empty() => Ok(SsssShare { token: fields.get(2).map(|token| String::from(*token)), index, value }),
+            _ => Err(String::from("Malformed ssss share")),
+        }
+    }
+}
+
+//# Split secret into pieces_count shares as ssss-split would, of which required_pieces_count are needed
+//# security_level is the size of the field in bits, a multiple of 8; the secret's length if None.
+pub fn split(secret: &[u8], pieces_count: u32, required_pieces_count: u32, token: Option<&str>, security_level: Option<usize>, diffusion: bool) -> Result<Vec<String>, String> {
+    secret::protect_process();
+    let degree = security_level.unwrap_or(secret.len() * 8);
+    if degree == 0 || degree % 8 != 0 || degree > MAX_DEGREE {
+        return Err(format!("The security level must be a multiple of 8 bits, up to {}", MAX_DEGREE));
+    }
+    if secret.len() * 8 > degree {
+        return Err(String::from("The secret is longer than the security level"));
+    }
+    if required_pieces_count < 2 || required_pieces_count > pieces_count {
+        return Err(String::from("The threshold must be at least 2, and no more than the number of shares"));
+    }
+    if token.map(|token| token.len() > MAX_TOKEN_LENGTH || token.contains('-')) == Some(true) {
+        return Err(format!("Tokens are at most {} characters, without dashes", MAX_TOKEN_LENGTH));
+    }
+
+    let field = Field::new(degree);
+    let mut padded = vec![0; degree / 8 - secret.len()];
+    padded.extend_from_slice(secret);
+    if diffusion && degree >= MIN_DIFFUSION_DEGREE {
+        diffuse(&mut padded, false);
+    }
+    let mut coefficients = vec![field.from_bytes(&padded)];
+    for _ in 1..required_pieces_count {
+        coefficients.push(field.from_bytes(&(0..(degree / 8)).map(|_| thread_rng().gen::<u8>()).collect::<Vec<u8>>()));
+    }
+
+    let width = pieces_count.to_string().len();
+    let prefix = token.map(|token| format!("{}-", token)).unwrap_or_default();
+    return Ok((1..=pieces_count).map(|index| {
+        // Horner's rule, starting from the leading coefficient of 1
+        let x = field.from_integer(index);
+        let mut y = x.clone();
+        for coefficient in coefficients[1..].iter().rev() {
+            y = field.multiply(&field.add(&y, coefficient), &x);
+        }
+        y = field.add(&y, &coefficients[0]);
+        let value: String = field.to_bytes(&y).iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("{}{:0width$}-{}", prefix, index, value, width = width)
+    }).collect());
+}
+
+//# Recover a secret from the first required_pieces_count of shares, as ssss-combine would
+//# The secret is the full security level in length, so may have leading zero bytes.
+pub fn combine<TShares, TShare>(shares: &TShares, required_pieces_count: u32, diffusion: bool) -> Result<SecretBuffer, String>
+    where TShares: AsRef<[TShare]> + ?Sized,
+        TShare: AsRef<str> {
+    let mut parsed: Vec<SsssShare> = Vec::new();
+    for (index, share) in shares.as_ref().iter().take(required_pieces_count as usize).enumerate() {
+        match SsssShare::parse(share.as_ref()) {
+            Err(message) => return Err(format!("Share {}: {}", index + 1, message)),
+            Ok(share) => parsed.push(share),
+        }
+    }
+    if required_pieces_count < 2 || (parsed.len() as u32) < required_pieces_count {
+        return Err(format!("Insufficient number of inputs ({} of {} required)", parsed.len(), required_pieces_count));
+    }
+    if parsed.iter().any(|share| share.value.len() != parsed[0].value.len()) {
+        return Err(String::from("Input shares have differing security levels"));
+    }
+    if parsed.iter().enumerate().any(|(index, share)| parsed[..index].iter().any(|other| other.index == share.index)) {
+        return Err(String::from("Input shares have duplicate indices"));
+    }
+
+    // Lagrange interpolation at 0 of the polynomial without its leading term; subtraction is addition
+    let degree = parsed[0].value.len() * 8;
+    let field = Field::new(degree);
+    let xs: Vec<Element> = parsed.iter().map(|share| field.from_integer(share.index)).collect();
+    let mut secret = field.from_integer(0);
+    for (index, share) in parsed.iter().enumerate() {
+        let mut y = field.add(&field.from_bytes(&share.value), &field.power(&xs[index], required_pieces_count));
+        for (other_index, other) in xs.iter().enumerate().filter(|(other_index, _)| *other_index != index) {
+            let denominator = field.add(&xs[index], &xs[other_index]);
+            y = field.multiply(&y, &field.multiply(other, &field.inverse(&denominator)));
+        }
+        let mut sum = field.add(&secret, &y);
+        std::mem::swap(&mut secret, &mut sum);
+        sum.zeroize();
+        y.zeroize();
+    }
+
+    let mut result = field.to_bytes(&secret);
+    secret.zeroize();
+    if diffusion && degree >= MIN_DIFFUSION_DEGREE {
+        diffuse(&mut result, true);
+    }
+    let buffer = SecretBuffer::from_slice(&result);
+    result.zeroize();
+    return Ok(buffer);
+}
+
+type Element = Vec<u64>;
+
+// GF(2^degree), with elements as polynomials over GF(2) in 64 bit limbs, least significant first
+struct Field {
+    degree: usize,
+    taps: [usize; 3],
+}
+
+impl Field {
+    fn new(degree: usize) -> Field {
+        return Field { degree, taps: IRREDUCIBLE[degree / 8 - 1] };
+    }
+
+    // One bit more than the degree, for the product by x before reduction
+    fn limbs(self: &Field) -> usize {
+        return self.degree / 64 + 1;
+    }
+
+    fn from_integer(self: &Field, value: u32) -> Element {
+        let mut result = vec![0; self.limbs()];
+        result[0] = value as u64;
+        return result;
+    }
+
+    // Big-endian
+    fn from_bytes(self: &Field, bytes: &[u8]) -> Element {
+        let mut result = vec![0; self.limbs()];
+        for (index, byte) in bytes.iter().rev().enumerate() {
+            result[index / 8] |= (*byte as u64) << (8 * (index % 8));
+        }
+        return result;
+    }
+
+    fn to_bytes(self: &Field, element: &Element) -> Vec<u8> {
+        return (0..(self.degree / 8)).rev().map(|index| (element[index / 8] >> (8 * (index % 8))) as u8).collect();
+    }
+
+    fn add(self: &Field, a: &Element, b: &Element) -> Element {
+        return a.iter().zip(b).map(|(a, b)| a ^ b).collect();
+    }
+
+    fn multiply(self: &Field, a: &Element, b: &Element) -> Element {
+        let mut result = vec![0; self.limbs()];
+        let mut shifted = a.clone();
+        for bit in 0..self.degree {
+            if b[bit / 64] >> (bit % 64) & 1 == 1 {
+                for (value, limb) in result.iter_mut().zip(&shifted) {
+                    *value ^= limb;
+                }
+            }
+
+            let mut carry = 0;
+            for limb in shifted.iter_mut() {
+                let next = *limb >> 63;
+                *limb = *limb << 1 | carry;
+                carry = next;
+            }
+            if shifted[self.degree / 64] >> (self.degree % 64) & 1 == 1 {
+                for tap in [self.degree, self.taps[0], self.taps[1], self.taps[2], 0].iter() {
+                    shifted[tap / 64] ^= 1 << (tap % 64);
+                }
+            }
+        }
+        return result;
+    }
+
+    fn power(self: &Field, a: &Element, exponent: u32) -> Element {
+        return (0..exponent).fold(self.from_integer(1), |result, _| self.multiply(&result, a));
+    }
+
+    // a^(2^degree - 2), the product of a^(2^i) for i from 1 to degree - 1
+    fn inverse(self: &Field, a: &Element) -> Element {
+        let mut result = self.from_integer(1);
+        let mut square = a.clone();
+        for _ in 1..self.degree {
+            square = self.multiply(&square, &square);
+            result = self.multiply(&result, &square);
+        }
+        return result;
+    }
+}
+
+// The diffusion layer works on the secret as ssss exports it from GMP: as 16 bit big-endian words, least significant
+// first, with the odd byte of a secret of odd length moved down into place
+fn diffuse(secret: &mut [u8], decode: bool) {
+    let length = secret.len();
+    let words = (length + 1) / 2;
+    let mut padded = vec![0; words * 2];
+    padded[(words * 2 - length)..].copy_from_slice(secret);
+    let mut data: Vec<u8> = (0..words).flat_map(|word| vec![padded[words * 2 - 2 - word * 2], padded[words * 2 - 1 - word * 2]]).collect();
+    if length % 2 == 1 {
+        data[length - 1] = data[length];
+    }
+
+    let slices: Vec<usize> = (0..(DIFFUSION_ROUNDS * length)).step_by(2).collect();
+    if decode {
+        slices.iter().rev().for_each(|slice| xtea_slice(&mut data[..length], *slice, true));
+    } else {
+        slices.iter().for_each(|slice| xtea_slice(&mut data[..length], *slice, false));
+    }
+
+    if length % 2 == 1 {
+        data[length] = data[length - 1];
+        data[length - 1] = 0;
+    }
+    for word in 0..words {
+        padded[words * 2 - 2 - word * 2] = data[word * 2];
+        padded[words * 2 - 1 - word * 2] = data[word * 2 + 1];
+    }
+    secret.copy_from_slice(&padded[(words * 2 - length)..]);
+}
+
+// XTEA with a zero key over the 8 bytes from start, wrapping around the end of data
+fn xtea_slice(data: &mut [u8], start: usize, decipher: bool) {
+    let length = data.len();
+    let mut block = [0u32; 2];
+    for (half, value) in block.iter_mut().enumerate() {
+        *value = (0..4).fold(0, |value, byte| value << 8 | data[(start + half * 4 + byte) % length] as u32);
+    }
+
+    let mix = |value: u32| ((value << 4) ^ (value >> 5)).wrapping_add(value);
+    if decipher {
+        let mut sum = XTEA_DELTA.wrapping_mul(XTEA_CYCLES);
+        for _ in 0..XTEA_CYCLES {
+            block[1] = block[1].wrapping_sub(mix(block[0]) ^ sum);
+            sum = sum.wrapping_sub(XTEA_DELTA);
+            block[0] = block[0].wrapping_sub(mix(block[1]) ^ sum);
+        }
+    } else {
+        let mut sum: u32 = 0;
+        for _ in 0..XTEA_CYCLES {
+            block[0] = block[0].wrapping_add(mix(block[1]) ^ sum);
+            sum = sum.wrapping_add(XTEA_DELTA);
+            block[1] = block[1].wrapping_add(mix(block[0]) ^ sum);
+        }
+    }
+
+    for (half, value) in block.iter().enumerate() {
+        for byte in 0..4 {
+            data[(start + half * 4 + byte) % length] = (value >> (24 - 8 * byte)) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example from the ssss manual
+    #[test]
+    fn test_combine_ssss_example() {
+        let shares = [
+            "3-fa1c3a9c6df8af0779c36de6c33f6e36e989d0e0b91309",
+            "5-4756974923c0dce0a55f4774d09ca7a4865f64f56a4ee0",
+            "2-fbc74a03a50e14ab406c225afb5f45c40ae11976d2b665",
+        ];
+        assert_eq!(combine(&shares, 3, true).unwrap().as_slice(), b"my secret root password");
+        assert!(combine(&shares[..2], 3, true).is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_ssss() {
+        for (secret, security_level) in &[(&b"short"[..], None), (&b"a secret of odd length"[..], None), (&b"padded"[..], Some(128)), (&[7u8; 40][..], None)] {
+            for diffusion in &[true, false] {
+                let shares = split(secret, 11, 4, Some("token"), *security_level, *diffusion).unwrap();
+                assert!(shares[0].starts_with("token-01-"));
+                let combined = combine(&[&shares[10], &shares[3], &shares[0], &shares[7]], 4, *diffusion).unwrap();
+                assert!(combined.as_slice().ends_with(secret));
+                assert!(combined.as_slice()[..(combined.len() - secret.len())].iter().all(|byte| *byte == 0));
+            }
+        }
+
+        let mut secret = b"a secret".to_vec();
+        diffuse(&mut secret, false);
+        assert!(secret != b"a secret");
+        diffuse(&mut secret, true);
+        assert_eq!(secret, b"a secret");
+    }
+}
@@ -0,0 +1,892 @@
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::io::{ErrorKind, Read, Write};
+
+use crate::chunk;
+use crate::chunk::{ChunkReader, ChunkWriter};
+use crate::field;
+use crate::header;
+use crate::header::Header;
+use crate::parity::{Layout, ParityDecoder, ParityEncoder};
+use crate::progress;
+use crate::progress::{Control, Phase, Progress};
+use crate::secret;
+use crate::secret::SecretBuffer;
+use crate::signature::Trailer;
+use crate::sss;
+use crate::sss::BUFFER_SIZE;
+
+//# Incrementally split a secret into pieces
+//# Each call to update consumes a block of the secret and returns the body data to append to each piece
+//# See chunk for the format of the body, and parity for the parity that may be added to it
+pub struct Splitter {
+    pieces_count: i32,
+    required_pieces_count: i32,
+    prime: i32,
+    writers: Vec<ChunkWriter>,
+    encoders: Option<Vec<ParityEncoder>>,
+}
+
+impl Splitter {
+    pub fn new(pieces_count: i32, required_pieces_count: i32, prime: i32) -> Result<Splitter, String> {
+        secret::protect_process();
+        if required_pieces_count < 2 {
+            return Err(format!("At least 2 pieces must be required ({})", required_pieces_count));
+        }
+        if pieces_count < required_pieces_count {
+            return Err(format!("Total pieces ({}) must not be less than required pieces ({})", pieces_count, required_pieces_count));
+        }
+        // Every byte value must be representable, and every point must fit in an i16
+        if prime <= u8::max_value() as i32 || prime > i16::max_value() as i32 {
+            return Err(format!("Prime {} must be between {} and {}", prime, u8::max_value(), i16::max_value()));
+        }
+        field::validate_prime(prime)?;
+
+        let writers = (0..pieces_count).map(|_| ChunkWriter::new(true)).collect();
+        return Ok(Splitter { pieces_count, required_pieces_count, prime, writers, encoders: None });
+    }
+
+    // Split into pieces with headers based on template, which supplies the prime and any parity layout
+    pub fn for_header(pieces_count: i32, required_pieces_count: i32, template: &Header) -> Result<Splitter, String> {
+        let mut splitter = Splitter::new(pieces_count, required_pieces_count, template.prime)?;
+        if let Some(layout) = Layout::from_header(template)? {
+            splitter.encoders = Some((0..pieces_count).map(|_| ParityEncoder::new(layout)).collect());
+        }
+        return Ok(splitter);
+    }
+
+    pub fn update<T>(self: &mut Splitter, data: &T) -> Vec<Vec<u8>>
+        where T: AsRef<[u8]> + ?Sized {
+        let mut bodies: Vec<Vec<u8>> = (0..self.pieces_count).map(|_| Vec::new()).collect();
+
+        // An empty chunk would mark the end of the pieces
+        for block in data.as_ref().chunks(BUFFER_SIZE) {
+            let pieces = sss::generate_string(block, self.pieces_count, self.required_pieces_count, self.prime, |_|{});
+            for ((body, writer), piece) in bodies.iter_mut().zip(self.writers.iter_mut()).zip(pieces) {
+                body.extend(writer.chunk(&piece.1));
+            }
+        }
+
+        if let Some(ref mut encoders) = self.encoders {
+            return bodies.iter().zip(encoders.iter_mut()).map(|(body, encoder)| encoder.update(body)).collect();
+        }
+        return bodies;
+    }
+
+    // Complete the split, returning the remaining body data for each piece
+    pub fn finish(self: Splitter) -> Vec<Vec<u8>> {
+        let bodies: Vec<Vec<u8>> = self.writers.into_iter().map(|writer| writer.end()).collect();
+        return match self.encoders {
+            None => bodies,
+            Some(encoders) => bodies.iter().zip(encoders).map(|(body, mut encoder)| {
+                let mut result = encoder.update(body);
+                result.extend(encoder.finish());
+                result
+            }).collect(),
+        }
+    }
+}
+
+//# Header for the piece with index, based on template, recording how many pieces are required
+pub fn piece_header(template: &Header, index: i32, required_pieces_count: i32) -> Header {
+    let mut header = Header { index, ..template.clone() };
+    header.extensions.retain(|extension| extension.0 != sss::REQUIRED_KEY);
+    header.add_extension(sss::REQUIRED_KEY, required_pieces_count.to_string());
+    return header;
+}
+
+//# Incrementally recover a secret from its pieces
+//# Each call to update accepts the next body data for each piece, in any amount,
+//# and returns as much of the secret as can be recovered so far
+//# When more pieces are supplied than are required, each chunk is recovered from pieces in which it
+//# is intact, so several damaged pieces can still make up for each other.
+//# Pieces with parity are repaired as they're read, wherever their parity allows.
+pub struct Combiner {
+    indices: Vec<i32>,
+    prime: i32,
+    required: usize,
+    decoders: Vec<Option<ParityDecoder>>,
+    // Unsolved points of each piece, for versions without chunks
+    pending: Vec<Vec<u8>>,
+    readers: Option<Vec<ChunkReader>>,
+    // Chunks recovered so far
+    sequence: u32,
+    // Pieces whose sources have ended, for step
+    ended: Vec<bool>,
+    // Signatures to be left off the end of signed pieces, and checked once they've all been supplied
+    trailers: Vec<Option<Trailer>>,
+}
+
+impl Combiner {
+    // Combine pieces in the current format, all of which are needed
+    pub fn new<T>(indices: &T, prime: i32) -> Result<Combiner, String>
+        where T: AsRef<[i32]> + ?Sized {
+        return Combiner::with_version(indices, prime, sss::VERSION, indices.as_ref().len());
+    }
+
+    // Combine pieces in the format given by their headers
+    pub fn for_headers(headers: &[Header]) -> Result<Combiner, String> {
+        let indices: Vec<i32> = headers.iter().map(|header| header.index).collect();
+        let required = match headers[0].extension(sss::REQUIRED_KEY) {
+            None => indices.len(),
+            Some(required) => match required.parse::<usize>() {
+                Err(error) => return Err(format!("Error parsing required pieces: {}", error)),
+                Ok(required) => required,
+            },
+        };
+        if required > indices.len() {
+            return Err(format!("Insufficient number of inputs ({} of {} required)", indices.len(), required));
+        }
+        let mut combiner = Combiner::with_version(&indices, headers[0].prime, headers[0].version, required)?;
+        for ((decoder, trailer), header) in combiner.decoders.iter_mut().zip(combiner.trailers.iter_mut()).zip(headers) {
+            *decoder = Layout::from_header(header)?.map(ParityDecoder::new);
+            *trailer = Trailer::new(header)?;
+        }
+        return Ok(combiner);
+    }
+
+    fn with_version<T>(indices: &T, prime: i32, version: i32, required: usize) -> Result<Combiner, String>
+        where T: AsRef<[i32]> + ?Sized {
+        let my_indices = indices.as_ref();
+        if my_indices.len() < 2 || required < 2 {
+            return Err(format!("Insufficient number of inputs ({})", my_indices.len()));
+        }
+        if (1..my_indices.len()).any(|i| my_indices[i..].iter().any(|value| *value == my_indices[i - 1])) {
+            return Err(String::from("Duplicate indices in input files"));
+        }
+
+        let readers = if version >= chunk::FIRST_CHUNKED_VERSION {
+            Some(my_indices.iter().map(|index| ChunkReader::new(*index, version >= chunk::FIRST_HASHED_VERSION)).collect())
+        } else {
+            None
+        };
+
+        return Ok(Combiner {
+            indices: my_indices.to_vec(),
+            prime,
+            required,
+            decoders: my_indices.iter().map(|_| None).collect(),
+            pending: my_indices.iter().map(|_| Vec::new()).collect(),
+            readers,
+            sequence: 0,
+            ended: my_indices.iter().map(|_| false).collect(),
+            trailers: my_indices.iter().map(|_| None).collect(),
+        });
+    }
+
+    pub fn update<TPieces, TBytes>(self: &mut Combiner, pieces: &TPieces) -> Result<SecretBuffer, String>
+        where TPieces: AsRef<[TBytes]> + ?Sized,
+            TBytes: AsRef<[u8]> {
+        let my_pieces = pieces.as_ref();
+        if my_pieces.len() != self.indices.len() {
+            return Err(format!("Expected data for {} pieces, got {}", self.indices.len(), my_pieces.len()));
+        }
+        let my_pieces: Vec<Cow<[u8]>> = my_pieces.iter().zip(self.trailers.iter_mut()).map(|(piece, trailer)| match trailer {
+            None => Cow::Borrowed(piece.as_ref()),
+            Some(trailer) => Cow::Owned(trailer.update(piece.as_ref())),
+        }).collect();
+
+        if let Some(ref mut readers) = self.readers {
+            for ((reader, decoder), piece) in readers.iter_mut().zip(self.decoders.iter_mut()).zip(my_pieces.iter()) {
+                match decoder {
+                    None => reader.update(piece.as_ref()),
+                    Some(decoder) => reader.update(&decoder.update(piece.as_ref())),
+                }
+            }
+            return self.recover_chunks();
+        }
+
+        for (pending, piece) in self.pending.iter_mut().zip(my_pieces.iter()) {
+            pending.extend_from_slice(piece.as_ref());
+        }
+
+        // Only whole points that are available from every piece can be solved
+        let available = self.pending.iter().map(|pending| pending.len()).min().unwrap() & !1;
+        if available == 0 {
+            return Ok(SecretBuffer::new());
+        }
+
+        let point_buffers: Vec<(i32, Vec<i16>)> = self.indices.iter().map(|x| *x).zip(self.pending.iter_mut().map(|pending| {
+            sss::binary_buffer_to_points(&pending.drain(0..available).collect::<Vec<u8>>())
+        })).collect();
+
+        return sss::interpolate_buffer(&point_buffers, self.prime, |_|{});
+    }
+
+    // Recover every chunk that is available from all the pieces still being read
+    fn recover_chunks(self: &mut Combiner) -> Result<SecretBuffer, String> {
+        let mut result = SecretBuffer::new();
+        let readers = self.readers.as_mut().unwrap();
+
+        loop {
+            if readers.iter().any(|reader| !reader.is_done() && reader.queued_chunks() == 0) ||
+                readers.iter().all(|reader| reader.queued_chunks() == 0) {
+                return Ok(result);
+            }
+
+            let chunks: Vec<Option<Option<Vec<u8>>>> = readers.iter_mut().map(|reader| reader.take()).collect();
+            let intact: Vec<(i32, &Vec<u8>)> = self.indices.iter().zip(chunks.iter()).filter_map(|(index, chunk)| match chunk {
+                Some(Some(points)) => Some((*index, points)),
+                _ => None,
+            }).take(self.required).collect();
+
+            if intact.len() < self.required {
+                return Err(format!("Chunk {} is damaged or missing in too many pieces ({} intact, {} required){}",
+                                   self.sequence, intact.len(), self.required, Combiner::describe_problems(readers)));
+            }
+            if intact.iter().any(|chunk| chunk.1.len() != intact[0].1.len()) {
+                return Err(format!("Mismatched lengths for chunk {} in input files", self.sequence));
+            }
+
+            let point_buffers: Vec<(i32, Vec<i16>)> = intact.iter().map(|chunk| (chunk.0, sss::binary_buffer_to_points(chunk.1))).collect();
+            result.extend_from_slice(&sss::interpolate_buffer(&point_buffers, self.prime, |_|{})?);
+            self.sequence += 1;
+        }
+    }
+
+    fn describe_problems(readers: &[ChunkReader]) -> String {
+        let problems: Vec<&str> = readers.iter().filter_map(|reader| reader.problem()).collect();
+        return if problems.is_empty() { String::new() } else { format!(": {}", problems.join("; ")) };
+    }
+
+    // Number of bytes of points received for piece that have not yet been solved
+    // This doesn't count partly received chunks, which can't be used until the rest arrives
+    pub fn pending_length(self: &Combiner, piece: usize) -> usize {
+        return match self.readers {
+            None => self.pending[piece].len(),
+            Some(ref readers) => readers[piece].queued_length(),
+        }
+    }
+
+    // Whether more of piece should be read for the next step; a piece that has ended isn't read again, and one
+    // that has got too far ahead of the others isn't read until they catch up
+    pub fn wants_input(self: &Combiner, piece: usize) -> bool {
+        return !self.ended[piece] && self.pending_length(piece) < BUFFER_SIZE;
+    }
+
+    //# Take what was read from each piece wanted by wants_input, given as None for the pieces that weren't read,
+    //# and an empty read for those that have ended, and return as much of the secret as can be recovered so far
+    //# Once no piece has anything more, the combination is completed, and the rest of the secret is returned with true.
+    //# This is all of combining sources but the reading itself, so that blocking and asynchronous readers share it.
+    pub fn step<T>(self: &mut Combiner, reads: &[Option<T>]) -> Result<(SecretBuffer, bool), String>
+        where T: AsRef<[u8]> {
+        if reads.len() != self.indices.len() {
+            return Err(format!("Expected data for {} pieces, got {}", self.indices.len(), reads.len()));
+        }
+        for (ended, read) in self.ended.iter_mut().zip(reads.iter()) {
+            *ended |= read.as_ref().map_or(false, |data| data.as_ref().is_empty());
+        }
+        if reads.iter().all(|read| read.as_ref().map_or(true, |data| data.as_ref().is_empty())) {
+            return Ok((self.finish_input()?, true));
+        }
+
+        let pieces: Vec<&[u8]> = reads.iter().map(|read| read.as_ref().map_or(&[] as &[u8], |data| data.as_ref())).collect();
+        return Ok((self.update(&pieces)?, false));
+    }
+
+    // Complete the combination once all the data has been supplied, returning the rest of the secret
+    // Fails if the pieces were truncated, or did not all contain the same amount of data
+    pub fn finish(mut self: Combiner) -> Result<SecretBuffer, String> {
+        return self.finish_input();
+    }
+
+    fn finish_input(self: &mut Combiner) -> Result<SecretBuffer, String> {
+        for (index, trailer) in self.indices.iter().zip(self.trailers.iter()) {
+            if let Some(trailer) = trailer {
+                if let Err(message) = trailer.verify() {
+                    return Err(format!("Piece {}: {}", index, message));
+                }
+            }
+        }
+
+        if self.readers.is_none() {
+            if self.pending.iter().any(|pending| !pending.is_empty()) {
+                return Err(String::from("Mismatched buffer sizes in input files"));
+            }
+            return Ok(SecretBuffer::new());
+        }
+
+        for (reader, decoder) in self.readers.as_mut().unwrap().iter_mut().zip(self.decoders.iter_mut()) {
+            if let Some(decoder) = decoder {
+                reader.update(&decoder.finish());
+            }
+            reader.finish();
+        }
+        let result = self.recover_chunks()?;
+
+        // The end has to be vouched for by at least one piece, or every piece may have been cut short
+        let readers = self.readers.as_ref().unwrap();
+        if !readers.iter().any(|reader| reader.end() == Some(self.sequence)) {
+            return Err(format!("Input files are truncated{}", Combiner::describe_problems(readers)));
+        }
+
+        return Ok(result);
+    }
+}
+
+pub(crate) fn read_source<R>(source: &mut R, buffer: &mut [u8]) -> Result<usize, String>
+    where R: Read {
+    loop {
+        return match source.read(buffer) {
+            Err(ref error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => Err(format!("Error reading input: {}", error)),
+            Ok(read) => Ok(read),
+        }
+    }
+}
+
+// Read until buffer is full or source ends, so that every piece is read in step
+pub(crate) fn fill_buffer<R>(source: &mut R, buffer: &mut [u8]) -> Result<usize, String>
+    where R: Read {
+    let mut length = 0;
+    while length < buffer.len() {
+        let read = read_source(source, &mut buffer[length..])?;
+        if read == 0 {
+            break;
+        }
+        length += read;
+    }
+    return Ok(length);
+}
+
+pub(crate) fn write_sink<W, T>(sink: &mut W, data: &T) -> Result<(), String>
+    where W: Write,
+        T: AsRef<[u8]> + ?Sized {
+    return match sink.write_all(data.as_ref()) {
+        Err(error) => Err(format!("Error writing output: {}", error)),
+        Ok(()) => Ok(()),
+    }
+}
+
+pub(crate) fn flush_sink<W>(sink: &mut W) -> Result<(), String>
+    where W: Write {
+    return match sink.flush() {
+        Err(error) => Err(format!("Error writing output: {}", error)),
+        Ok(()) => Ok(()),
+    }
+}
+
+//# Split everything read from secret into one piece per sink, each preceded by a header naming filename
+//# progress_callback may cancel the split, in which case the sinks are left incomplete
+pub fn split<TName, R, W, TProgress, TControl>(secret: &mut R, sinks: &mut [W], required_pieces_count: i32, prime: i32, filename: &TName, progress_callback: TProgress) -> Result<u64, String>
+    where TName: AsRef<OsStr> + ?Sized,
+        R: Read,
+        W: Write,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    return split_with_header(secret, sinks, required_pieces_count, &Header::new(0, prime, filename), progress_callback);
+}
+
+//# As split, but with every piece's header based on template, which supplies the prime, filename and extensions
+pub fn split_with_header<R, W, TProgress, TControl>(secret: &mut R, sinks: &mut [W], required_pieces_count: i32, template: &Header, mut progress_callback: TProgress) -> Result<u64, String>
+    where R: Read,
+        W: Write,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    header::validate_filename(&template.filename_os())?;
+    let mut splitter = Splitter::for_header(sinks.len() as i32, required_pieces_count, template)?;
+    let mut buffer = SecretBuffer::zeroed(BUFFER_SIZE);
+    let mut progress: u64 = 0;
+
+    for index in 0..sinks.len() {
+        progress::report(&mut progress_callback, Progress::new(Phase::WritingHeaders, 0, None, Some(index)))?;
+        piece_header(template, index as i32 + 1, required_pieces_count).write(&mut sinks[index])?;
+    }
+
+    loop {
+        let length = read_source(secret, &mut buffer[..])?;
+        if length == 0 {
+            break;
+        }
+
+        progress += length as u64;
+        for (index, (sink, piece)) in sinks.iter_mut().zip(splitter.update(&buffer[0..length])).enumerate() {
+            write_sink(sink, &piece)?;
+            progress::report(&mut progress_callback, Progress::new(Phase::Splitting, progress, None, Some(index)))?;
+        }
+    }
+
+    for (sink, piece) in sinks.iter_mut().zip(splitter.finish()) {
+        write_sink(sink, &piece)?;
+        flush_sink(sink)?;
+    }
+
+    return Ok(progress);
+}
+
+//# Recover the secret from the pieces read from sources, writing it into sink
+//# Returns the header shared by the pieces
+pub fn combine<R, W, TProgress, TControl>(sources: &mut [R], sink: &mut W, mut progress_callback: TProgress) -> Result<Header, String>
+    where R: Read,
+        W: Write,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    let headers = sss::read_headers(sources, &mut progress_callback)?;
+    combine_bodies(sources, &headers, sink, progress_callback)?;
+    return Ok(headers[0].clone());
+}
+
+//# Recover the secret from sources whose headers have already been read
+//# progress_callback may cancel the combination, in which case sink is left incomplete
+pub fn combine_bodies<R, W, TProgress, TControl>(sources: &mut [R], headers: &[Header], sink: &mut W, mut progress_callback: TProgress) -> Result<u64, String>
+    where R: Read,
+        W: Write,
+        TProgress: FnMut(&Progress) -> TControl,
+        TControl: Into<Control> {
+    let mut combiner = Combiner::for_headers(headers)?;
+    let mut buffers: Vec<Vec<u8>> = sources.iter().map(|_| vec![0 as u8; BUFFER_SIZE]).collect();
+    let mut progress: u64 = 0;
+
+    loop {
+        let mut lengths: Vec<Option<usize>> = Vec::new();
+        for (index, (source, buffer)) in sources.iter_mut().zip(buffers.iter_mut()).enumerate() {
+            lengths.push(if combiner.wants_input(index) { Some(read_source(source, &mut buffer[..])?) } else { None });
+        }
+
+        let reads: Vec<Option<&[u8]>> = buffers.iter().zip(lengths.iter()).map(|(buffer, length)| length.map(|length| &buffer[0..length])).collect();
+        let (result, done) = combiner.step(&reads)?;
+        write_sink(sink, &result)?;
+        progress += result.len() as u64;
+        if done {
+            break;
+        }
+        progress::report(&mut progress_callback, Progress::new(Phase::Combining, progress, None, None))?;
+    }
+    flush_sink(sink)?;
+
+    return Ok(progress);
+}
+
+//# Writer that splits everything written to it into one piece per sink
+//# For producers, such as archive builders, that want to write the secret rather than have it read.
+//# Headers are written on creation, and finish must be called once the secret is complete.
+pub struct SplitWriter<'a, W, TProgress> {
+    splitter: Splitter,
+    sinks: &'a mut [W],
+    progress_callback: TProgress,
+    progress: u64,
+    error: Option<String>,
+}
+
+impl<'a, W, TProgress> SplitWriter<'a, W, TProgress>
+    where W: Write,
+        TProgress: FnMut(&Progress) -> Control {
+    pub fn new(sinks: &'a mut [W], required_pieces_count: i32, template: &Header, mut progress_callback: TProgress) -> Result<SplitWriter<'a, W, TProgress>, String> {
+        header::validate_filename(&template.filename_os())?;
+        let splitter = Splitter::for_header(sinks.len() as i32, required_pieces_count, template)?;
+
+        for index in 0..sinks.len() {
+            progress::report(&mut progress_callback, Progress::new(Phase::WritingHeaders, 0, None, Some(index)))?;
+            piece_header(template, index as i32 + 1, required_pieces_count).write(&mut sinks[index])?;
+        }
+
+        return Ok(SplitWriter { splitter, sinks, progress_callback, progress: 0, error: None });
+    }
+
+    // The reason the last write failed, since the io::Error returned has to go through whoever is writing
+    pub fn error(self: &SplitWriter<'a, W, TProgress>) -> Option<&str> {
+        return self.error.as_ref().map(|error| error.as_str());
+    }
+
+    // Complete the split, returning the number of bytes of the secret
+    pub fn finish(self: SplitWriter<'a, W, TProgress>) -> Result<u64, String> {
+        for (sink, piece) in self.sinks.iter_mut().zip(self.splitter.finish()) {
+            write_sink(sink, &piece)?;
+            flush_sink(sink)?;
+        }
+
+        return Ok(self.progress);
+    }
+
+    fn split(self: &mut SplitWriter<'a, W, TProgress>, data: &[u8]) -> Result<(), String> {
+        for (sink, piece) in self.sinks.iter_mut().zip(self.splitter.update(data)) {
+            write_sink(sink, &piece)?;
+        }
+        self.progress += data.len() as u64;
+        return progress::report(&mut self.progress_callback, Progress::new(Phase::Splitting, self.progress, None, None));
+    }
+}
+
+impl<'a, W, TProgress> Write for SplitWriter<'a, W, TProgress>
+    where W: Write,
+        TProgress: FnMut(&Progress) -> Control {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let length = std::cmp::min(data.len(), BUFFER_SIZE);
+        return match self.split(&data[0..length]) {
+            Err(message) => {
+                self.error = Some(message.clone());
+                Err(std::io::Error::new(ErrorKind::Other, message))
+            },
+            Ok(()) => Ok(length),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return Ok(());
+    }
+}
+
+//# Reader that recovers the secret from sources whose headers have already been read
+//# For consumers, such as archive extractors, that want to read the secret rather than have it written.
+//# Reaching the end checks that the pieces all contained the same amount of data.
+pub struct CombineReader<'a, R, TProgress> {
+    combiner: Option<Combiner>,
+    sources: &'a mut [R],
+    buffer: Vec<u8>,
+    output: SecretBuffer,
+    position: usize,
+    progress_callback: TProgress,
+    progress: u64,
+    error: Option<String>,
+}
+
+impl<'a, R, TProgress> CombineReader<'a, R, TProgress>
+    where R: Read,
+        TProgress: FnMut(&Progress) -> Control {
+    pub fn new(sources: &'a mut [R], headers: &[Header], progress_callback: TProgress) -> Result<CombineReader<'a, R, TProgress>, String> {
+        let combiner = Combiner::for_headers(headers)?;
+
+        return Ok(CombineReader {
+            combiner: Some(combiner),
+            sources,
+            buffer: vec![0 as u8; BUFFER_SIZE],
+            output: SecretBuffer::new(),
+            position: 0,
+            progress_callback,
+            progress: 0,
+            error: None,
+        });
+    }
+
+    // The reason the last read failed, since the io::Error returned has to go through whoever is reading
+    pub fn error(self: &CombineReader<'a, R, TProgress>) -> Option<&str> {
+        return self.error.as_ref().map(|error| error.as_str());
+    }
+
+    // Replace the output with the next block of the secret, leaving it empty at the end
+    fn combine(self: &mut CombineReader<'a, R, TProgress>) -> Result<(), String> {
+        self.output.truncate(0);
+        self.position = 0;
+
+        while self.output.is_empty() {
+            let mut combiner = match self.combiner.take() {
+                None => return Ok(()),
+                Some(combiner) => combiner,
+            };
+
+            let mut reads: Vec<Option<Vec<u8>>> = Vec::new();
+            for (index, source) in self.sources.iter_mut().enumerate() {
+                if !combiner.wants_input(index) {
+                    reads.push(None);
+                    continue;
+                }
+                let length = read_source(source, &mut self.buffer[..])?;
+                reads.push(Some(self.buffer[0..length].to_vec()));
+            }
+
+            let (result, done) = combiner.step(&reads)?;
+            self.output.extend_from_slice(&result);
+            self.progress += result.len() as u64;
+            if done {
+                return Ok(());
+            }
+            self.combiner = Some(combiner);
+            progress::report(&mut self.progress_callback, Progress::new(Phase::Combining, self.progress, None, None))?;
+        }
+
+        return Ok(());
+    }
+}
+
+impl<'a, R, TProgress> Read for CombineReader<'a, R, TProgress>
+    where R: Read,
+        TProgress: FnMut(&Progress) -> Control {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.output.len() {
+            if let Err(message) = self.combine() {
+                self.error = Some(message.clone());
+                return Err(std::io::Error::new(ErrorKind::Other, message));
+            }
+        }
+
+        let length = std::cmp::min(buffer.len(), self.output.len() - self.position);
+        buffer[0..length].copy_from_slice(&self.output[self.position..(self.position + length)]);
+        self.position += length;
+        return Ok(length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use ed25519_dalek::SigningKey;
+    use rand::prelude::*;
+    use crate::signature;
+    use crate::signature::SignWriter;
+
+    // Yields at most one byte per read, like a slow pipe
+    struct TrickleReader<R> {
+        source: R,
+    }
+
+    impl<R: Read> Read for TrickleReader<R> {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            let length = std::cmp::min(1, buffer.len());
+            return self.source.read(&mut buffer[0..length]);
+        }
+    }
+
+    fn split_to_vectors(secret: &[u8], pieces_count: usize, required_pieces_count: i32) -> Vec<Vec<u8>> {
+        let mut sinks: Vec<Vec<u8>> = (0..pieces_count).map(|_| Vec::new()).collect();
+        let mut progress_callbacks = 0;
+        let written = split(&mut Cursor::new(secret), &mut sinks, required_pieces_count, 5717, "secret", |progress: &Progress| {
+            assert!(progress.shard.unwrap() < pieces_count);
+            progress_callbacks += 1;
+        }).unwrap();
+        assert_eq!(written, secret.len() as u64);
+        assert!(progress_callbacks > pieces_count);
+        return sinks;
+    }
+
+    #[test]
+    fn test_splitter_combiner_incremental() {
+        let secret: Vec<u8> = (0..100).map(|_| random::<u8>()).collect();
+        let mut splitter = Splitter::new(5, 3, 5717).unwrap();
+        let mut bodies: Vec<Vec<u8>> = (0..5).map(|_| Vec::new()).collect();
+
+        for chunk in secret.chunks(7) {
+            for (body, piece) in bodies.iter_mut().zip(splitter.update(chunk)) {
+                body.extend(piece);
+            }
+        }
+        for (body, piece) in bodies.iter_mut().zip(splitter.finish()) {
+            body.extend(piece);
+        }
+
+        // Feed uneven amounts of each piece at a time
+        let mut combiner = Combiner::new(&[2, 4, 5], 5717).unwrap();
+        let mut result: Vec<u8> = Vec::new();
+        let mut offsets = [0 as usize; 3];
+        while offsets.iter().any(|offset| *offset < bodies[1].len()) {
+            let pieces: Vec<&[u8]> = (0..3).map(|index| {
+                let body = &bodies[[1, 3, 4][index]];
+                let end = std::cmp::min(body.len(), offsets[index] + index * 3 + 1);
+                let piece = &body[offsets[index]..end];
+                offsets[index] = end;
+                piece
+            }).collect();
+            result.extend_from_slice(&combiner.update(&pieces).unwrap());
+        }
+        result.extend_from_slice(&combiner.finish().unwrap());
+
+        assert_eq!(result, secret);
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(Splitter::new(5, 1, 5717).is_err());
+        assert!(Splitter::new(3, 5, 5717).is_err());
+        assert!(Splitter::new(5, 3, 251).is_err());
+        assert!(Splitter::new(5, 3, 40009).is_err());
+        assert!(Splitter::new(5, 3, 5719).is_err());
+        assert!(Combiner::new(&[1], 5717).is_err());
+        assert!(Combiner::new(&[1, 2, 1], 5717).is_err());
+    }
+
+    #[test]
+    fn test_roundtrip_stream() {
+        let secret: Vec<u8> = (0..(BUFFER_SIZE * 2 + 123)).map(|_| random::<u8>()).collect();
+        let sinks = split_to_vectors(&secret, 5, 3);
+
+        let mut sources: Vec<TrickleReader<Cursor<&Vec<u8>>>> = [4, 0, 2].iter().map(|index| TrickleReader { source: Cursor::new(&sinks[*index]) }).collect();
+        let mut result: Vec<u8> = Vec::new();
+        let header = combine(&mut sources, &mut result, |_|{}).unwrap();
+
+        assert_eq!(header.filename, b"secret");
+        assert_eq!(result, secret);
+    }
+
+    #[test]
+    fn test_cancel_stream() {
+        let secret: Vec<u8> = (0..(BUFFER_SIZE * 4)).map(|_| random::<u8>()).collect();
+        let mut sinks: Vec<Vec<u8>> = (0..3).map(|_| Vec::new()).collect();
+        let result = split(&mut Cursor::new(&secret), &mut sinks, 2, 5717, "secret", |progress: &Progress| {
+            if progress.bytes_done > BUFFER_SIZE as u64 { Control::Cancel } else { Control::Continue }
+        });
+        assert_eq!(result, Err(String::from(progress::CANCELLED)));
+        assert!(sinks[0].len() < secret.len() * 2);
+
+        let sinks = split_to_vectors(&secret, 3, 2);
+        let mut sources: Vec<Cursor<&Vec<u8>>> = sinks.iter().map(|sink| Cursor::new(sink)).collect();
+        let mut events: Vec<Phase> = Vec::new();
+        let result = combine(&mut sources, &mut Vec::new(), |progress: &Progress| {
+            events.push(progress.phase);
+            if progress.phase == Phase::Combining { Control::Cancel } else { Control::Continue }
+        });
+        assert!(result.is_err());
+        assert_eq!(events, vec![Phase::ReadingHeaders, Phase::ReadingHeaders, Phase::ReadingHeaders, Phase::Combining]);
+    }
+
+    #[test]
+    fn test_split_writer_combine_reader() {
+        let secret: Vec<u8> = (0..(BUFFER_SIZE * 2 + 5)).map(|_| random::<u8>()).collect();
+        let mut sinks: Vec<Vec<u8>> = (0..4).map(|_| Vec::new()).collect();
+        let mut writer = SplitWriter::new(&mut sinks, 3, &Header::new(0, 5717, "secret"), |_: &Progress| Control::Continue).unwrap();
+        writer.write_all(&secret).unwrap();
+        assert_eq!(writer.finish().unwrap(), secret.len() as u64);
+
+        // Interchangeable with split and combine
+        let mut sources: Vec<TrickleReader<Cursor<&Vec<u8>>>> = [3, 1, 0].iter().map(|index| TrickleReader { source: Cursor::new(&sinks[*index]) }).collect();
+        let mut result: Vec<u8> = Vec::new();
+        combine(&mut sources, &mut result, |_|{}).unwrap();
+        assert_eq!(result, secret);
+
+        let sinks = split_to_vectors(&secret, 3, 2);
+        let mut sources: Vec<Cursor<&Vec<u8>>> = sinks[1..].iter().map(|sink| Cursor::new(sink)).collect();
+        let headers = sss::read_headers(&mut sources, |_|{}).unwrap();
+        let mut reader = CombineReader::new(&mut sources, &headers, |_: &Progress| Control::Continue).unwrap();
+        let mut result: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut result).unwrap();
+        assert_eq!(result, secret);
+
+        let mut sources: Vec<Cursor<&Vec<u8>>> = sinks[1..].iter().map(|sink| Cursor::new(sink)).collect();
+        let headers = sss::read_headers(&mut sources, |_|{}).unwrap();
+        let mut reader = CombineReader::new(&mut sources, &headers, |_: &Progress| Control::Cancel).unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+        assert_eq!(reader.error(), Some(progress::CANCELLED));
+    }
+
+    // Split secret into signed pieces, as generate_file_with_options does
+    fn split_signed(secret: &[u8], pieces_count: usize, required_pieces_count: i32) -> Vec<Vec<u8>> {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut template = Header::new(0, 5717, "secret");
+        signature::add_to_header(&key, &mut template);
+        let mut sinks: Vec<SignWriter<Vec<u8>>> = (0..pieces_count).map(|_| SignWriter::new(Vec::new(), Some(&key))).collect();
+        let mut writer = SplitWriter::new(&mut sinks, required_pieces_count, &template, |_: &Progress| Control::Continue).unwrap();
+        writer.write_all(secret).unwrap();
+        writer.finish().unwrap();
+        return sinks.into_iter().map(|sink| sink.finish().unwrap()).collect();
+    }
+
+    #[test]
+    fn test_roundtrip_signed_stream() {
+        let secret: Vec<u8> = (0..(BUFFER_SIZE + 77)).map(|_| random::<u8>()).collect();
+        let sinks = split_signed(&secret, 3, 2);
+
+        // Signatures are left off and checked
+        let mut sources: Vec<TrickleReader<Cursor<&Vec<u8>>>> = [2, 0].iter().map(|index| TrickleReader { source: Cursor::new(&sinks[*index]) }).collect();
+        let mut result: Vec<u8> = Vec::new();
+        combine(&mut sources, &mut result, |_|{}).unwrap();
+        assert_eq!(result, secret);
+
+        let mut sources: Vec<Cursor<&Vec<u8>>> = sinks[1..].iter().map(|sink| Cursor::new(sink)).collect();
+        let headers = sss::read_headers(&mut sources, |_|{}).unwrap();
+        let mut reader = CombineReader::new(&mut sources, &headers, |_: &Progress| Control::Continue).unwrap();
+        let mut result: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut result).unwrap();
+        assert_eq!(result, secret);
+
+        let mut forged = sinks[1].clone();
+        let last = forged.len() - 1;
+        forged[last] ^= 1;
+        let mut sources: Vec<Cursor<&Vec<u8>>> = vec![Cursor::new(&sinks[0]), Cursor::new(&forged)];
+        assert!(combine(&mut sources, &mut Vec::new(), |_|{}).unwrap_err().contains("signature doesn't match"));
+    }
+
+    #[test]
+    fn test_unchunked_version() {
+        // Bodies of bare points, as written before chunks were introduced
+        let secret: Vec<u8> = (0..100).map(|_| random::<u8>()).collect();
+        let pieces = sss::generate_string(&secret, 3, 2, 5717, |_|{});
+        let sinks: Vec<Vec<u8>> = pieces.iter().map(|piece| {
+            let mut sink = Header { version: 2, ..Header::new(piece.0, 5717, "secret") }.to_bytes();
+            sink.extend_from_slice(&piece.1);
+            sink
+        }).collect();
+
+        let mut sources: Vec<Cursor<&Vec<u8>>> = sinks[..2].iter().map(|sink| Cursor::new(sink)).collect();
+        let mut result: Vec<u8> = Vec::new();
+        combine(&mut sources, &mut result, |_|{}).unwrap();
+        assert_eq!(result, secret);
+    }
+
+    // Offset of the body in each of sinks
+    fn body_offset(sinks: &[Vec<u8>]) -> usize {
+        let mut sources: Vec<Cursor<&Vec<u8>>> = sinks.iter().map(|sink| Cursor::new(sink)).collect();
+        sss::read_headers(&mut sources, |_|{}).unwrap();
+        return sources[0].position() as usize;
+    }
+
+    #[test]
+    fn test_combine_truncated() {
+        let secret: Vec<u8> = (0..(BUFFER_SIZE * 2)).map(|_| random::<u8>()).collect();
+        let mut sinks = split_to_vectors(&secret, 3, 2);
+        let chunk_header_length = 8 + chunk::HASH_LENGTH;
+
+        // Losing the end marker from every piece leaves them all the same length
+        for sink in sinks.iter_mut() {
+            let length = sink.len();
+            sink.truncate(length - chunk_header_length);
+        }
+        let mut sources: Vec<Cursor<&Vec<u8>>> = sinks.iter().map(|sink| Cursor::new(sink)).collect();
+        let result = combine(&mut sources, &mut Vec::new(), |_|{});
+        assert_eq!(result, Err(String::from("Input files are truncated: Piece 1 is truncated; Piece 2 is truncated; Piece 3 is truncated")));
+
+        // Losing a whole chunk from every piece
+        let sinks = split_to_vectors(&secret, 3, 2);
+        let body_offset = body_offset(&sinks);
+        let chunk_length = chunk_header_length + chunk::MAX_CHUNK_LENGTH;
+        let shortened: Vec<Vec<u8>> = sinks.iter().map(|sink| {
+            let mut shortened = sink[..body_offset].to_vec();
+            shortened.extend_from_slice(&sink[(body_offset + chunk_length)..]);
+            shortened
+        }).collect();
+        let mut sources: Vec<Cursor<&Vec<u8>>> = shortened.iter().map(|sink| Cursor::new(sink)).collect();
+        assert!(combine(&mut sources, &mut Vec::new(), |_|{}).unwrap_err().contains("out of order"));
+    }
+
+    #[test]
+    fn test_combine_mismatched_lengths() {
+        let secret: Vec<u8> = (0..64).map(|_| random::<u8>()).collect();
+        let mut sinks = split_to_vectors(&secret, 4, 2);
+        // Cut into the last chunk of points, past the end marker
+        let length = sinks[1].len() - (8 + chunk::HASH_LENGTH) - 2;
+        sinks[1].truncate(length);
+
+        // The other pieces make up for one that's cut short
+        let mut sources: Vec<Cursor<&Vec<u8>>> = sinks.iter().map(|sink| Cursor::new(sink)).collect();
+        let mut result: Vec<u8> = Vec::new();
+        combine(&mut sources, &mut result, |_|{}).unwrap();
+        assert_eq!(result, secret);
+
+        for index in [0, 2] {
+            sinks[index].truncate(length);
+        }
+        let mut sources: Vec<Cursor<&Vec<u8>>> = sinks.iter().map(|sink| Cursor::new(sink)).collect();
+        assert!(combine(&mut sources, &mut Vec::new(), |_|{}).is_err());
+    }
+
+    #[test]
+    fn test_combine_damaged() {
+        let secret: Vec<u8> = (0..(BUFFER_SIZE * 3)).map(|_| random::<u8>()).collect();
+        let mut sinks = split_to_vectors(&secret, 3, 2);
+        let body_offset = body_offset(&sinks);
+        let chunk_header_length = 8 + chunk::HASH_LENGTH;
+        let chunk_offset = |chunk: usize| body_offset + chunk * (chunk_header_length + chunk::MAX_CHUNK_LENGTH) + chunk_header_length;
+
+        // Every piece is damaged, but each chunk is intact in enough of them
+        for index in 0..3 {
+            sinks[index][chunk_offset(index) + 100] ^= 1;
+        }
+        let mut sources: Vec<TrickleReader<Cursor<&Vec<u8>>>> = sinks.iter().map(|sink| TrickleReader { source: Cursor::new(sink) }).collect();
+        let mut result: Vec<u8> = Vec::new();
+        combine(&mut sources, &mut result, |_|{}).unwrap();
+        assert_eq!(result, secret);
+
+        sinks[1][chunk_offset(0)] ^= 1;
+        let mut sources: Vec<Cursor<&Vec<u8>>> = sinks.iter().map(|sink| Cursor::new(sink)).collect();
+        let error = combine(&mut sources, &mut Vec::new(), |_|{}).unwrap_err();
+        assert!(error.starts_with("Chunk 0 is damaged or missing in too many pieces (1 intact, 2 required)"));
+    }
+}
@@ -3,7 +3,18 @@ extern crate gdk;
 extern crate gio;
 extern crate base64;
 
-use crate::sss;
+use russs::sss;
+use russs::output;
+use russs::passphrase;
+use russs::policy;
+use russs::policy::Policy;
+use russs::progress::{Control, Progress};
+use russs::recipient;
+use russs::secret::{SecretBuffer, SecretString};
+use russs::share::{Encoding, TextShare};
+use russs::signature;
+use russs::ssss::SsssShare;
+use russs::vault;
 
 use gtk::prelude::*;
 use gio::prelude::*;
@@ -12,6 +23,12 @@ use gtk::*;
 use gio::*;
 use gdk::Event;
 use std::cell::RefCell;
+use std::path::PathBuf;
+
+// Shorter text shares are assumed to be still being typed in
+const MIN_FLAGGED_SHARE_LENGTH: usize = 32;
+// Controls whose settings a split policy decides instead
+const POLICY_CONTROLS: [&str; 5] = ["spinnerTotalPiecesFile", "spinnerRequiredPiecesFile", "checkGfshareFile", "checkHissFile", "entryRecipientsFile"];
 
 // All UI action is on the main thread in any case
 thread_local!(static INSTANCE: UI = UI::new());
@@ -20,6 +37,7 @@ pub struct UI {
     builder: Builder,
     file_result_path: RefCell<String>,
     reconstructed_file_result_path: RefCell<String>,
+    cancel_requested: RefCell<bool>,
 }
 
 impl UI {
@@ -28,6 +46,7 @@ impl UI {
             builder: Builder::new_from_string(include_str!("ui.glade")),
             file_result_path: RefCell::new(String::from("")),
             reconstructed_file_result_path: RefCell::new(String::from("")),
+            cancel_requested: RefCell::new(false),
         };
     }
 
@@ -66,38 +85,53 @@ impl UI {
         let button_choose_secret_file: FileChooserButton = self.get_object_instance("buttonChooseSecretFile");
         button_choose_secret_file.connect_file_set(UI::ui_validate_file_chooser);
 
+        let check_secret_folder: CheckButton = self.get_object_instance("checkSecretFolder");
+        check_secret_folder.connect_toggled(UI::ui_toggle_secret_folder);
+
         let spinner_total_pieces_file: SpinButton = self.get_object_instance("spinnerTotalPiecesFile");
         spinner_total_pieces_file.connect_value_changed(UI::ui_validate_file_spinner);
 
         let spinner_required_pieces_file: SpinButton = self.get_object_instance("spinnerRequiredPiecesFile");
         spinner_required_pieces_file.connect_value_changed(UI::ui_validate_file_spinner);
 
+        let button_choose_policy_file: FileChooserButton = self.get_object_instance("buttonChoosePolicyFile");
+        button_choose_policy_file.connect_file_set(UI::ui_choose_policy_file);
+
+        let button_clear_policy: Button = self.get_object_instance("buttonClearPolicy");
+        button_clear_policy.connect_clicked(UI::ui_clear_policy);
+
+        let button_new_dealer_key: Button = self.get_object_instance("buttonNewDealerKey");
+        button_new_dealer_key.connect_clicked(UI::ui_new_dealer_key);
+
         let button_generate_file: Button = self.get_object_instance("buttonGenerateFile");
         button_generate_file.connect_clicked(UI::ui_generate_file);
 
+        let button_cancel_file: Button = self.get_object_instance("buttonCancelFile");
+        button_cancel_file.connect_clicked(UI::ui_cancel);
+
         let button_open_result_file: Button = self.get_object_instance("buttonOpenResultFile");
         button_open_result_file.connect_clicked(UI::ui_open_file);
 
         // Reconstruct text
 
-        let entry_reconstruct_text_prime_modulator: Entry = self.get_object_instance("entryReconstructTextPrimeModulator");
-        entry_reconstruct_text_prime_modulator.connect_changed(UI::ui_validate_reconstruct_text_entry);
-
         let spinner_reconstruct_text_pieces: SpinButton = self.get_object_instance("spinnerReconstructTextPieces");
         spinner_reconstruct_text_pieces.connect_value_changed(UI::ui_validate_reconstruct_text_spinner);
 
+        let entry_reconstruct_text_prime_modulator: Entry = self.get_object_instance("entryReconstructTextPrimeModulator");
+        entry_reconstruct_text_prime_modulator.connect_changed(UI::ui_validate_reconstruct_text_entry);
+
         let grid_reconstruct_text: Grid = self.get_object_instance("gridReconstructTextPieces");
         let mut row = 0;
 
         loop {
-            let child = grid_reconstruct_text.get_child_at(row, 0);
+            let child = grid_reconstruct_text.get_child_at(0, row);
             if child.is_none() {
                 break;
             }
 
             let entry: Entry = child.unwrap().downcast().unwrap();
             entry.connect_changed(UI::ui_validate_reconstruct_text_entry);
-            let entry: Entry = grid_reconstruct_text.get_child_at(row, 1).unwrap().downcast().unwrap();
+            let entry: Entry = grid_reconstruct_text.get_child_at(1, row).unwrap().downcast().unwrap();
             entry.connect_changed(UI::ui_validate_reconstruct_text_entry);
             row += 1;
         }
@@ -113,6 +147,12 @@ impl UI {
         let button_reconstruct_file: Button = self.get_object_instance("buttonReconstructFile");
         button_reconstruct_file.connect_clicked(UI::ui_reconstruct_file);
 
+        let button_repair_pieces: Button = self.get_object_instance("buttonRepairPieces");
+        button_repair_pieces.connect_clicked(UI::ui_repair_pieces);
+
+        let button_cancel_reconstruct_file: Button = self.get_object_instance("buttonCancelReconstructFile");
+        button_cancel_reconstruct_file.connect_clicked(UI::ui_cancel);
+
         let button_reconstruct_file_open_result: Button = self.get_object_instance("buttonReconstructFileOpenResult");
         button_reconstruct_file_open_result.connect_clicked(UI::ui_open_reconstruct_file);
     }
@@ -151,6 +191,53 @@ impl UI {
         UI::get_object::<InfoBar>("mainInfoBar").show_all();
     }
 
+    // Ask whether to replace existing output, if that's why the operation failed
+    fn confirm_overwrite(message: &str) -> bool {
+        if !message.starts_with(output::ALREADY_EXISTS) {
+            return false;
+        }
+
+        let dialog = MessageDialog::new(Some(&UI::get_object::<Window>("mainWindow")),
+                                        DialogFlags::MODAL,
+                                        MessageType::Question,
+                                        ButtonsType::YesNo,
+                                        format!("{}\n\nReplace it?", message).as_str());
+        let response = dialog.run();
+        dialog.destroy();
+        return response == ResponseType::Yes;
+    }
+
+    // Ask for a passphrase, returning None if the user cancels
+    fn prompt_passphrase(message: &str) -> Option<SecretString> {
+        let dialog = MessageDialog::new(Some(&UI::get_object::<Window>("mainWindow")),
+                                        DialogFlags::MODAL,
+                                        MessageType::Question,
+                                        ButtonsType::OkCancel,
+                                        message);
+        let entry = Entry::new();
+        entry.set_visibility(false);
+        entry.set_input_purpose(InputPurpose::Password);
+        entry.set_activates_default(true);
+        dialog.set_default_response(ResponseType::Ok);
+        dialog.get_content_area().add(&entry);
+        entry.show();
+
+        let response = dialog.run();
+        let passphrase = SecretString::from(entry.get_text().unwrap().as_str());
+        dialog.destroy();
+        return if response == ResponseType::Ok { Some(passphrase) } else { None };
+    }
+
+    // Ask for a passphrase for each of count new shards, any of which may be left without one; None if the user cancels
+    fn prompt_new_passphrases(count: i32) -> Option<Vec<Option<SecretString>>> {
+        let mut passphrases: Vec<Option<SecretString>> = Vec::new();
+        for index in 0..count {
+            let passphrase = UI::prompt_passphrase(format!("Passphrase for shard {} (leave empty for none)", index + 1).as_str())?;
+            passphrases.push(if passphrase.as_str().is_empty() { None } else { Some(passphrase) });
+        }
+        return Some(passphrases);
+    }
+
     fn clear_grid(grid: &Grid) {
         loop {
             match grid.get_child_at(0, 0) {
@@ -196,37 +283,66 @@ impl UI {
         return label;
     }
 
-    fn encode_base64(input: &Vec<u8>) -> String {
-        base64::encode_config(input, base64::URL_SAFE)
-    }
-
     fn ui_generate_text(_button: &Button) {
-        let secret = UI::get_object::<Entry>("entrySecretText").get_text().unwrap();
+        // GTK keeps its own copy of the entry text, which we can't wipe; ours at least doesn't linger
+        let secret = SecretString::from(UI::get_object::<Entry>("entrySecretText").get_text().unwrap().as_str());
         let total_pieces = UI::get_object::<SpinButton>("spinnerTotalPiecesText").get_value() as i32;
         let required_pieces = UI::get_object::<SpinButton>("spinnerRequiredPiecesText").get_value() as i32;
         let progress_bar: ProgressBar = UI::get_object("progressText");
         let generate_button: Button = UI::get_object("buttonGenerateText");
         let prime = 5717;
+        let format = UI::get_object::<ComboBoxText>("comboEncodingText").get_active_id();
+        let encoding = match format.as_ref().map(|id| id.as_str()) {
+            Some("words") => Encoding::Words,
+            Some("base32") => Encoding::Base32,
+            _ => Encoding::Base64,
+        };
 
         UI::ui_clear_errors(&UI::get_object("mainInfoBar"), ResponseType::Close);
         generate_button.set_sensitive(false);
 
-        let pieces = sss::generate_string(&secret.as_str(),
-                                          total_pieces,
-                                          required_pieces,
-                                          prime,
-                                          |progress| UI::set_progress(&progress_bar, progress));
-        // Build result grid
+        let result = match format.as_ref().map(|id| id.as_str()) {
+            // Shares in the format of the ssss tool
+            Some("ssss") => sss::generate_ssss_shares(secret.as_bytes(), total_pieces, required_pieces),
+            // The secret is a Vault root key, in base64 or hex as Vault shows it
+            Some("vault") => vault::decode(secret.as_str())
+                .map(|key| SecretBuffer::from_slice(&key))
+                .and_then(|key| sss::generate_vault_shares(key.as_slice(), total_pieces, required_pieces)),
+            _ => sss::generate_text_shares(secret.as_bytes(),
+                                           total_pieces,
+                                           required_pieces,
+                                           prime,
+                                           encoding,
+                                           |progress| UI::set_progress(&progress_bar, progress)),
+        };
+        // Holders who want one get their shard locked with their own passphrase
+        let result = match result {
+            Ok(ref shares) if UI::get_object::<CheckButton>("checkPassphraseText").get_active() => match UI::prompt_new_passphrases(shares.len() as i32) {
+                None => {
+                    generate_button.set_sensitive(true);
+                    return;
+                },
+                Some(passphrases) => sss::lock_text_shares(shares, &passphrases),
+            },
+            _ => result,
+        };
+        let shares = match result {
+            Ok(shares) => shares,
+            Err(message) => {
+                UI::display_error(format!("Error generating shards: {}", message).as_str());
+                generate_button.set_sensitive(true);
+                return;
+            },
+        };
+        // Build result grid; each share carries its own index and prime
         let grid: Grid = UI::get_object("gridResultText");
         UI::clear_grid(&grid);
-        for index in 0..pieces.len() as i32 {
+        for index in 0..shares.len() as i32 {
             grid.insert_row(index);
-            grid.attach(&UI::get_selectable_label(format!("{}", pieces[index as usize].0).as_str(), 1.0), 0, index, 1, 1);
-            grid.attach(&UI::get_selectable_label(UI::encode_base64(&pieces[index as usize].1).as_str(), 0.25), 1, index, 1, 1);
+            grid.attach(&UI::get_selectable_label(shares[index as usize].as_str(), 0.0), 0, index, 1, 1);
         }
 
         progress_bar.set_fraction(1.0);
-        UI::get_object::<Label>("labelPrimeText").set_text(format!("{}", prime).as_str());
         UI::get_object::<Frame>("frameResultsText").show_all();
         generate_button.set_sensitive(true);
     }
@@ -247,6 +363,50 @@ impl UI {
         UI::ui_validate_file();
     }
 
+    fn ui_toggle_secret_folder(check: &CheckButton) {
+        let chooser: FileChooserButton = UI::get_object("buttonChooseSecretFile");
+        chooser.unselect_all();
+        if check.get_active() {
+            chooser.set_action(FileChooserAction::SelectFolder);
+            chooser.set_title("Choose a folder");
+        } else {
+            chooser.set_action(FileChooserAction::Open);
+            chooser.set_title("Choose a file");
+        }
+        UI::ui_validate_file();
+    }
+
+    // The counts and holders come from the policy, so show its counts in place of the user's
+    fn ui_choose_policy_file(chooser: &FileChooserButton) {
+        UI::clear_errors();
+        let policy = match chooser.get_filename().map(Policy::read) {
+            None => return,
+            Some(Err(message)) => {
+                UI::display_error(message.as_str());
+                UI::ui_clear_policy(&UI::get_object("buttonClearPolicy"));
+                return;
+            },
+            Some(Ok(policy)) => policy,
+        };
+
+        let total_spinner: SpinButton = UI::get_object("spinnerTotalPiecesFile");
+        let required_spinner: SpinButton = UI::get_object("spinnerRequiredPiecesFile");
+        total_spinner.set_value(policy.holders.len() as f64);
+        required_spinner.set_value(policy.threshold as f64);
+        for name in &POLICY_CONTROLS {
+            UI::get_object::<Widget>(name).set_sensitive(false);
+        }
+        UI::ui_validate_file();
+    }
+
+    fn ui_clear_policy(_button: &Button) {
+        UI::get_object::<FileChooserButton>("buttonChoosePolicyFile").unselect_all();
+        for name in &POLICY_CONTROLS {
+            UI::get_object::<Widget>(name).set_sensitive(true);
+        }
+        UI::ui_validate_file();
+    }
+
     fn set_progress(progress_bar: &ProgressBar, progress: f64) {
         progress_bar.set_fraction(progress);
         while gtk::events_pending() {
@@ -254,20 +414,85 @@ impl UI {
         }
     }
 
+    // Update progress_bar, and stop the operation if the user has asked to cancel it
+    fn report_progress(progress_bar: &ProgressBar, progress: &Progress) -> Control {
+        UI::set_progress(progress_bar, progress.fraction());
+        return match INSTANCE.with(|instance| *instance.cancel_requested.borrow()) {
+            true => Control::Cancel,
+            false => Control::Continue,
+        }
+    }
+
+    fn begin_cancellable(cancel_button: &Button) {
+        INSTANCE.with(|instance| instance.cancel_requested.replace(false));
+        cancel_button.set_sensitive(true);
+    }
+
+    fn end_cancellable(cancel_button: &Button) {
+        cancel_button.set_sensitive(false);
+        INSTANCE.with(|instance| instance.cancel_requested.replace(false));
+    }
+
+    fn ui_cancel(button: &Button) {
+        INSTANCE.with(|instance| instance.cancel_requested.replace(true));
+        button.set_sensitive(false);
+    }
+
+    // Create a dealer key to sign shards with, and show the public key to give their holders
+    fn ui_new_dealer_key(_button: &Button) {
+        let main_window: Window = UI::get_object("mainWindow");
+        let dialog = FileChooserDialog::with_buttons(Some("Save the new dealer key"),
+                                                     Some(&main_window),
+                                                     FileChooserAction::Save,
+                                                     &[("_Cancel", ResponseType::Cancel), ("_Save", ResponseType::Accept)]);
+        dialog.set_do_overwrite_confirmation(true);
+        let response = dialog.run();
+        let path = dialog.get_filename();
+        dialog.destroy();
+        let path = match (response, path) {
+            (ResponseType::Accept, Some(path)) => path,
+            _ => return,
+        };
+
+        UI::clear_errors();
+        let public_key = match signature::generate_key(&path, true) {
+            Err(message) => {
+                UI::display_error(format!("Error creating dealer key: {}", message).as_str());
+                return;
+            },
+            Ok(public_key) => public_key,
+        };
+        UI::get_object::<FileChooserButton>("buttonChooseDealerKeyFile").set_filename(&path);
+
+        let dialog = MessageDialog::new(Some(&main_window),
+                                        DialogFlags::MODAL,
+                                        MessageType::Info,
+                                        ButtonsType::Ok,
+                                        "Give the holders of your shards this public key, so they can tell your shards from any others:");
+        let entry = Entry::new();
+        entry.set_text(&public_key);
+        entry.set_editable(false);
+        dialog.get_content_area().add(&entry);
+        entry.show();
+        dialog.run();
+        dialog.destroy();
+    }
+
     fn ui_generate_file(_button: &Button) {
-        let prime = 7919;
+        let prime = policy::DEFAULT_PRIME;
         let total_pieces = UI::get_object::<SpinButton>("spinnerTotalPiecesFile").get_value() as i32;
         let required_pieces = UI::get_object::<SpinButton>("spinnerRequiredPiecesFile").get_value() as i32;
         let progress_bar: ProgressBar = UI::get_object("progressFile");
         let generate_button: Button = UI::get_object("buttonGenerateFile");
+        let cancel_button: Button = UI::get_object("buttonCancelFile");
 
         let secret_file = UI::get_object::<FileChooserButton>("buttonChooseSecretFile").get_file().unwrap();
-        let secret_file_path = secret_file.get_path().unwrap().into_os_string().into_string().unwrap();
+        let secret_file_path = secret_file.get_path().unwrap();
 
         let parent: String;
         match secret_file.get_parent() {
             None => {
-                UI::display_error(format!("Error getting parent for {}", secret_file_path).as_str());
+                UI::display_error(format!("Error getting parent for {}", secret_file_path.display()).as_str());
                 return;
             },
             Some(file) => parent = file.get_uri().to_string(),
@@ -275,19 +500,74 @@ impl UI {
 
         UI::clear_errors();
         generate_button.set_sensitive(false);
+        UI::begin_cancellable(&cancel_button);
+
+        // The policy is read again, in case it has changed since it was chosen
+        let policy = match UI::get_object::<FileChooserButton>("buttonChoosePolicyFile").get_filename().map(Policy::read).transpose() {
+            Err(message) => {
+                UI::display_error(message.as_str());
+                UI::end_cancellable(&cancel_button);
+                generate_button.set_sensitive(true);
+                return;
+            },
+            Ok(policy) => policy,
+        };
+        let total_pieces = policy.as_ref().map_or(total_pieces, |policy| policy.holders.len() as i32);
 
-        match sss::generate_file(secret_file_path.as_str(),
-                                        total_pieces,
-                                        required_pieces,
-                                        prime,
-                                        |progress| UI::set_progress(&progress_bar, progress)) {
-            Err(message) => UI::display_error(format!("Error generating shards for {}: {}", secret_file_path, message).as_str()),
+        // gfcombine and Hiss only read bare binary shards
+        let (gfshare, hiss) = match policy {
+            Some(ref policy) => (policy.scheme == policy::Scheme::Gfshare, policy.scheme == policy::Scheme::Hiss),
+            None => {
+                let gfshare = UI::get_object::<CheckButton>("checkGfshareFile").get_active();
+                (gfshare, !gfshare && UI::get_object::<CheckButton>("checkHissFile").get_active())
+            },
+        };
+        let passphrases = match UI::get_object::<CheckButton>("checkPassphraseFile").get_active() {
+            false => Vec::new(),
+            true => match UI::prompt_new_passphrases(total_pieces) {
+                None => {
+                    UI::end_cancellable(&cancel_button);
+                    generate_button.set_sensitive(true);
+                    return;
+                },
+                Some(passphrases) => passphrases,
+            },
+        };
+        let mut options = sss::GenerateOptions {
+            parity_overhead: if gfshare || hiss { 0 } else { UI::get_object::<SpinButton>("spinnerParityOverheadFile").get_value() as u32 },
+            armor: !gfshare && !hiss && UI::get_object::<CheckButton>("checkArmorFile").get_active(),
+            gfshare,
+            hiss,
+            recipients: UI::get_object::<Entry>("entryRecipientsFile").get_text().unwrap().split_whitespace().map(String::from).collect(),
+            passphrases,
+            dealer_key: if gfshare || hiss { None } else { UI::get_object::<FileChooserButton>("buttonChooseDealerKeyFile").get_filename() },
+            ..sss::GenerateOptions::default()
+        };
+        let result = loop {
+            let progress_callback = |progress: &Progress| UI::report_progress(&progress_bar, progress);
+            // A folder is split as an archive, with the shards named after it
+            let result = if let Some(ref policy) = policy {
+                policy::split(&secret_file_path, policy, &options, progress_callback).map(|(pieces, _)| pieces)
+            } else if secret_file_path.is_dir() {
+                sss::generate_archive_with_options(&[&secret_file_path], &secret_file_path, total_pieces, required_pieces, prime, &options, progress_callback)
+            } else {
+                sss::generate_file_with_options(&secret_file_path, total_pieces, required_pieces, prime, &options, progress_callback)
+            };
+            match result {
+                Err(ref message) if !options.overwrite && UI::confirm_overwrite(message) => options.overwrite = true,
+                _ => break result,
+            }
+        };
+
+        match result {
+            Err(message) => UI::display_error(format!("Error generating shards for {}: {}", secret_file_path.display(), message).as_str()),
             Ok(_) => {
                 INSTANCE.with(|instance| instance.file_result_path.replace(parent));
                 UI::get_object::<Frame>("frameResultsFile").show_all();
             },
         }
 
+        UI::end_cancellable(&cancel_button);
         progress_bar.set_fraction(1.0);
         generate_button.set_sensitive(true);
     }
@@ -301,21 +581,58 @@ impl UI {
     // Reconstruct text
 
     fn ui_validate_reconstruct_text() {
-        let mut valid = UI::get_object::<Entry>("entryReconstructTextPrimeModulator").get_text().unwrap().as_str().parse::<i32>().is_ok();
-
-        if valid {
-            let grid: Grid = UI::get_object("gridReconstructTextPieces");
-            let pieces_count = UI::get_object::<SpinButton>("spinnerReconstructTextPieces").get_value() as i32;
+        let grid: Grid = UI::get_object("gridReconstructTextPieces");
+        let pieces_count = UI::get_object::<SpinButton>("spinnerReconstructTextPieces").get_value() as i32;
 
-            valid = (0..pieces_count).all(|index| {
-                grid.get_child_at(0, index).unwrap().downcast::<Entry>().unwrap().get_text().unwrap().as_str().parse::<i32>().is_ok() &&
-                base64::decode_config(grid.get_child_at(1, index).unwrap().downcast::<Entry>().unwrap().get_text().unwrap().as_str(), base64::URL_SAFE).is_ok()
-            });
+        let prime = UI::get_object::<Entry>("entryReconstructTextPrimeModulator").get_text().unwrap();
+
+        // Only check that every share parses here, so that mismatched shares get a proper error
+        // A share being typed in is flagged with where it goes wrong, once it's long enough to be complete
+        let mut valid = true;
+        for index in 0..pieces_count {
+            let index_text = grid.get_child_at(0, index).unwrap().downcast::<Entry>().unwrap().get_text().unwrap();
+            let entry = grid.get_child_at(1, index).unwrap().downcast::<Entry>().unwrap();
+            let text = entry.get_text().unwrap();
+            let problem = match UI::legacy_share(&index_text, &text) {
+                Some(share) => share.err().or_else(|| UI::legacy_prime(&prime).err()),
+                None => TextShare::parse(text.as_str()).err().filter(|_| !UI::is_ssss_share(&text) && !UI::is_vault_share(&text) && !passphrase::is_locked_text(&text)),
+            };
+            let flagged = problem.as_ref().filter(|_| entry.get_text().unwrap().len() >= MIN_FLAGGED_SHARE_LENGTH);
+            entry.set_icon_from_icon_name(EntryIconPosition::Secondary, flagged.map(|_| "dialog-warning-symbolic"));
+            entry.set_icon_tooltip_text(EntryIconPosition::Secondary, flagged.map(|problem| problem.as_str()));
+            valid &= problem.is_none();
         }
 
         UI::get_object::<Button>("buttonReconstructText").set_sensitive(valid);
     }
 
+    // Shares from before text shares carried everything needed to use them, given as an index and the points in
+    // URL-safe base64, with the prime entered separately; None for any other share, which is given without an index
+    fn legacy_share(index: &str, share: &str) -> Option<Result<(i32, Vec<u8>), String>> {
+        if index.trim().is_empty() {
+            return None;
+        }
+        return Some(match (index.trim().parse::<i32>(), base64::decode_config(share.trim(), base64::URL_SAFE)) {
+            (Err(_), _) => Err(format!("Invalid index: {}", index.trim())),
+            (_, Err(_)) => Err(String::from("Shards given with an index are in base64")),
+            (Ok(index), Ok(data)) => Ok((index, data)),
+        });
+    }
+
+    fn legacy_prime(prime: &str) -> Result<i32, String> {
+        return prime.trim().parse::<i32>().map_err(|_| String::from("Shards given with an index need the prime modulator"));
+    }
+
+    // Shares from the ssss tool, which carry neither the threshold nor the field, so are taken to be exactly enough
+    fn is_ssss_share(share: &str) -> bool {
+        return TextShare::parse(share).is_err() && SsssShare::parse(share).is_ok();
+    }
+
+    // Vault unseal key shares, in base64 or hex
+    fn is_vault_share(share: &str) -> bool {
+        return TextShare::parse(share).is_err() && SsssShare::parse(share).is_err() && vault::decode(share).is_ok();
+    }
+
     fn ui_validate_reconstruct_text_entry(_entry: &Entry) {
         UI::ui_validate_reconstruct_text();
     }
@@ -337,14 +654,13 @@ impl UI {
         for row in 0..pieces_count {
             grid.insert_row(row);
             grid.attach(&UI::get_custom_entry("Index", InputPurpose::Digits), 0, row, 1, 1);
-            grid.attach(&UI::get_custom_entry("Secret shard", InputPurpose::FreeForm), 1, row, 1, 1);
+            grid.attach(&UI::get_custom_entry("Shard", InputPurpose::FreeForm), 1, row, 1, 1);
         }
         grid.show_all();
         UI::ui_validate_reconstruct_text();
     }
 
     fn ui_reconstruct_text(_button: &Button) {
-        let prime =  UI::get_object::<Entry>("entryReconstructTextPrimeModulator").get_text().unwrap().as_str().parse::<i32>().unwrap();
         let progress_bar: ProgressBar = UI::get_object("progressReconstructText");
         let generate_button: Button = UI::get_object("buttonReconstructText");
         let grid: Grid = UI::get_object("gridReconstructTextPieces");
@@ -353,21 +669,78 @@ impl UI {
         UI::clear_errors();
         generate_button.set_sensitive(false);
 
-        let pieces: Vec<(i32, Vec<u8>)> = (0..pieces_count).map(|index| {
-            (grid.get_child_at(0, index).unwrap().downcast::<Entry>().unwrap().get_text().unwrap().as_str().parse::<i32>().unwrap(),
-            base64::decode_config(grid.get_child_at(1, index).unwrap().downcast::<Entry>().unwrap().get_text().unwrap().as_str(), base64::URL_SAFE).unwrap())
+        let entries: Vec<Entry> = (0..pieces_count).map(|index| grid.get_child_at(1, index).unwrap().downcast::<Entry>().unwrap()).collect();
+        let shares: Vec<String> = entries.iter().map(|entry| entry.get_text().unwrap().to_string()).collect();
+
+        // Old shards are combined as they always were, given they're all old
+        let legacy: Vec<Option<Result<(i32, Vec<u8>), String>>> = (0..pieces_count).map(|index| {
+            UI::legacy_share(&grid.get_child_at(0, index).unwrap().downcast::<Entry>().unwrap().get_text().unwrap(), &shares[index as usize])
         }).collect();
+        if legacy.iter().any(|share| share.is_some()) {
+            let result = if legacy.iter().all(|share| share.is_some()) {
+                legacy.into_iter().map(|share| share.unwrap()).collect::<Result<Vec<(i32, Vec<u8>)>, String>>().and_then(|pieces| {
+                    let prime = UI::legacy_prime(&UI::get_object::<Entry>("entryReconstructTextPrimeModulator").get_text().unwrap())?;
+                    sss::interpolate_string(&pieces, prime, |progress| UI::set_progress(&progress_bar, progress))
+                })
+            } else {
+                Err(String::from("Shards given with an index can't be combined with other shards"))
+            };
+            UI::show_reconstructed_text(result);
+            progress_bar.set_fraction(1.0);
+            generate_button.set_sensitive(true);
+            return;
+        }
 
-        match sss::interpolate_string(&pieces, prime, |progress| UI::set_progress(&progress_bar, progress)) {
+        // Locked shards are unlocked first, with their holders' passphrases
+        let mut passphrases: Vec<Option<SecretString>> = Vec::new();
+        for (index, share) in shares.iter().enumerate() {
+            if !passphrase::is_locked_text(share) {
+                passphrases.push(None);
+                continue;
+            }
+            match UI::prompt_passphrase(format!("Passphrase for shard {}", index + 1).as_str()) {
+                None => {
+                    generate_button.set_sensitive(true);
+                    return;
+                },
+                passphrase => passphrases.push(passphrase),
+            }
+        }
+        let unlocked = match sss::unlock_text_shares(&shares, &passphrases) {
+            Err(message) => {
+                UI::display_error(format!("Error reconstructing text: {}", message).as_str());
+                generate_button.set_sensitive(true);
+                return;
+            },
+            Ok(unlocked) => unlocked,
+        };
+        let shares: Vec<&str> = unlocked.iter().map(|share| share.as_str()).collect();
+
+        let result = if shares.iter().all(|share| UI::is_ssss_share(share)) {
+            // ssss secrets are text with any leading zero bytes dropped
+            sss::interpolate_ssss_shares(&shares, pieces_count).and_then(|secret| {
+                SecretString::from_buffer(SecretBuffer::from_slice(&secret[secret.iter().take_while(|byte| **byte == 0).count()..]))
+            })
+        } else if shares.iter().all(|share| UI::is_vault_share(share)) {
+            // Vault keys are binary, so are shown in base64 as Vault shows them
+            sss::interpolate_vault_shares(&shares).and_then(|key| SecretString::from_buffer(SecretBuffer::from_slice(vault::encode(key.as_slice()).as_bytes())))
+        } else {
+            sss::interpolate_text_shares(&shares, |progress| UI::set_progress(&progress_bar, progress))
+        };
+        UI::show_reconstructed_text(result);
+
+        progress_bar.set_fraction(1.0);
+        generate_button.set_sensitive(true);
+    }
+
+    fn show_reconstructed_text(result: Result<SecretString, String>) {
+        match result {
             Ok(secret) => {
                 UI::get_object::<Label>("labelReconstructTextSecret").set_text(secret.as_str());
                 UI::get_object::<Box>("boxReconstructTextSecret").show_all();
             },
             Err(message) => UI::display_error(format!("Error reconstructing text: {}", message).as_str()),
         }
-
-        progress_bar.set_fraction(1.0);
-        generate_button.set_sensitive(true);
     }
 
     // Reconstruct file
@@ -380,6 +753,8 @@ impl UI {
             UI::get_object::<Button>("buttonReconstructFileChoosePieces").set_label(format!("({} files)", pieces.len()).as_str());
         }
         UI::get_object::<Button>("buttonReconstructFile").set_sensitive(valid);
+        // Shards are repaired one at a time, so even one will do
+        UI::get_object::<Button>("buttonRepairPieces").set_sensitive(!pieces.is_empty());
     }
 
     fn ui_choose_pieces_reconstruct_file(_button: &Button) {
@@ -392,31 +767,104 @@ impl UI {
     fn ui_reconstruct_file(_button: &Button) {
         let reconstruct_button: Button = UI::get_object("buttonReconstructFile");
         let progress_bar: ProgressBar = UI::get_object("progressReconstructFile");
+        let cancel_button: Button = UI::get_object("buttonCancelReconstructFile");
 
         UI::clear_errors();
         reconstruct_button.set_sensitive(false);
+        UI::begin_cancellable(&cancel_button);
 
         let piece_files = UI::get_object::<FileChooserDialog>("chooserReconstructFileChoosePieces").get_files();
-        let pieces: Vec<String> = piece_files.iter().map(|file| {
-            file.get_path().unwrap().into_os_string().into_string().unwrap()
-        }).collect();
-        let destination = piece_files[0].get_parent().unwrap().get_path().unwrap().into_os_string().into_string().unwrap();
+        let pieces: Vec<PathBuf> = piece_files.iter().map(|file| file.get_path().unwrap()).collect();
+        let destination = piece_files[0].get_parent().unwrap().get_path().unwrap();
+
+        // Holders may each have decrypted their own shard, so one identity file will often do
+        let identity_file = UI::get_object::<FileChooserButton>("buttonChooseIdentityFile").get_filename();
+        // Each locked shard needs its holder's passphrase
+        let mut passphrases: Vec<Option<SecretString>> = Vec::new();
+        for piece in &pieces {
+            let locked = std::fs::File::open(piece).map_err(|error| error.to_string()).and_then(|mut file| recipient::is_locked(&mut file)).unwrap_or(false);
+            if !locked {
+                passphrases.push(None);
+                continue;
+            }
+            match UI::prompt_passphrase(format!("Passphrase for {}", piece.file_name().unwrap_or_default().to_string_lossy()).as_str()) {
+                None => {
+                    UI::end_cancellable(&cancel_button);
+                    reconstruct_button.set_sensitive(true);
+                    return;
+                },
+                passphrase => passphrases.push(passphrase),
+            }
+        }
+        let dealer = UI::get_object::<Entry>("entryDealerFile").get_text().unwrap().trim().to_string();
+        let mut options = sss::InterpolateOptions {
+            identities: identity_file.into_iter().collect(),
+            passphrases,
+            dealer: if dealer.is_empty() { None } else { Some(dealer) },
+            ..sss::InterpolateOptions::default()
+        };
+        let result = loop {
+            let result = sss::interpolate_file_with_options(&pieces, &destination, &options, |progress: &Progress| UI::report_progress(&progress_bar, progress));
+            match result {
+                Err(ref message) if !options.overwrite && UI::confirm_overwrite(message) => options.overwrite = true,
+                _ => break result,
+            }
+        };
 
-        match sss::interpolate_file(&pieces, destination.as_str(), |progress| UI::set_progress(&progress_bar, progress)) {
+        match result {
             Err(message) => UI::display_error(format!("Error reconstructing file: {}", message).as_str()),
             Ok(output_file) => {
-                INSTANCE.with(|instance| instance.reconstructed_file_result_path.replace(output_file));
+                let uri = gio::File::new_for_path(&output_file).get_uri().to_string();
+                INSTANCE.with(|instance| instance.reconstructed_file_result_path.replace(uri));
                 UI::get_object::<Frame>("frameReconstructFileResults").show_all();
             },
         }
 
+        UI::end_cancellable(&cancel_button);
         progress_bar.set_fraction(1.0);
         reconstruct_button.set_sensitive(true);
     }
 
+    fn ui_repair_pieces(_button: &Button) {
+        let repair_button: Button = UI::get_object("buttonRepairPieces");
+        let progress_bar: ProgressBar = UI::get_object("progressReconstructFile");
+        let cancel_button: Button = UI::get_object("buttonCancelReconstructFile");
+
+        UI::clear_errors();
+        repair_button.set_sensitive(false);
+        UI::begin_cancellable(&cancel_button);
+
+        let pieces: Vec<PathBuf> = UI::get_object::<FileChooserDialog>("chooserReconstructFileChoosePieces").get_files().iter().map(|file| file.get_path().unwrap()).collect();
+        // Stop at the first shard that can't be repaired
+        let result: Result<Vec<String>, String> = pieces.iter().filter_map(|piece| {
+            match sss::repair_file(piece, |progress: &Progress| UI::report_progress(&progress_bar, progress)) {
+                Err(message) => Some(Err(format!("Error repairing {}: {}", piece.display(), message))),
+                Ok(0) => None,
+                Ok(blocks) => Some(Ok(format!("{}: {} damaged blocks repaired", piece.display(), blocks))),
+            }
+        }).collect();
+
+        UI::end_cancellable(&cancel_button);
+        progress_bar.set_fraction(1.0);
+        repair_button.set_sensitive(true);
+
+        let repaired = match result {
+            Err(message) => return UI::display_error(message.as_str()),
+            Ok(repaired) => repaired,
+        };
+        let message = if repaired.is_empty() { String::from("No damage found") } else { repaired.join("\n") };
+        let dialog = MessageDialog::new(Some(&UI::get_object::<Window>("mainWindow")),
+                                        DialogFlags::MODAL,
+                                        MessageType::Info,
+                                        ButtonsType::Ok,
+                                        message.as_str());
+        dialog.run();
+        dialog.destroy();
+    }
+
     fn ui_open_reconstruct_file(_button: &Button) {
         let _ = INSTANCE.with(|instance| {
-            gio::AppInfo::launch_default_for_uri::<AppLaunchContext>(format!("file://{}", &instance.reconstructed_file_result_path.borrow().as_str()).as_str(), None)
+            gio::AppInfo::launch_default_for_uri::<AppLaunchContext>(&instance.reconstructed_file_result_path.borrow().as_str(), None)
         });
     }
 }
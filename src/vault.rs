@@ -0,0 +1,111 @@
+extern crate base64;
+
+use rand::prelude::*;
+
+use crate::gf256;
+use crate::secret::SecretBuffer;
+
+//# Unseal key shares in the format of HashiCorp Vault's shamir package
+//# Each byte of the key is shared over GF(256) with the polynomial of AES, and a share is its value for every byte
+//# followed by its x coordinate as the last byte. Vault shows shares and keys in base64, and accepts them in hex too.
+
+pub const MAX_PARTS: usize = 255;
+
+//# Split secret into parts shares, threshold of which are needed, as Vault does for its root key
+pub fn split(secret: &[u8], parts: usize, threshold: usize) -> Result<Vec<Vec<u8>>, String> {
+    if parts < threshold {
+        return Err(String::from("The number of shares can't be less than the threshold"));
+    }
+    if parts > MAX_PARTS {
+        return Err(format!("Vault supports at most {} shares", MAX_PARTS));
+    }
+    if threshold < 2 {
+        return Err(String::from("Vault needs a threshold of at least 2"));
+    }
+    if secret.is_empty() {
+        return Err(String::from("Can't split an empty secret"));
+    }
+
+    // Distinct random x coordinates from 1 to 255, as Vault chooses them
+    let mut xs: Vec<u8> = (1..=(MAX_PARTS as u8)).collect();
+    xs.shuffle(&mut thread_rng());
+    let mut shares: Vec<Vec<u8>> = vec![Vec::with_capacity(secret.len() + 1); parts];
+    // The coefficients of any byte and one share would reveal that byte, so they're wiped when dropped
+    let mut coefficients = SecretBuffer::zeroed(threshold);
+    for byte in secret {
+        coefficients[0] = *byte;
+        thread_rng().fill(&mut coefficients[1..]);
+        for (share, x) in shares.iter_mut().zip(&xs) {
+            share.push(gf256::evaluate(&coefficients, *x));
+        }
+    }
+
+    for (share, x) in shares.iter_mut().zip(&xs) {
+        share.push(*x);
+    }
+    return Ok(shares);
+}
+
+//# Recover the secret from shares made by Vault or split
+pub fn combine<TShares, TShare>(shares: &TShares) -> Result<SecretBuffer, String>
+    where TShares: AsRef<[TShare]> + ?Sized,
+        TShare: AsRef<[u8]> {
+    let my_shares = shares.as_ref();
+    if my_shares.len() < 2 {
+        return Err(format!("Insufficient number of inputs ({} of at least 2 required)", my_shares.len()));
+    }
+    let length = my_shares[0].as_ref().len();
+    if length < 2 {
+        return Err(String::from("Input shares are too short"));
+    }
+    if my_shares.iter().any(|share| share.as_ref().len() != length) {
+        return Err(String::from("Input shares are of differing lengths"));
+    }
+
+    let points: Vec<(u8, Vec<u8>)> = my_shares.iter().map(|share| (share.as_ref()[length - 1], share.as_ref()[..(length - 1)].to_vec())).collect();
+    if points.iter().enumerate().any(|(index, point)| points[..index].iter().any(|other| other.0 == point.0)) {
+        return Err(String::from("Input shares have duplicate x coordinates"));
+    }
+    return Ok(gf256::interpolate(&points, 0));
+}
+
+//# A share or key as Vault shows them
+pub fn encode(data: &[u8]) -> String {
+    return base64::encode(data);
+}
+
+//# A share or key in base64 or hex, as Vault accepts them
+pub fn decode(text: &str) -> Result<Vec<u8>, String> {
+    let my_text = text.trim();
+    let is_hex = my_text.len() % 2 == 0 && my_text.bytes().all(|byte| byte.is_ascii_hexdigit());
+    if is_hex {
+        return Ok((0..(my_text.len() / 2)).map(|index| u8::from_str_radix(&my_text[(index * 2)..(index * 2 + 2)], 16).unwrap()).collect());
+    }
+    return base64::decode(my_text).map_err(|_| String::from("Malformed Vault share; expected base64 or hex"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_vault() {
+        let key: Vec<u8> = (0..32).map(|index| (index * 9) as u8).collect();
+        let shares = split(&key, 5, 3).unwrap();
+        assert!(shares.iter().all(|share| share.len() == key.len() + 1));
+        assert_eq!(combine(&[&shares[4], &shares[1], &shares[2]]).unwrap().as_slice(), key.as_slice());
+        assert_eq!(combine(&shares).unwrap().as_slice(), key.as_slice());
+        assert!(combine(&[&shares[1], &shares[1]]).is_err());
+        assert!(split(&key, 5, 1).is_err());
+    }
+
+    // The line 0x42 + 0x80x, whose value at 2 only comes out right with the polynomial of AES
+    #[test]
+    fn test_combine_known_shares() {
+        let shares: Vec<Vec<u8>> = ["wgE=", "WQI="].iter().map(|share| decode(share).unwrap()).collect();
+        assert_eq!(shares, vec![vec![0xc2, 1], vec![0x59, 2]]);
+        assert_eq!(combine(&shares).unwrap().as_slice(), &[0x42]);
+        assert_eq!(decode("c201").unwrap(), shares[0]);
+        assert_eq!(encode(&shares[1]), "WQI=");
+    }
+}